@@ -1,4 +1,6 @@
 use std::fs;
+use std::fs::DirEntry;
+use std::io::ErrorKind;
 use std::path::Path;
 use std::process::Command;
 
@@ -6,22 +8,42 @@ fn main() {
     println!("compiling shaders");
 
     let shader_dir_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("shader");
+    let compiled_dir_path = shader_dir_path.join("compiled");
     println!("shader source directory: {}", shader_dir_path.to_str().unwrap());
+    println!("cargo:rerun-if-changed={}", shader_dir_path.to_str().unwrap());
+    fs::create_dir_all(&compiled_dir_path).unwrap();
 
-    fs::read_dir(shader_dir_path.clone())
-        .unwrap()
-        .map(Result::unwrap)
-        .filter(|file| file.file_type().unwrap().is_file())
+    // shaders are compiled again at runtime, keep the checked in binaries
+    if let Err(e) = Command::new("glslc").arg("--version").output() {
+        if e.kind() == ErrorKind::NotFound {
+            // the runtime falls back to the checked in binaries without glslc
+            let missing = shader_sources(&shader_dir_path)
+                .into_iter()
+                .map(|file| format!("{}.spv", file.file_name().to_str().unwrap()))
+                .filter(|binary| !compiled_dir_path.join(binary).is_file())
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                panic!(
+                    "glslc not found and shader/compiled is missing {}",
+                    missing.join(", ")
+                );
+            }
+            println!("cargo:warning=glslc not found, skipping shader compilation");
+            return;
+        }
+    }
+
+    shader_sources(&shader_dir_path)
+        .into_iter()
         .for_each(|file| {
             let output = Command::new("glslc")
                 .current_dir(&shader_dir_path)
                 .arg(file.path())
                 .arg("-o")
-                .arg(format!(
-                    "{}\\compiled\\{}.spv",
-                    file.path().parent().unwrap().to_str().unwrap(),
+                .arg(compiled_dir_path.join(format!(
+                    "{}.spv",
                     file.file_name().to_str().unwrap()
-                ))
+                )))
                 .output()
                 .expect("failed to compile shader");
 
@@ -38,3 +60,13 @@ fn main() {
             }
         })
 }
+
+/// Shader stages in `shader_dir_path`, `.glsl` files are only included by them.
+fn shader_sources(shader_dir_path: &Path) -> Vec<DirEntry> {
+    fs::read_dir(shader_dir_path)
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|file| file.file_type().unwrap().is_file())
+        .filter(|file| file.path().extension().is_none_or(|ext| ext != "glsl"))
+        .collect()
+}
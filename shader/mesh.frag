@@ -0,0 +1,11 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "mesh_shading.glsl"
+
+// white for models without a base color texture
+layout(set = 1, binding = 0) uniform sampler2D base_color_texture;
+
+void main() {
+    shade(texture(base_color_texture, in_uv));
+}
@@ -0,0 +1,26 @@
+#version 450
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec3 normal;
+layout(location = 2) in vec3 color;
+layout(location = 3) in vec2 texture;
+
+layout(location = 0) out vec3 out_color;
+layout(location = 1) out vec3 out_normal;
+layout(location = 2) out vec2 out_uv;
+
+layout(set = 0, binding = 0) uniform Camera {
+    mat4 view;
+    mat4 projection;
+} camera;
+
+layout(push_constant) uniform Model {
+    mat4 transform;
+} model;
+
+void main() {
+    gl_Position = camera.projection * camera.view * model.transform * vec4(position, 1.0f);
+    out_color = color;
+    out_normal = transpose(inverse(mat3(model.transform))) * normal;
+    out_uv = texture;
+}
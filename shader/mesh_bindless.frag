@@ -0,0 +1,17 @@
+#version 450
+#extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+#include "mesh_shading.glsl"
+
+// every texture of the scene, registered in the bindless table
+layout(set = 1, binding = 0) uniform sampler2D textures[];
+
+// follows the model transform of the vertex shader
+layout(push_constant) uniform Material {
+    layout(offset = 64) uint base_color_texture;
+} material;
+
+void main() {
+    shade(texture(textures[material.base_color_texture], in_uv));
+}
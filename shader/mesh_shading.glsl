@@ -0,0 +1,17 @@
+// shading shared by the mesh fragment shaders, which sample the base color texture
+
+layout(location = 0) in vec3 in_color;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_frag_color;
+
+// world space, the vertex shader transforms normals with the normal matrix of the model
+const vec3 light_direction = normalize(vec3(-0.3, -1.0, -0.6));
+const float ambient = 0.15;
+
+void shade(vec4 texel) {
+    vec4 base_color = texel * vec4(in_color, 1.0f);
+    float diffuse = max(dot(normalize(in_normal), -light_direction), 0.0);
+    out_frag_color = vec4(base_color.rgb * (ambient + diffuse), base_color.a);
+}
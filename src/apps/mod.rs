@@ -1,2 +1,20 @@
+use crate::core::command::KewCommandPool;
+use crate::core::context::KewContext;
+use crate::core::device::{KewDevice, KewQueueIndices};
+use crate::core::error::KewError;
+use crate::core::memory::KewAllocator;
+
 pub mod img;
 pub mod sqr;
+
+/// Runs `app` on a headless device with a command pool of the compute queue family.
+pub fn run_headless<T>(
+    app: impl FnOnce(&KewDevice, &KewAllocator, &KewCommandPool) -> Result<T, KewError>,
+) -> Result<T, KewError> {
+    let kew_context = KewContext::new_headless()?;
+    let queue_indices = KewQueueIndices::new_headless(&kew_context)?;
+    let kew_device = KewDevice::new(kew_context, &queue_indices)?;
+    let allocator = KewAllocator::new(&kew_device);
+    let cmp_cmd_pool = KewCommandPool::new(&kew_device, queue_indices.cmp_idx)?;
+    app(&kew_device, &allocator, &cmp_cmd_pool)
+}
@@ -1,19 +1,21 @@
-use crate::core::buffer::KewBuffer;
 use crate::core::command::KewCommandPool;
-use crate::core::descriptor::KewDescriptorPoolBuilder;
+use crate::core::compute::KewComputeJob;
 use crate::core::device::KewDevice;
-use crate::core::memory::KewMemory;
-use crate::core::pipeline::KewCmpPipeline;
-use crate::core::shader::{DescriptorSetLayoutBindingInfo, KewShader, ShaderStageConfig};
+use crate::core::error::KewError;
+use crate::core::memory::KewAllocator;
+use crate::core::shader::{DescriptorSetLayoutBindingInfo, ShaderStageConfig};
+use crate::core::sync::KewTimeline;
 use ash::vk;
+use log::{debug, info};
 use std::ffi::CStr;
-use log::info;
+use std::time::Duration;
 
 const SQR_SHADER_CONFIG: ShaderStageConfig<2> = unsafe {
     ShaderStageConfig {
         entry_name: CStr::from_bytes_with_nul_unchecked(b"main\0"),
-        path: "./shader/sqr.spv",
-        bindings: [
+        path: "./shader/compiled/sqr.comp.spv",
+        set: 0,
+        bindings: Some([
             DescriptorSetLayoutBindingInfo {
                 descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
                 descriptor_count: 1,
@@ -24,63 +26,48 @@ const SQR_SHADER_CONFIG: ShaderStageConfig<2> = unsafe {
                 descriptor_count: 1,
                 stage_flags: vk::ShaderStageFlags::COMPUTE,
             },
-        ],
+        ]),
+        push_constants: None,
         stage: vk::ShaderStageFlags::COMPUTE,
         create_flags: vk::PipelineShaderStageCreateFlags::empty(),
     }
 };
 
-pub fn sqr_compute(kew_device: &KewDevice, cmp_cmd_pool: &KewCommandPool, data: &[i32]) {
-    let buffer_b_size = (data.len() * 4) as u64;
+/// Timeline value the host signals to release the job.
+const RELEASED: u64 = 1;
+/// Timeline value the job signals once the squares are written.
+const SQUARED: u64 = 2;
 
-    let buffer_memory: KewMemory;
-    let mut src_buffer = KewBuffer::new(
-        kew_device,
-        buffer_b_size,
-        vk::BufferUsageFlags::STORAGE_BUFFER,
-    );
-    let mut dst_buffer = KewBuffer::new(
-        kew_device,
-        buffer_b_size,
-        vk::BufferUsageFlags::STORAGE_BUFFER,
-    );
-
-    let min_alignment = src_buffer.get_memory_requirements().alignment;
-    let memory_b_size = (buffer_b_size + min_alignment - 1) & !(min_alignment - 1);
-
-    buffer_memory = KewMemory::new(
-        kew_device,
-        memory_b_size * 2,
-        src_buffer.get_memory_requirements(),
-        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-    );
-    src_buffer.bind_memory(&buffer_memory, 0);
-    dst_buffer.bind_memory(&buffer_memory, memory_b_size);
-    unsafe {
-        buffer_memory.map(vk::WHOLE_SIZE, 0);
-        buffer_memory.wr_visible_mem(&data, buffer_b_size, 0);
-    }
-
-    let shader = KewShader::new(kew_device, &SQR_SHADER_CONFIG);
-    let descriptor_pool = KewDescriptorPoolBuilder::new(1)
-        .add_pool_size(vk::DescriptorType::STORAGE_BUFFER, 2)
-        .build(kew_device);
-
-    let set = unsafe { descriptor_pool.allocate_descriptor_set(shader.descriptor_set_layout) };
-    shader.write_buffer(0, src_buffer.descriptor_info(), &set);
-    shader.write_buffer(1, dst_buffer.descriptor_info(), &set);
-    let pipeline = KewCmpPipeline::new(kew_device, &shader);
-
-    let command_buffer = pipeline.get_bound_cmd_buffer(cmp_cmd_pool, set);
-    unsafe {
-        kew_device.cmd_dispatch(command_buffer, data.len() as u32, 1, 1);
-        cmp_cmd_pool
-            .submit_command_buffers(&[command_buffer])
-            .unwrap()
-    }
-    let mut result: Vec<i32> = vec![0; data.len()];
-    unsafe {
-        buffer_memory.rd_visible_mem(&mut result, buffer_b_size, dst_buffer.get_offset());
-    }
+/// Squares `data` on the GPU. With timeline semaphores the job is submitted held back until the
+/// host releases it, and reports completion on the timeline like work of other queues would.
+pub fn sqr_compute(
+    kew_device: &KewDevice,
+    allocator: &KewAllocator,
+    cmp_cmd_pool: &KewCommandPool,
+    data: &[i32],
+) -> Result<Vec<i32>, KewError> {
+    let timeline = match kew_device.timeline_semaphores {
+        true => Some(KewTimeline::new(kew_device, 0)?),
+        false => None,
+    };
+    let mut job = KewComputeJob::new(kew_device, allocator, cmp_cmd_pool, &SQR_SHADER_CONFIG)?;
+    job.input(data)?;
+    let squares = job.output::<i32>(data.len())?;
+    // sqr.comp has no bounds check, one workgroup of a single invocation per element
+    job.workgroups([data.len() as u32, 1, 1]);
+    let outputs = match &timeline {
+        Some(timeline) => {
+            job.wait_for(timeline, RELEASED);
+            job.signal(timeline, SQUARED);
+            let future = job.submit()?;
+            timeline.signal(RELEASED)?;
+            timeline.wait(SQUARED, Duration::MAX)?;
+            debug!("sqr timeline at {}", timeline.value()?);
+            future.wait()?
+        }
+        None => job.run()?,
+    };
+    let result = outputs.read(&squares);
     info!("results: {:?}", result);
+    Ok(result)
 }
@@ -0,0 +1,204 @@
+use crate::core::buffer::KewBuffer;
+use crate::core::descriptor::{KewDescriptorPool, KewDescriptorPoolBuilder};
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::image::KewImage;
+use crate::core::sampler::KewSampler;
+use ash::vk;
+use log::{debug, warn};
+use std::ops::Deref;
+use std::slice;
+
+/// Binding of the image array, declared as `uniform sampler2D textures[]`.
+pub const BINDLESS_IMAGE_BINDING: u32 = 0;
+/// Binding of the buffer array, declared as `buffer Data { .. } data[]`.
+pub const BINDLESS_BUFFER_BINDING: u32 = 1;
+
+/// A single descriptor set holding partially bound arrays of sampled images and storage
+/// buffers. Registering a resource writes it to the next element of its array and returns the
+/// element as a handle, which shaders read from push constants to index the array. The set is
+/// updated after bind, so it is bound once and resources are registered while frames are in
+/// flight. Handles stay valid as long as the table. Needs `KewDevice::bindless`.
+pub struct KewBindlessTable<'a> {
+    kew_device: &'a KewDevice,
+    /// Layout of the bindless set, part of every pipeline layout the set is bound with.
+    pub layout: vk::DescriptorSetLayout,
+    _descriptor_pool: KewDescriptorPool<'a>,
+    vk_set: vk::DescriptorSet,
+    image_capacity: u32,
+    buffer_capacity: u32,
+    registered_images: u32,
+    registered_buffers: u32,
+}
+
+impl<'a> KewBindlessTable<'a> {
+    /// Capacities are clamped to the update after bind limits of the device.
+    pub fn new(
+        kew_device: &'a KewDevice,
+        image_capacity: u32,
+        buffer_capacity: u32,
+    ) -> Result<Self, KewError> {
+        if !kew_device.bindless {
+            return Err(KewError::UNSUPPORTED("descriptor indexing"));
+        }
+        let (image_limit, buffer_limit, resource_limit) = bindless_limits(kew_device);
+        // both arrays count towards the resources of every stage
+        let clamped_images = image_capacity.min(image_limit).min(resource_limit);
+        let clamped_buffers = buffer_capacity
+            .min(buffer_limit)
+            .min(resource_limit - clamped_images);
+        if (clamped_images, clamped_buffers) != (image_capacity, buffer_capacity) {
+            warn!(
+                "bindless capacity of {} images and {} buffers clamped to {} and {}",
+                image_capacity, buffer_capacity, clamped_images, clamped_buffers
+            );
+        }
+        let (image_capacity, buffer_capacity) = (clamped_images, clamped_buffers);
+
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(BINDLESS_IMAGE_BINDING)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(image_capacity)
+                .stage_flags(vk::ShaderStageFlags::ALL),
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(BINDLESS_BUFFER_BINDING)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(buffer_capacity)
+                .stage_flags(vk::ShaderStageFlags::ALL),
+        ];
+        let flags = vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
+        let binding_flags = [flags; 2];
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&binding_flags);
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+            .bindings(&bindings)
+            .push_next(&mut binding_flags_info);
+        let layout = unsafe { kew_device.create_descriptor_set_layout(&create_info, None)? };
+
+        let allocate_set = || {
+            let pool = KewDescriptorPoolBuilder::new(1)
+                .add_pool_size(
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    image_capacity.max(1),
+                )
+                .add_pool_size(vk::DescriptorType::STORAGE_BUFFER, buffer_capacity.max(1))
+                .pool_flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+                .build(kew_device)?;
+            let vk_set = unsafe { pool.allocate_descriptor_set(layout)? };
+            Ok((pool, vk_set))
+        };
+        let (pool, vk_set) = match allocate_set() {
+            Ok(allocated) => allocated,
+            Err(e) => {
+                unsafe { kew_device.destroy_descriptor_set_layout(layout, None) };
+                return Err(e);
+            }
+        };
+        debug!(
+            "created bindless table ({} images, {} buffers)",
+            image_capacity, buffer_capacity
+        );
+        Ok(Self {
+            kew_device,
+            layout,
+            _descriptor_pool: pool,
+            vk_set,
+            image_capacity,
+            buffer_capacity,
+            registered_images: 0,
+            registered_buffers: 0,
+        })
+    }
+
+    /// Handle of `image` sampled through `sampler`, in the layout the image is in now.
+    pub fn register_image(
+        &mut self,
+        image: &mut KewImage,
+        sampler: &KewSampler,
+    ) -> Result<u32, KewError> {
+        if self.registered_images == self.image_capacity {
+            return Err(KewError::VULKAN(vk::Result::ERROR_OUT_OF_POOL_MEMORY));
+        }
+        let image_info = image.descriptor_info(Some(sampler))?;
+        let handle = self.registered_images;
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.vk_set)
+            .dst_binding(BINDLESS_IMAGE_BINDING)
+            .dst_array_element(handle)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(slice::from_ref(&image_info));
+        unsafe { self.kew_device.update_descriptor_sets(&[write], &[]) };
+        self.registered_images += 1;
+        Ok(handle)
+    }
+
+    /// Handle of the whole of `buffer`.
+    pub fn register_buffer(&mut self, buffer: &KewBuffer) -> Result<u32, KewError> {
+        if self.registered_buffers == self.buffer_capacity {
+            return Err(KewError::VULKAN(vk::Result::ERROR_OUT_OF_POOL_MEMORY));
+        }
+        let buffer_info = buffer.descriptor_info();
+        let handle = self.registered_buffers;
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(self.vk_set)
+            .dst_binding(BINDLESS_BUFFER_BINDING)
+            .dst_array_element(handle)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .buffer_info(slice::from_ref(&buffer_info));
+        unsafe { self.kew_device.update_descriptor_sets(&[write], &[]) };
+        self.registered_buffers += 1;
+        Ok(handle)
+    }
+}
+
+impl Deref for KewBindlessTable<'_> {
+    type Target = vk::DescriptorSet;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_set
+    }
+}
+
+impl Drop for KewBindlessTable<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewBindlessTable");
+        unsafe {
+            self.kew_device
+                .destroy_descriptor_set_layout(self.layout, None);
+        }
+    }
+}
+
+/// Most combined image samplers, storage buffers and resources of any kind a set updated
+/// after bind may hold.
+fn bindless_limits(kew_device: &KewDevice) -> (u32, u32, u32) {
+    let mut properties12 = vk::PhysicalDeviceVulkan12Properties::default();
+    let mut properties2 = vk::PhysicalDeviceProperties2::default().push_next(&mut properties12);
+    unsafe {
+        kew_device
+            .context
+            .instance
+            .get_physical_device_properties2(kew_device.context.physical, &mut properties2)
+    };
+    let image_limit = [
+        properties12.max_descriptor_set_update_after_bind_samplers,
+        properties12.max_descriptor_set_update_after_bind_sampled_images,
+        properties12.max_per_stage_descriptor_update_after_bind_samplers,
+        properties12.max_per_stage_descriptor_update_after_bind_sampled_images,
+    ]
+    .into_iter()
+    .min()
+    .unwrap();
+    let buffer_limit = properties12
+        .max_descriptor_set_update_after_bind_storage_buffers
+        .min(properties12.max_per_stage_descriptor_update_after_bind_storage_buffers);
+    (
+        image_limit,
+        buffer_limit,
+        properties12.max_per_stage_update_after_bind_resources,
+    )
+}
@@ -1,47 +1,75 @@
-use crate::core::device::KewDevice;
+use crate::core::device::{distinct_families, KewDevice};
+use crate::core::error::KewError;
 use crate::core::image::KewImage;
-use crate::core::memory::{KewMemory, KewMemoryBinding};
+use crate::core::memory::KewAllocation;
 use ash::vk;
 use log::{debug, warn};
 use std::ops::Deref;
 
 pub struct KewBuffer<'a> {
     kew_device: &'a KewDevice,
-    m_bind: Option<KewMemoryBinding<'a>>,
+    m_bind: Option<KewAllocation<'a>>,
+    concurrent: bool,
     pub vk_buffer: vk::Buffer,
     pub b_size: vk::DeviceSize,
+    /// Queue family that owns the contents, `None` until the first `acquire_barrier`.
+    /// Exclusive buffers have to be released and acquired to move between families, releasing
+    /// one without owner is an error.
+    pub owner: Option<u32>,
 }
 
 impl<'a> KewBuffer<'a> {
-    pub fn new(kew_device: &'a KewDevice, b_size: u64, usage: vk::BufferUsageFlags) -> Self {
-        let create_info = vk::BufferCreateInfo::default()
-            .size(b_size)
-            .usage(usage)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
-        let vk_buffer = unsafe {
-            kew_device
-                .create_buffer(&create_info, None)
-                .expect("failed to create buffer")
+    pub fn new(
+        kew_device: &'a KewDevice,
+        b_size: u64,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Self, KewError> {
+        Self::new_shared(kew_device, b_size, usage, &[])
+    }
+
+    /// Same as `new` for a buffer used concurrently by `families` (e.g.
+    /// `KewQueueIndices::families`), which never needs ownership transfers. Falls back to
+    /// exclusive sharing for less than two distinct families.
+    pub fn new_shared(
+        kew_device: &'a KewDevice,
+        b_size: u64,
+        usage: vk::BufferUsageFlags,
+        families: &[u32],
+    ) -> Result<Self, KewError> {
+        let families = distinct_families(families);
+        let concurrent = families.len() > 1;
+        let create_info = vk::BufferCreateInfo::default().size(b_size).usage(usage);
+        let create_info = match concurrent {
+            true => create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&families),
+            false => create_info.sharing_mode(vk::SharingMode::EXCLUSIVE),
         };
-        Self {
+        let vk_buffer = unsafe { kew_device.create_buffer(&create_info, None)? };
+        Ok(Self {
             kew_device,
             m_bind: None,
+            concurrent,
             vk_buffer,
             b_size,
-        }
+            owner: None,
+        })
     }
 
-    pub fn bind_memory(&mut self, memory: &'a KewMemory, offset: vk::DeviceSize) {
+    pub fn bind_memory(&mut self, allocation: KewAllocation<'a>) -> Result<(), KewError> {
         if self.m_bind.is_none() {
-            self.m_bind = Some(KewMemoryBinding { memory, offset });
             unsafe {
-                self.kew_device
-                    .bind_buffer_memory(self.vk_buffer, memory.memory, offset)
-                    .expect("failed to bind memory")
+                self.kew_device.bind_buffer_memory(
+                    self.vk_buffer,
+                    allocation.memory,
+                    allocation.offset,
+                )?
             };
+            self.m_bind = Some(allocation);
         } else {
             warn!("buffer already bound to memory (skipped)")
         }
+        Ok(())
     }
 
     pub fn copy_to_image(&self, image: &KewImage, cmd_buffer: vk::CommandBuffer) {
@@ -75,15 +103,78 @@ impl<'a> KewBuffer<'a> {
         b_size: vk::DeviceSize,
         offset: vk::DeviceSize,
     ) {
-        if let Some(binding) = &self.m_bind {
-            binding
-                .memory
-                .wr_visible_mem(data, b_size, binding.offset + offset);
+        if let Some(allocation) = &self.m_bind {
+            allocation.wr_visible_mem(data, b_size, offset);
         } else {
             panic!("buffer not bound on write")
         }
     }
 
+    pub unsafe fn rd_visible_mem<T: Copy>(
+        &self,
+        data: &mut [T],
+        b_size: vk::DeviceSize,
+        offset: vk::DeviceSize,
+    ) {
+        if let Some(allocation) = &self.m_bind {
+            allocation.rd_visible_mem(data, b_size, offset);
+        } else {
+            panic!("buffer not bound on read")
+        }
+    }
+
+    /// Barrier releasing the buffer from its owner to `dst_family`, recorded on the owning queue
+    /// after `src_access`. `None` if no ownership transfer is needed, fails if the owner is
+    /// unknown.
+    pub fn release_barrier(
+        &self,
+        dst_family: u32,
+        src_access: vk::AccessFlags,
+    ) -> Result<Option<vk::BufferMemoryBarrier<'static>>, KewError> {
+        if !self.concurrent && self.owner.is_none() {
+            return Err(KewError::QUEUE("owner"));
+        }
+        Ok(self.transfer_from(dst_family).map(|src_family| {
+            self.ownership_barrier(src_family, dst_family)
+                .src_access_mask(src_access)
+        }))
+    }
+
+    /// Barrier acquiring the buffer for `dst_family` before `dst_access`, recorded on that
+    /// family's queue after the submission holding the release has been waited for. `None` if
+    /// no ownership transfer is needed.
+    pub fn acquire_barrier(
+        &mut self,
+        dst_family: u32,
+        dst_access: vk::AccessFlags,
+    ) -> Option<vk::BufferMemoryBarrier<'static>> {
+        let src_family = self.transfer_from(dst_family);
+        self.owner = Some(dst_family);
+        Some(
+            self.ownership_barrier(src_family?, dst_family)
+                .dst_access_mask(dst_access),
+        )
+    }
+
+    /// Owner the contents have to be transferred from for `dst_family` to use them.
+    fn transfer_from(&self, dst_family: u32) -> Option<u32> {
+        self.owner
+            .filter(|owner| !self.concurrent && *owner != dst_family)
+    }
+
+    fn ownership_barrier(
+        &self,
+        src_family: u32,
+        dst_family: u32,
+    ) -> vk::BufferMemoryBarrier<'static> {
+        vk::BufferMemoryBarrier::default()
+            .src_queue_family_index(src_family)
+            .dst_queue_family_index(dst_family)
+            .buffer(self.vk_buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+    }
+
     pub fn descriptor_info(&self) -> vk::DescriptorBufferInfo {
         vk::DescriptorBufferInfo::default()
             .buffer(self.vk_buffer)
@@ -0,0 +1,114 @@
+use crate::core::context::KewContext;
+use crate::core::error::KewError;
+use ash::{vk, Device};
+use log::{debug, info, warn};
+use std::fs;
+use std::io::ErrorKind;
+use std::ops::Deref;
+use std::path::PathBuf;
+
+const CACHE_MAGIC: &[u8; 4] = b"KEWP";
+
+/// Pipeline cache of a `KewDevice`, loaded from `path` on creation and written back by
+/// `save`. The driver data is prefixed with the device identity, so a cache written by another
+/// device or driver version is discarded instead of handed to the driver.
+pub struct KewPipelineCache {
+    vk_cache: vk::PipelineCache,
+    path: PathBuf,
+    header: Vec<u8>,
+}
+
+impl KewPipelineCache {
+    pub fn new(
+        context: &KewContext,
+        vk_device: &Device,
+        path: impl Into<PathBuf>,
+    ) -> Result<Self, KewError> {
+        let path = path.into();
+        let header = cache_header(context);
+        let initial_data = match fs::read(&path) {
+            Ok(data) if data.starts_with(&header) => data[header.len()..].to_vec(),
+            Ok(_) => {
+                warn!(
+                    "{}: pipeline cache of another device (discarded)",
+                    path.display()
+                );
+                Vec::new()
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                warn!("{}: {} (pipeline cache discarded)", path.display(), e);
+                Vec::new()
+            }
+        };
+
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(&initial_data);
+        let vk_cache = match unsafe { vk_device.create_pipeline_cache(&create_info, None) } {
+            Ok(vk_cache) => vk_cache,
+            Err(e) if !initial_data.is_empty() => {
+                warn!("{}: {} (pipeline cache discarded)", path.display(), e);
+                let create_info = vk::PipelineCacheCreateInfo::default();
+                unsafe { vk_device.create_pipeline_cache(&create_info, None)? }
+            }
+            Err(e) => return Err(e.into()),
+        };
+        debug!(
+            "loaded pipeline cache ({} bytes) from {}",
+            initial_data.len(),
+            path.display()
+        );
+        Ok(Self {
+            vk_cache,
+            path,
+            header,
+        })
+    }
+
+    /// Writes the cache to its file, replacing it only once the new one is complete.
+    pub fn save(&self, vk_device: &Device) -> Result<(), KewError> {
+        let data = unsafe { vk_device.get_pipeline_cache_data(self.vk_cache)? };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, [self.header.as_slice(), &data].concat())?;
+        fs::rename(&tmp_path, &self.path)?;
+        info!(
+            "saved pipeline cache ({} bytes) to {}",
+            data.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    /// Called by the owning `KewDevice` before the device itself is destroyed.
+    pub unsafe fn destroy(&self, vk_device: &Device) {
+        debug!("dropping KewPipelineCache");
+        vk_device.destroy_pipeline_cache(self.vk_cache, None);
+    }
+}
+
+impl Deref for KewPipelineCache {
+    type Target = vk::PipelineCache;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_cache
+    }
+}
+
+/// Identifies the device and driver a cache was written by.
+fn cache_header(context: &KewContext) -> Vec<u8> {
+    let properties = unsafe {
+        context
+            .instance
+            .get_physical_device_properties(context.physical)
+    };
+    [
+        CACHE_MAGIC.as_slice(),
+        &properties.vendor_id.to_le_bytes(),
+        &properties.device_id.to_le_bytes(),
+        &properties.driver_version.to_le_bytes(),
+        &properties.pipeline_cache_uuid,
+    ]
+    .concat()
+}
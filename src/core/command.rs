@@ -1,40 +1,39 @@
 use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::queue::KewQueue;
 use ash::vk;
 use ash::vk::CommandPool;
 use log::debug;
 use std::mem::MaybeUninit;
+use std::time::Duration;
 
+/// Command buffers for the queue family of `queue`, submitted through it.
 pub struct KewCommandPool<'a> {
     kew_device: &'a KewDevice,
-    pub queue: vk::Queue,
+    pub queue: KewQueue<'a>,
     command_pool: CommandPool,
 }
 
 impl<'a> KewCommandPool<'a> {
-    pub fn new(kew_device: &'a KewDevice, queue_idx: u32) -> Self {
+    pub fn new(kew_device: &'a KewDevice, queue_idx: u32) -> Result<Self, KewError> {
         let create_info = vk::CommandPoolCreateInfo::default()
             .queue_family_index(queue_idx)
             .flags(
                 vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER
                     | vk::CommandPoolCreateFlags::TRANSIENT,
             );
-        unsafe {
-            let command_pool = kew_device
-                .create_command_pool(&create_info, None)
-                .expect("failed to create command pool");
-            let queue = kew_device.get_device_queue(queue_idx, 0);
-            Self {
-                kew_device,
-                queue,
-                command_pool,
-            }
-        }
+        let command_pool = unsafe { kew_device.create_command_pool(&create_info, None)? };
+        Ok(Self {
+            kew_device,
+            queue: KewQueue::new(kew_device, queue_idx),
+            command_pool,
+        })
     }
 
     pub fn allocate_command_buffers<const N: usize>(
         &self,
         level: vk::CommandBufferLevel,
-    ) -> [vk::CommandBuffer; N] {
+    ) -> Result<[vk::CommandBuffer; N], KewError> {
         let mut cmd_buffers: [MaybeUninit<vk::CommandBuffer>; N] =
             [const { MaybeUninit::uninit() }; N];
         let alloc_info = vk::CommandBufferAllocateInfo::default()
@@ -42,14 +41,57 @@ impl<'a> KewCommandPool<'a> {
             .command_pool(self.command_pool)
             .command_buffer_count(N as u32);
         unsafe {
-            let cmd_buffers_vec = self
-                .kew_device
-                .allocate_command_buffers(&alloc_info)
-                .expect("failed allocating command buffers");
+            let cmd_buffers_vec = self.kew_device.allocate_command_buffers(&alloc_info)?;
             for (index, cmd_buffer) in cmd_buffers_vec.into_iter().enumerate().take(N) {
                 cmd_buffers[index].write(cmd_buffer.to_owned());
             }
-            std::ptr::read(cmd_buffers.as_ptr() as *const [vk::CommandBuffer; N])        }
+            Ok(std::ptr::read(
+                cmd_buffers.as_ptr() as *const [vk::CommandBuffer; N]
+            ))
+        }
+    }
+
+    pub fn free_command_buffers(&self, cmd_buffers: &[vk::CommandBuffer]) {
+        unsafe {
+            self.kew_device
+                .free_command_buffers(self.command_pool, cmd_buffers);
+        }
+    }
+
+    /// Allocates a primary command buffer and begins it for a single submission.
+    pub fn begin_once(&self) -> Result<vk::CommandBuffer, KewError> {
+        let [cmd_buffer] = self.allocate_command_buffers::<1>(vk::CommandBufferLevel::PRIMARY)?;
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let begun = unsafe {
+            self.kew_device
+                .begin_command_buffer(cmd_buffer, &begin_info)
+        };
+        if let Err(e) = begun {
+            self.free_command_buffers(&[cmd_buffer]);
+            return Err(e.into());
+        }
+        Ok(cmd_buffer)
+    }
+
+    pub fn end(&self, cmd_buffer: vk::CommandBuffer) -> Result<(), KewError> {
+        unsafe { self.kew_device.end_command_buffer(cmd_buffer)? };
+        Ok(())
+    }
+
+    /// Records a one time command buffer and blocks until the queue has executed it. Nothing is
+    /// submitted if `record` fails.
+    pub fn submit_once(
+        &self,
+        record: impl FnOnce(vk::CommandBuffer) -> Result<(), KewError>,
+    ) -> Result<(), KewError> {
+        let cmd_buffer = self.begin_once()?;
+        let result = record(cmd_buffer)
+            .and_then(|_| self.end(cmd_buffer))
+            .and_then(|_| self.queue.submit(&[cmd_buffer], &[], &[]))
+            .and_then(|fence| fence.wait(Duration::MAX).map(|_| ()));
+        self.free_command_buffers(&[cmd_buffer]);
+        result
     }
 }
 
@@ -0,0 +1,142 @@
+use crate::core::error::KewError;
+use crossbeam::channel::{unbounded, Receiver};
+use log::{debug, info, warn};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Compiles GLSL sources to SPIR-V at runtime by invoking `glslc` (or `$GLSLC`).
+/// Results are cached in `cache_dir` keyed by the hash of the source contents, including the
+/// files it pulls in through `#include "..."` (`GL_GOOGLE_include_directive`).
+pub struct KewShaderCompiler {
+    source_dir: PathBuf,
+    cache_dir: PathBuf,
+    glslc: OsString,
+}
+
+impl KewShaderCompiler {
+    pub fn new(source_dir: impl Into<PathBuf>, cache_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            source_dir: source_dir.into(),
+            cache_dir: cache_dir.into(),
+            glslc: std::env::var_os("GLSLC").unwrap_or_else(|| "glslc".into()),
+        }
+    }
+
+    /// Loads SPIR-V for `path`. `.spv` files are read as is, anything else is treated as GLSL.
+    pub fn compile(&self, path: &Path) -> Result<Vec<u32>, KewError> {
+        if path.extension().is_some_and(|ext| ext == "spv") {
+            return read_spv(path);
+        }
+
+        let mut source = Vec::new();
+        for source_path in self.sources(path)? {
+            source.extend(fs::read(source_path)?);
+        }
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        let cached = self
+            .cache_dir
+            .join(format!("{}.{:016x}.spv", file_name, fnv1a(&source)));
+        if cached.is_file() {
+            debug!("shader cache hit: {}", cached.display());
+            return read_spv(&cached);
+        }
+
+        fs::create_dir_all(&self.cache_dir)?;
+        let output = match Command::new(&self.glslc)
+            .arg(path)
+            .arg("-o")
+            .arg(&cached)
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let precompiled = self
+                    .source_dir
+                    .join("compiled")
+                    .join(format!("{}.spv", file_name));
+                warn!(
+                    "{:?} not found, using precompiled {}",
+                    self.glslc,
+                    precompiled.display()
+                );
+                return read_spv(&precompiled);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !output.status.success() {
+            let _ = fs::remove_file(&cached);
+            return Err(KewError::COMPILE(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        info!("compiled {} to {}", path.display(), cached.display());
+        read_spv(&cached)
+    }
+
+    /// `path` followed by every file it includes, directly or through other includes.
+    /// Include paths are relative to the including file.
+    pub fn sources(&self, path: &Path) -> Result<Vec<PathBuf>, KewError> {
+        let mut sources = vec![path.to_path_buf()];
+        let mut idx = 0;
+        while idx < sources.len() {
+            let source = fs::read_to_string(&sources[idx])?;
+            let dir = sources[idx].parent().unwrap_or(Path::new("")).to_path_buf();
+            for include in source.lines().filter_map(include_path) {
+                let include = dir.join(include);
+                if !sources.contains(&include) {
+                    sources.push(include);
+                }
+            }
+            idx += 1;
+        }
+        Ok(sources)
+    }
+
+    /// Watches the source directory and yields the path of every created or modified source.
+    /// The watcher stops when it is dropped.
+    pub fn watch(&self) -> Result<(RecommendedWatcher, Receiver<PathBuf>), KewError> {
+        let (tx, rx) = unbounded();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => return warn!("shader watcher error: {}", e),
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+            for path in event.paths {
+                if path.is_file() && path.extension().is_some_and(|ext| ext != "spv") {
+                    let _ = tx.send(path);
+                }
+            }
+        })?;
+        watcher.watch(&self.source_dir, RecursiveMode::NonRecursive)?;
+        info!("watching {} for shader changes", self.source_dir.display());
+        Ok((watcher, rx))
+    }
+}
+
+fn read_spv(path: &Path) -> Result<Vec<u32>, KewError> {
+    let mut file = File::open(path)?;
+    ash::util::read_spv(&mut file)
+        .map_err(|e| KewError::SPIRV(format!("{}: {}", path.display(), e)))
+}
+
+/// Path of an `#include "path"` line.
+fn include_path(line: &str) -> Option<&str> {
+    let path = line.trim().strip_prefix("#include")?.trim();
+    path.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
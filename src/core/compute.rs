@@ -0,0 +1,615 @@
+use crate::core::buffer::KewBuffer;
+use crate::core::command::KewCommandPool;
+use crate::core::descriptor::{KewDescriptorPool, KewDescriptorPoolBuilder, KewDescriptorWriter};
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::graph::{BufferAccess, ImageAccess, KewRenderGraph};
+use crate::core::image::KewImage;
+use crate::core::memory::{KewAllocator, KewResourceKind};
+use crate::core::pipeline::KewCmpPipeline;
+use crate::core::shader::{KewShader, ShaderStageConfig};
+use crate::core::sync::{KewFence, KewTimeline};
+use ash::vk;
+use image::RgbaImage;
+use log::debug;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem::{size_of, size_of_val};
+use std::pin::Pin;
+use std::slice;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const JOB_IMAGE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const COMPUTE_STAGE: vk::PipelineStageFlags = vk::PipelineStageFlags::COMPUTE_SHADER;
+
+/// Handle of a buffer declared with `KewComputeJob::output`, read back from `KewComputeOutputs`.
+pub struct KewJobOutput<T> {
+    binding: usize,
+    len: usize,
+    _element: PhantomData<T>,
+}
+
+/// Handle of an image declared with `KewComputeJob::output_image`.
+pub struct KewJobImage {
+    binding: usize,
+}
+
+enum JobResource<'a> {
+    BUFFER(KewBuffer<'a>),
+    /// Storage image with the host visible buffer it is uploaded from or read back into.
+    IMAGE {
+        image: KewImage<'a>,
+        staging: KewBuffer<'a>,
+        upload: bool,
+    },
+}
+
+/// Compute shader dispatch over host data. Resources are declared in binding order (the first
+/// one is binding 0 of the shader set) and kept host visible, images are `rgba8` storage images.
+///
+/// ```ignore
+/// let mut job = KewComputeJob::new(kew_device, allocator, cmp_cmd_pool, &SQR_SHADER_CONFIG)?;
+/// job.input(&data)?;
+/// let squares = job.output::<i32>(data.len())?;
+/// let squares = job.run()?.read(&squares);
+/// ```
+pub struct KewComputeJob<'a> {
+    kew_device: &'a KewDevice,
+    allocator: &'a KewAllocator<'a>,
+    cmd_pool: &'a KewCommandPool<'a>,
+    shader: KewShader<'a>,
+    set: u32,
+    resources: Vec<JobResource<'a>>,
+    /// Invocations per dimension, taken from the first output.
+    elements: Option<[u32; 3]>,
+    workgroups: Option<[u32; 3]>,
+    push_constants: Vec<u8>,
+    wait_points: Vec<(&'a KewTimeline<'a>, u64)>,
+    signal_points: Vec<(&'a KewTimeline<'a>, u64)>,
+}
+
+impl<'a> KewComputeJob<'a> {
+    /// `cmd_pool` has to belong to a compute capable queue family.
+    pub fn new<const N: usize>(
+        kew_device: &'a KewDevice,
+        allocator: &'a KewAllocator<'a>,
+        cmd_pool: &'a KewCommandPool<'a>,
+        shader_config: &'a ShaderStageConfig<N>,
+    ) -> Result<Self, KewError> {
+        let shader = KewShader::new(kew_device, shader_config)?;
+        if shader.reflection.stage != vk::ShaderStageFlags::COMPUTE {
+            return Err(KewError::SPIRV(format!(
+                "{}: compute jobs need a compute shader",
+                shader_config.path
+            )));
+        }
+        Ok(Self {
+            kew_device,
+            allocator,
+            cmd_pool,
+            shader,
+            set: shader_config.set,
+            resources: Vec::new(),
+            elements: None,
+            workgroups: None,
+            push_constants: Vec::new(),
+            wait_points: Vec::new(),
+            signal_points: Vec::new(),
+        })
+    }
+
+    /// Binds a buffer holding `data` to the next binding.
+    pub fn input<T: Copy>(&mut self, data: &[T]) -> Result<(), KewError> {
+        let b_size = size_of_val(data) as u64;
+        let buffer = self.create_buffer(b_size)?;
+        unsafe { buffer.wr_visible_mem(data, b_size, 0) };
+        self.resources.push(JobResource::BUFFER(buffer));
+        Ok(())
+    }
+
+    /// Binds a buffer of `len` elements to the next binding.
+    pub fn output<T: Copy + Default>(&mut self, len: usize) -> Result<KewJobOutput<T>, KewError> {
+        let buffer = self.create_buffer((len * size_of::<T>()) as u64)?;
+        self.resources.push(JobResource::BUFFER(buffer));
+        self.elements.get_or_insert([len as u32, 1, 1]);
+        Ok(KewJobOutput {
+            binding: self.resources.len() - 1,
+            len,
+            _element: PhantomData,
+        })
+    }
+
+    /// Binds a storage image holding `image` to the next binding.
+    pub fn input_image(&mut self, image_data: &RgbaImage) -> Result<(), KewError> {
+        let (image_dx, image_dy) = image_data.dimensions();
+        let (image, staging) = self.create_image(image_dx, image_dy)?;
+        let pixels = image_data.as_raw();
+        unsafe { staging.wr_visible_mem(pixels, pixels.len() as u64, 0) };
+        self.resources.push(JobResource::IMAGE {
+            image,
+            staging,
+            upload: true,
+        });
+        Ok(())
+    }
+
+    /// Binds an uninitialized storage image to the next binding.
+    pub fn output_image(&mut self, image_dx: u32, image_dy: u32) -> Result<KewJobImage, KewError> {
+        let (image, staging) = self.create_image(image_dx, image_dy)?;
+        self.resources.push(JobResource::IMAGE {
+            image,
+            staging,
+            upload: false,
+        });
+        self.elements.get_or_insert([image_dx, image_dy, 1]);
+        Ok(KewJobImage {
+            binding: self.resources.len() - 1,
+        })
+    }
+
+    /// Dispatches exactly `workgroups` workgroups instead of enough workgroups of the shader's
+    /// local size to cover the first output.
+    pub fn workgroups(&mut self, workgroups: [u32; 3]) {
+        self.workgroups = Some(workgroups);
+    }
+
+    /// Passes `data` to the push constant block of the shader, e.g. small parameters that do
+    /// not warrant a buffer.
+    pub fn push_constants<T: Copy>(&mut self, data: &T) {
+        let bytes = unsafe { slice::from_raw_parts(data as *const T as *const u8, size_of::<T>()) };
+        self.push_constants = bytes.to_vec();
+    }
+
+    /// Holds the job back until `timeline` reaches `value`, e.g. an upload on the transfer queue.
+    pub fn wait_for(&mut self, timeline: &'a KewTimeline<'a>, value: u64) {
+        self.wait_points.push((timeline, value));
+    }
+
+    /// Signals `value` on `timeline` once the job has completed, so graphics or further compute
+    /// work can consume the outputs on the device without a host round trip.
+    pub fn signal(&mut self, timeline: &'a KewTimeline<'a>, value: u64) {
+        self.signal_points.push((timeline, value));
+    }
+
+    /// Runs the job and blocks until the outputs can be read.
+    pub fn run(self) -> Result<KewComputeOutputs<'a>, KewError> {
+        self.submit()?.wait()
+    }
+
+    /// Records and submits the job. The returned future resolves to the outputs once the
+    /// queue has executed it, `wait` blocks on it instead.
+    pub fn submit(self) -> Result<KewComputeFuture<'a>, KewError> {
+        let workgroups = dispatch_size(
+            self.workgroups,
+            self.elements,
+            self.shader.reflection.local_size,
+        )?;
+        let descriptor_types = self.descriptor_types()?;
+
+        let mut pool_builder = KewDescriptorPoolBuilder::new(1);
+        for descriptor_type in &descriptor_types {
+            pool_builder = pool_builder.add_pool_size(*descriptor_type, 1);
+        }
+        let descriptor_pool = pool_builder.build(self.kew_device)?;
+        let set =
+            unsafe { descriptor_pool.allocate_descriptor_set(self.shader.descriptor_set_layout)? };
+        let pipeline = KewCmpPipeline::new(self.kew_device, &self.shader)?;
+
+        let Self {
+            kew_device,
+            cmd_pool,
+            shader,
+            mut resources,
+            push_constants,
+            wait_points,
+            signal_points,
+            ..
+        } = self;
+        let mut writer = KewDescriptorWriter::new();
+        for (binding, resource) in resources.iter_mut().enumerate() {
+            let descriptor_type = shader.descriptor_type(binding);
+            match resource {
+                JobResource::BUFFER(buffer) => {
+                    writer.write_buffer(
+                        set,
+                        binding as u32,
+                        descriptor_type,
+                        buffer.descriptor_info(),
+                    );
+                }
+                JobResource::IMAGE { image, .. } => {
+                    // the image is in the general layout by the time it is dispatched
+                    let image_info = image
+                        .descriptor_info(None)?
+                        .image_layout(vk::ImageLayout::GENERAL);
+                    writer.write_image(set, binding as u32, descriptor_type, image_info);
+                }
+            }
+        }
+        writer.update(kew_device);
+
+        let cmd_buffer = cmd_pool.begin_once()?;
+        let recorded = unsafe {
+            record_job(
+                kew_device,
+                cmd_buffer,
+                &mut resources,
+                &pipeline,
+                set,
+                &push_constants,
+                workgroups,
+            )
+        };
+        let wait = wait_points
+            .iter()
+            .map(|(timeline, value)| (timeline.at(*value), vk::PipelineStageFlags::ALL_COMMANDS))
+            .collect::<Vec<_>>();
+        let signal = signal_points
+            .iter()
+            .map(|(timeline, value)| timeline.at(*value))
+            .collect::<Vec<_>>();
+        let fence = match recorded
+            .and_then(|_| cmd_pool.end(cmd_buffer))
+            .and_then(|_| cmd_pool.queue.submit(&[cmd_buffer], &wait, &signal))
+        {
+            Ok(fence) => fence,
+            Err(e) => {
+                cmd_pool.free_command_buffers(&[cmd_buffer]);
+                return Err(e);
+            }
+        };
+        debug!("submitted compute job ({:?} workgroups)", workgroups);
+        Ok(KewComputeFuture {
+            kew_device,
+            cmd_pool,
+            cmd_buffer,
+            fence,
+            waiter: None,
+            resources: Some(resources),
+            _pipeline: pipeline,
+            _descriptor_pool: descriptor_pool,
+            _shader: shader,
+        })
+    }
+
+    fn create_buffer(&self, b_size: u64) -> Result<KewBuffer<'a>, KewError> {
+        let usage = match self.binding_type(self.resources.len())? {
+            vk::DescriptorType::UNIFORM_BUFFER => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            vk::DescriptorType::STORAGE_BUFFER => vk::BufferUsageFlags::STORAGE_BUFFER,
+            descriptor_type => return Err(self.type_mismatch(descriptor_type, "buffer")),
+        };
+        let mut buffer = KewBuffer::new(self.kew_device, b_size, usage)?;
+        buffer.bind_memory(self.allocator.allocate(
+            &buffer.get_memory_requirements(),
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            KewResourceKind::LINEAR,
+        )?)?;
+        Ok(buffer)
+    }
+
+    fn create_image(
+        &self,
+        image_dx: u32,
+        image_dy: u32,
+    ) -> Result<(KewImage<'a>, KewBuffer<'a>), KewError> {
+        match self.binding_type(self.resources.len())? {
+            vk::DescriptorType::STORAGE_IMAGE => (),
+            descriptor_type => return Err(self.type_mismatch(descriptor_type, "image")),
+        }
+        let b_size = image_dx as vk::DeviceSize * image_dy as vk::DeviceSize * 4;
+        let mut image = KewImage::new(
+            self.kew_device,
+            image_dx,
+            image_dy,
+            JOB_IMAGE_FORMAT,
+            b_size,
+            vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST,
+        )?;
+        image.bind_memory(self.allocator.allocate(
+            &image.get_memory_requirements(),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            KewResourceKind::OPTIMAL,
+        )?)?;
+        image.recreate_image_view()?;
+
+        let mut staging = KewBuffer::new(
+            self.kew_device,
+            b_size,
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+        )?;
+        staging.bind_memory(self.allocator.allocate(
+            &staging.get_memory_requirements(),
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            KewResourceKind::LINEAR,
+        )?)?;
+        Ok((image, staging))
+    }
+
+    fn binding_type(&self, binding: usize) -> Result<vk::DescriptorType, KewError> {
+        self.shader
+            .reflection
+            .set_bindings(self.set)
+            .find(|reflected| reflected.binding == binding as u32)
+            .map(|reflected| reflected.descriptor_type)
+            .ok_or_else(|| KewError::SPIRV(format!("compute shader has no binding {}", binding)))
+    }
+
+    fn type_mismatch(&self, descriptor_type: vk::DescriptorType, declared: &str) -> KewError {
+        KewError::SPIRV(format!(
+            "binding {} is a {:?}, the job declares an {}",
+            self.resources.len(),
+            descriptor_type,
+            declared
+        ))
+    }
+
+    fn descriptor_types(&self) -> Result<Vec<vk::DescriptorType>, KewError> {
+        if self.resources.is_empty() {
+            return Err(KewError::UNSUPPORTED("compute job without bindings"));
+        }
+        let declared = self.shader.reflection.set_bindings(self.set).count();
+        if declared != self.resources.len() {
+            return Err(KewError::SPIRV(format!(
+                "compute shader declares {} bindings, the job binds {}",
+                declared,
+                self.resources.len()
+            )));
+        }
+        (0..self.resources.len())
+            .map(|binding| self.binding_type(binding))
+            .collect()
+    }
+}
+
+/// `workgroups` if given, otherwise enough workgroups of `local_size` to cover `elements`.
+fn dispatch_size(
+    workgroups: Option<[u32; 3]>,
+    elements: Option<[u32; 3]>,
+    local_size: Option<[u32; 3]>,
+) -> Result<[u32; 3], KewError> {
+    if let Some(workgroups) = workgroups {
+        return Ok(workgroups);
+    }
+    let elements = elements.ok_or(KewError::UNSUPPORTED(
+        "compute job without outputs or dispatch size",
+    ))?;
+    let local_size = local_size.unwrap_or([1, 1, 1]);
+    Ok([0, 1, 2].map(|axis| elements[axis].div_ceil(local_size[axis].max(1))))
+}
+
+/// Uploads the input images, dispatches and makes the outputs visible to the host.
+unsafe fn record_job<'a>(
+    kew_device: &'a KewDevice,
+    cmd_buffer: vk::CommandBuffer,
+    resources: &mut [JobResource<'a>],
+    pipeline: &KewCmpPipeline,
+    set: vk::DescriptorSet,
+    push_constants: &[u8],
+    workgroups: [u32; 3],
+) -> Result<(), KewError> {
+    let mut graph = KewRenderGraph::new(kew_device);
+    let mut buffers = Vec::new();
+    let mut images = Vec::new();
+    for resource in resources.iter_mut() {
+        match resource {
+            JobResource::BUFFER(buffer) => {
+                let buffer = graph.import_buffer("job buffer", buffer, None);
+                graph.export_buffer(buffer, BufferAccess::HOST);
+                buffers.push(buffer);
+            }
+            JobResource::IMAGE {
+                image,
+                staging,
+                upload,
+            } => {
+                let image = graph.import_image("job image", image, None);
+                let staging = graph.import_buffer("job staging", staging, None);
+                if !*upload {
+                    graph.export_buffer(staging, BufferAccess::HOST);
+                }
+                images.push((image, staging, *upload));
+            }
+        }
+    }
+
+    for (image, staging, upload) in images.iter().copied() {
+        if upload {
+            graph
+                .add_pass("upload")
+                .image(image, ImageAccess::DESTINATION)
+                .buffer(staging, BufferAccess::SOURCE)
+                .record(move |cmd_buffer, resources| {
+                    let image = resources.kew_image(image).unwrap();
+                    resources.buffer(staging).copy_to_image(image, cmd_buffer);
+                    Ok(())
+                });
+        }
+    }
+    let mut dispatch = graph.add_pass("dispatch");
+    for (image, _, _) in &images {
+        dispatch = dispatch.image(*image, ImageAccess::STORAGE(COMPUTE_STAGE));
+    }
+    for buffer in &buffers {
+        dispatch = dispatch.buffer(*buffer, BufferAccess::STORAGE(COMPUTE_STAGE));
+    }
+    dispatch.record(|cmd_buffer, _| unsafe {
+        pipeline.bind(set, cmd_buffer);
+        if !push_constants.is_empty() {
+            pipeline.push_constants(cmd_buffer, 0, push_constants);
+        }
+        kew_device.cmd_dispatch(cmd_buffer, workgroups[0], workgroups[1], workgroups[2]);
+        Ok(())
+    });
+    for (image, staging, upload) in images.iter().copied() {
+        if !upload {
+            graph
+                .add_pass("readback")
+                .image(image, ImageAccess::SOURCE)
+                .buffer(staging, BufferAccess::DESTINATION)
+                .record(move |cmd_buffer, resources| {
+                    let image = resources.kew_image(image).unwrap();
+                    image.copy_to_buffer(resources.buffer(staging), cmd_buffer);
+                    Ok(())
+                });
+        }
+    }
+    graph.execute(cmd_buffer, None)
+}
+
+/// Submitted `KewComputeJob`. Dropping it before completion waits for the queue.
+pub struct KewComputeFuture<'a> {
+    kew_device: &'a KewDevice,
+    cmd_pool: &'a KewCommandPool<'a>,
+    cmd_buffer: vk::CommandBuffer,
+    fence: KewFence<'a>,
+    /// Started by the first pending poll.
+    waiter: Option<FenceWaiter>,
+    resources: Option<Vec<JobResource<'a>>>,
+    _pipeline: KewCmpPipeline<'a>,
+    _descriptor_pool: KewDescriptorPool<'a>,
+    _shader: KewShader<'a>,
+}
+
+impl<'a> KewComputeFuture<'a> {
+    pub fn is_complete(&self) -> Result<bool, KewError> {
+        self.fence.is_signaled()
+    }
+
+    /// Blocks until the job has completed.
+    pub fn wait(mut self) -> Result<KewComputeOutputs<'a>, KewError> {
+        self.fence.wait(Duration::MAX)?;
+        Ok(self.take_outputs())
+    }
+
+    /// Wakes `waker` once the fence is signaled, replacing the waker of an earlier poll.
+    fn wake_on_completion(&mut self, waker: &Waker) {
+        match &self.waiter {
+            Some(waiter) => waiter.waker.lock().unwrap().clone_from(waker),
+            None => {
+                let waker = Arc::new(Mutex::new(waker.clone()));
+                let vk_device = (**self.kew_device).clone();
+                let vk_fence = *self.fence;
+                let thread_waker = Arc::clone(&waker);
+                let thread = thread::spawn(move || {
+                    // errors surface when the task polls the fence again
+                    let _ = unsafe { vk_device.wait_for_fences(&[vk_fence], true, u64::MAX) };
+                    thread_waker.lock().unwrap().wake_by_ref();
+                });
+                self.waiter = Some(FenceWaiter { waker, thread });
+            }
+        }
+    }
+
+    fn take_outputs(&mut self) -> KewComputeOutputs<'a> {
+        KewComputeOutputs {
+            resources: self.resources.take().unwrap_or_default(),
+        }
+    }
+}
+
+/// Polls the fence, while pending a thread blocking on it wakes the task once the job has
+/// completed.
+impl<'a> Future for KewComputeFuture<'a> {
+    type Output = Result<KewComputeOutputs<'a>, KewError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = self.get_mut();
+        if let Ok(false) = future.is_complete() {
+            future.wake_on_completion(cx.waker());
+        }
+        // checked again, the fence may have been signaled before the waker was registered
+        match future.is_complete() {
+            Ok(true) => Poll::Ready(Ok(future.take_outputs())),
+            Ok(false) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Thread waiting on the fence of a pending `KewComputeFuture`.
+struct FenceWaiter {
+    waker: Arc<Mutex<Waker>>,
+    thread: JoinHandle<()>,
+}
+
+impl Drop for KewComputeFuture<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewComputeFuture");
+        // the resources may only be freed once the queue is done with them
+        let _ = self.fence.wait(Duration::MAX);
+        // the waiter has to be done with the fence before it is destroyed
+        if let Some(waiter) = self.waiter.take() {
+            let _ = waiter.thread.join();
+        }
+        self.cmd_pool.free_command_buffers(&[self.cmd_buffer]);
+    }
+}
+
+/// Resources of a completed `KewComputeJob`.
+pub struct KewComputeOutputs<'a> {
+    resources: Vec<JobResource<'a>>,
+}
+
+impl KewComputeOutputs<'_> {
+    pub fn read<T: Copy + Default>(&self, output: &KewJobOutput<T>) -> Vec<T> {
+        let buffer = match &self.resources[output.binding] {
+            JobResource::BUFFER(buffer) => buffer,
+            JobResource::IMAGE { staging, .. } => staging,
+        };
+        let mut data = vec![T::default(); output.len];
+        unsafe { buffer.rd_visible_mem(&mut data, (output.len * size_of::<T>()) as u64, 0) };
+        data
+    }
+
+    pub fn read_image(&self, output: &KewJobImage) -> RgbaImage {
+        match &self.resources[output.binding] {
+            JobResource::IMAGE { image, staging, .. } => {
+                let mut image_data = RgbaImage::new(image.extent.width, image.extent.height);
+                unsafe { staging.rd_visible_mem(&mut image_data, staging.b_size, 0) };
+                image_data
+            }
+            JobResource::BUFFER(_) => unreachable!("job image handle of a buffer binding"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_workgroups_are_rounded_up() {
+        let workgroups = dispatch_size(None, Some([1000, 300, 1]), Some([16, 16, 1]));
+        assert_eq!(workgroups.unwrap(), [63, 19, 1]);
+    }
+
+    #[test]
+    fn exact_multiples_are_not_rounded() {
+        let workgroups = dispatch_size(None, Some([64, 32, 1]), Some([16, 16, 1]));
+        assert_eq!(workgroups.unwrap(), [4, 2, 1]);
+    }
+
+    #[test]
+    fn missing_local_size_dispatches_per_element() {
+        let workgroups = dispatch_size(None, Some([7, 1, 1]), None);
+        assert_eq!(workgroups.unwrap(), [7, 1, 1]);
+    }
+
+    #[test]
+    fn explicit_workgroups_override_elements() {
+        let workgroups = dispatch_size(Some([2, 3, 4]), Some([1000, 300, 1]), Some([16, 16, 1]));
+        assert_eq!(workgroups.unwrap(), [2, 3, 4]);
+        let workgroups = dispatch_size(Some([5, 1, 1]), None, None);
+        assert_eq!(workgroups.unwrap(), [5, 1, 1]);
+    }
+
+    #[test]
+    fn dispatch_without_outputs_fails() {
+        assert!(dispatch_size(None, None, Some([16, 1, 1])).is_err());
+    }
+}
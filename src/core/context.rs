@@ -1,3 +1,4 @@
+use crate::core::error::KewError;
 use crate::core::ENABLE_VALIDATION_LAYERS;
 use ash::ext::debug_utils;
 #[allow(unused_imports)]
@@ -13,32 +14,49 @@ pub struct KewContext {
     pub instance: Instance,
     pub physical: vk::PhysicalDevice,
     pub mem_properties: vk::PhysicalDeviceMemoryProperties,
+    pub headless: bool,
     #[cfg(debug_assertions)]
     debug_utils: Option<(debug_utils::Instance, vk::DebugUtilsMessengerEXT)>,
 }
 
 impl KewContext {
-    pub fn new() -> Self {
-        let entry: Entry = unsafe { Entry::load().expect("failed loading entry") };
-        let instance = Self::create_instance(&entry);
+    pub fn new() -> Result<Self, KewError> {
+        Self::create(false)
+    }
+
+    /// Context without surface extensions, for compute and offscreen work without a window.
+    pub fn new_headless() -> Result<Self, KewError> {
+        Self::create(true)
+    }
+
+    fn create(headless: bool) -> Result<Self, KewError> {
+        let entry: Entry = unsafe { Entry::load()? };
+        let instance = Self::create_instance(&entry, headless)?;
         unsafe {
+            let physical = match Self::pick_physical_device(&instance) {
+                Ok(physical) => physical,
+                Err(e) => {
+                    instance.destroy_instance(None);
+                    return Err(e);
+                }
+            };
             #[cfg(debug_assertions)]
             let debug_utils = Self::create_debug_utils(&entry, &instance);
-            let physical = Self::pick_physical_device(&instance);
             let mem_properties = instance.get_physical_device_memory_properties(physical);
 
-            Self {
+            Ok(Self {
                 entry,
                 instance,
                 physical,
                 mem_properties,
+                headless,
                 #[cfg(debug_assertions)]
                 debug_utils,
-            }
+            })
         }
     }
 
-    fn create_instance(entry: &Entry) -> Instance {
+    fn create_instance(entry: &Entry, headless: bool) -> Result<Instance, KewError> {
         let kew_str = CString::new("kew").unwrap();
         let version = get_version();
         let app_info = vk::ApplicationInfo::default()
@@ -47,7 +65,7 @@ impl KewContext {
             .application_version(version)
             .engine_name(&kew_str)
             .engine_version(version);
-        let extensions = Self::get_extensions();
+        let extensions = Self::get_extensions(headless);
 
         let mut create_info = vk::InstanceCreateInfo::default()
             .application_info(&app_info)
@@ -65,17 +83,19 @@ impl KewContext {
             } else {
                 entry.create_instance(&create_info, None)
             };
-            instance.expect("failed creating instance")
+            Ok(instance?)
         }
     }
 
-    fn get_extensions() -> Vec<*const i8> {
+    fn get_extensions(headless: bool) -> Vec<*const i8> {
         let mut extensions: Vec<*const i8> = Vec::new();
-        extensions.push(surface::NAME.as_ptr());
-        #[cfg(target_os = "windows")]
-        extensions.push(win32_surface::NAME.as_ptr());
-        #[cfg(target_os = "linux")]
-        extensions.push(wayland_surface::NAME.as_ptr());
+        if !headless {
+            extensions.push(surface::NAME.as_ptr());
+            #[cfg(target_os = "windows")]
+            extensions.push(win32_surface::NAME.as_ptr());
+            #[cfg(target_os = "linux")]
+            extensions.push(wayland_surface::NAME.as_ptr());
+        }
         if cfg!(debug_assertions) {
             extensions.push(debug_utils::NAME.as_ptr());
         }
@@ -83,10 +103,8 @@ impl KewContext {
         extensions
     }
 
-    unsafe fn pick_physical_device(instance: &Instance) -> vk::PhysicalDevice {
-        let devices = instance
-            .enumerate_physical_devices()
-            .expect("failed to enumerate physical devices");
+    unsafe fn pick_physical_device(instance: &Instance) -> Result<vk::PhysicalDevice, KewError> {
+        let devices = instance.enumerate_physical_devices()?;
         info!("instance enumerated {} device(s)", devices.len());
 
         match devices.iter().find(|device| {
@@ -95,10 +113,13 @@ impl KewContext {
                 .device_type;
             device_type == vk::PhysicalDeviceType::DISCRETE_GPU
         }) {
-            Some(selected) => *selected,
+            Some(selected) => Ok(*selected),
             None => {
                 warn!("no discrete gpu found, falling back to first enumerated");
-                *devices.first().expect("no devices found")
+                devices
+                    .first()
+                    .copied()
+                    .ok_or(KewError::UNSUPPORTED("no physical devices found"))
             }
         }
     }
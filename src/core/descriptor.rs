@@ -1,6 +1,11 @@
 use crate::core::device::KewDevice;
+use crate::core::error::KewError;
 use ash::vk;
 use log::debug;
+use std::slice;
+
+/// Largest pool chained by `KewDescriptorAllocator`, in sets.
+const MAX_POOL_SETS: u32 = 4096;
 
 pub struct KewDescriptorPool<'a> {
     kew_device: &'a KewDevice,
@@ -13,30 +18,31 @@ impl<'a> KewDescriptorPool<'a> {
         pool_sizes: &Vec<vk::DescriptorPoolSize>,
         pool_flags: vk::DescriptorPoolCreateFlags,
         max_sets: u32,
-    ) -> Self {
+    ) -> Result<Self, KewError> {
         let create_info = vk::DescriptorPoolCreateInfo::default()
             .pool_sizes(pool_sizes.as_slice())
             .max_sets(max_sets)
             .flags(pool_flags);
-        let pool = unsafe {
-            kew_device
-                .create_descriptor_pool(&create_info, None)
-                .expect("failed to create descriptor pool")
-        };
-        Self { kew_device, pool }
+        let pool = unsafe { kew_device.create_descriptor_pool(&create_info, None)? };
+        Ok(Self { kew_device, pool })
     }
 
     pub unsafe fn allocate_descriptor_set(
         &self,
         descriptor_set_layout: vk::DescriptorSetLayout,
-    ) -> vk::DescriptorSet {
+    ) -> Result<vk::DescriptorSet, KewError> {
         let binding = [descriptor_set_layout];
         let alloc_info = vk::DescriptorSetAllocateInfo::default()
             .descriptor_pool(self.pool)
             .set_layouts(&binding);
+        Ok(self.kew_device.allocate_descriptor_sets(&alloc_info)?[0])
+    }
+
+    /// Frees every set allocated from the pool.
+    pub unsafe fn reset(&self) -> Result<(), KewError> {
         self.kew_device
-            .allocate_descriptor_sets(&alloc_info)
-            .expect("failed to allocate descriptor set")[0]
+            .reset_descriptor_pool(self.pool, vk::DescriptorPoolResetFlags::empty())?;
+        Ok(())
     }
 }
 
@@ -76,7 +82,152 @@ impl<'a> KewDescriptorPoolBuilder {
         self
     }
 
-    pub fn build(self, kew_device: &'a KewDevice) -> KewDescriptorPool<'a> {
+    pub fn pool_flags(mut self, pool_flags: vk::DescriptorPoolCreateFlags) -> Self {
+        self.pool_flags = pool_flags;
+        self
+    }
+
+    pub fn build(self, kew_device: &'a KewDevice) -> Result<KewDescriptorPool<'a>, KewError> {
         KewDescriptorPool::new(kew_device, &self.pool_sizes, self.pool_flags, self.max_sets)
     }
 }
+
+/// Allocates sets of any layout from a chain of pools. When the current pool runs out the next
+/// one is used, or a new one twice the size of the last is chained. Sets live until `reset`,
+/// so sets written every frame come from an allocator per frame in flight.
+pub struct KewDescriptorAllocator<'a> {
+    kew_device: &'a KewDevice,
+    /// Descriptors of each type per set, scaled by the sets of a pool.
+    pool_ratios: Vec<(vk::DescriptorType, u32)>,
+    pools: Vec<KewDescriptorPool<'a>>,
+    /// Pools before `current` are exhausted.
+    current: usize,
+    next_pool_sets: u32,
+}
+
+impl<'a> KewDescriptorAllocator<'a> {
+    pub fn new(
+        kew_device: &'a KewDevice,
+        pool_ratios: &[(vk::DescriptorType, u32)],
+        initial_pool_sets: u32,
+    ) -> Self {
+        Self {
+            kew_device,
+            pool_ratios: pool_ratios.to_vec(),
+            pools: Vec::new(),
+            current: 0,
+            next_pool_sets: initial_pool_sets.clamp(1, MAX_POOL_SETS),
+        }
+    }
+
+    pub fn allocate(
+        &mut self,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, KewError> {
+        loop {
+            let created = self.current == self.pools.len();
+            if created {
+                let pool = self.create_pool()?;
+                self.pools.push(pool);
+            }
+            match unsafe { self.pools[self.current].allocate_descriptor_set(descriptor_set_layout) }
+            {
+                Err(KewError::VULKAN(
+                    vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL,
+                )) if !created => self.current += 1,
+                result => return result,
+            }
+        }
+    }
+
+    /// Frees every set allocated so far and keeps the pools for the next ones.
+    pub fn reset(&mut self) -> Result<(), KewError> {
+        for pool in &self.pools[..self.pools.len().min(self.current + 1)] {
+            unsafe { pool.reset()? };
+        }
+        self.current = 0;
+        Ok(())
+    }
+
+    fn create_pool(&mut self) -> Result<KewDescriptorPool<'a>, KewError> {
+        let max_sets = self.next_pool_sets;
+        let mut pool_builder = KewDescriptorPoolBuilder::new(max_sets);
+        for (descriptor_type, count) in &self.pool_ratios {
+            pool_builder = pool_builder.add_pool_size(*descriptor_type, count * max_sets);
+        }
+        let pool = pool_builder.build(self.kew_device)?;
+        self.next_pool_sets = (max_sets * 2).min(MAX_POOL_SETS);
+        debug!("chained descriptor pool ({} sets)", max_sets);
+        Ok(pool)
+    }
+}
+
+enum DescriptorInfo {
+    BUFFER(usize),
+    IMAGE(usize),
+}
+
+/// Collects writes to any number of sets and bindings and applies them with a single
+/// `update_descriptor_sets` call.
+#[derive(Default)]
+pub struct KewDescriptorWriter {
+    buffer_infos: Vec<vk::DescriptorBufferInfo>,
+    image_infos: Vec<vk::DescriptorImageInfo>,
+    writes: Vec<(vk::DescriptorSet, u32, vk::DescriptorType, DescriptorInfo)>,
+}
+
+impl KewDescriptorWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_buffer(
+        &mut self,
+        descriptor_set: vk::DescriptorSet,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer_info: vk::DescriptorBufferInfo,
+    ) -> &mut Self {
+        self.buffer_infos.push(buffer_info);
+        let info = DescriptorInfo::BUFFER(self.buffer_infos.len() - 1);
+        self.writes
+            .push((descriptor_set, binding, descriptor_type, info));
+        self
+    }
+
+    pub fn write_image(
+        &mut self,
+        descriptor_set: vk::DescriptorSet,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        image_info: vk::DescriptorImageInfo,
+    ) -> &mut Self {
+        self.image_infos.push(image_info);
+        let info = DescriptorInfo::IMAGE(self.image_infos.len() - 1);
+        self.writes
+            .push((descriptor_set, binding, descriptor_type, info));
+        self
+    }
+
+    pub fn update(&self, kew_device: &KewDevice) {
+        let writes = self
+            .writes
+            .iter()
+            .map(|(descriptor_set, binding, descriptor_type, info)| {
+                let write = vk::WriteDescriptorSet::default()
+                    .dst_set(*descriptor_set)
+                    .dst_binding(*binding)
+                    .descriptor_type(*descriptor_type);
+                match info {
+                    DescriptorInfo::BUFFER(idx) => {
+                        write.buffer_info(slice::from_ref(&self.buffer_infos[*idx]))
+                    }
+                    DescriptorInfo::IMAGE(idx) => {
+                        write.image_info(slice::from_ref(&self.image_infos[*idx]))
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+        unsafe { kew_device.update_descriptor_sets(&writes, &[]) };
+    }
+}
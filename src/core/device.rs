@@ -1,38 +1,131 @@
+use crate::core::cache::KewPipelineCache;
 use crate::core::context::KewContext;
+use crate::core::error::KewError;
+use crate::core::{PIPELINE_CACHE_PATH, PREFERRED_DEPTH_FORMATS};
 use ash::khr::{surface, swapchain};
 use ash::{vk, Device};
-use log::debug;
+use log::{debug, warn};
 use std::ops::Deref;
 
 pub struct KewDevice {
     pub context: KewContext,
+    pub features: vk::PhysicalDeviceFeatures,
+    /// Descriptor indexing features needed by `KewBindlessTable` are enabled.
+    pub bindless: bool,
+    /// Dynamic rendering (vulkan 1.3) is enabled, see `PipelineTarget::DYNAMIC`.
+    pub dynamic_rendering: bool,
+    /// Timeline semaphores (vulkan 1.2) are enabled, see `KewTimeline`.
+    pub timeline_semaphores: bool,
+    /// Used by every pipeline created on the device, saved when the device is dropped.
+    pub pipeline_cache: KewPipelineCache,
     vk_device: Device,
 }
 
 impl KewDevice {
-    pub fn new(context: KewContext, queue_indices: &KewQueueIndices) -> Self {
+    pub fn new(context: KewContext, queue_indices: &KewQueueIndices) -> Result<Self, KewError> {
         let queue_create_infos = queue_indices.get_queue_create_infos();
-        let device_features = vk::PhysicalDeviceFeatures::default();
-        let extension_names = [swapchain::NAME.as_ptr()];
+        let api_version = unsafe {
+            context
+                .instance
+                .get_physical_device_properties(context.physical)
+                .api_version
+        };
+        let mut supported_features12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut supported_features13 = vk::PhysicalDeviceVulkan13Features::default();
+        let mut supported_features2 = vk::PhysicalDeviceFeatures2::default();
+        if api_version >= vk::API_VERSION_1_2 {
+            supported_features2 = supported_features2.push_next(&mut supported_features12);
+        }
+        if api_version >= vk::API_VERSION_1_3 {
+            supported_features2 = supported_features2.push_next(&mut supported_features13);
+        }
+        // vulkan 1.0 devices cannot be queried for extended features
+        let supported_features = match api_version >= vk::API_VERSION_1_1 {
+            true => unsafe {
+                context
+                    .instance
+                    .get_physical_device_features2(context.physical, &mut supported_features2);
+                supported_features2.features
+            },
+            false => unsafe {
+                context
+                    .instance
+                    .get_physical_device_features(context.physical)
+            },
+        };
+
+        let timeline_semaphores = supported_features12.timeline_semaphore == vk::TRUE;
+        if !timeline_semaphores {
+            warn!("timeline semaphores unsupported (fences only)");
+        }
+        let bindless = supports_bindless(&supported_features12);
+        if !bindless {
+            warn!("descriptor indexing unsupported (bindless disabled)");
+        }
+        let dynamic_rendering = supported_features13.dynamic_rendering == vk::TRUE;
+        if !dynamic_rendering {
+            warn!("dynamic rendering unsupported (render passes only)");
+        }
 
-        let create_info = vk::DeviceCreateInfo::default()
+        let device_features = vk::PhysicalDeviceFeatures::default()
+            .sample_rate_shading(supported_features.sample_rate_shading == vk::TRUE)
+            .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE);
+        let mut device_features12 = vk::PhysicalDeviceVulkan12Features::default()
+            .timeline_semaphore(timeline_semaphores)
+            .descriptor_indexing(bindless)
+            .runtime_descriptor_array(bindless)
+            .descriptor_binding_partially_bound(bindless)
+            .descriptor_binding_sampled_image_update_after_bind(bindless)
+            .descriptor_binding_storage_buffer_update_after_bind(bindless)
+            .descriptor_binding_update_unused_while_pending(bindless)
+            .shader_sampled_image_array_non_uniform_indexing(bindless)
+            .shader_storage_buffer_array_non_uniform_indexing(bindless);
+        let mut device_features13 =
+            vk::PhysicalDeviceVulkan13Features::default().dynamic_rendering(true);
+        let extension_names = match context.headless {
+            true => vec![],
+            false => vec![swapchain::NAME.as_ptr()],
+        };
+
+        let mut create_info = vk::DeviceCreateInfo::default()
             .enabled_features(&device_features)
             .enabled_extension_names(&extension_names)
             .queue_create_infos(&queue_create_infos);
+        if api_version >= vk::API_VERSION_1_2 {
+            create_info = create_info.push_next(&mut device_features12);
+        }
+        if dynamic_rendering {
+            create_info = create_info.push_next(&mut device_features13);
+        }
         let vk_device = unsafe {
             context
                 .instance
-                .create_device(context.physical, &create_info, None)
-                .unwrap_or_else(|e| panic!("failed to create logical device: {}", e))
+                .create_device(context.physical, &create_info, None)?
         };
-        Self { context, vk_device }
+        let pipeline_cache = match KewPipelineCache::new(&context, &vk_device, PIPELINE_CACHE_PATH)
+        {
+            Ok(pipeline_cache) => pipeline_cache,
+            Err(e) => {
+                unsafe { vk_device.destroy_device(None) };
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            context,
+            features: device_features,
+            bindless,
+            dynamic_rendering,
+            timeline_semaphores,
+            pipeline_cache,
+            vk_device,
+        })
     }
 
     pub fn find_memory_type(
         &self,
         memory_reqs: &vk::MemoryRequirements,
         memory_flag: vk::MemoryPropertyFlags,
-    ) -> Option<u32> {
+    ) -> Result<u32, KewError> {
         self.context.mem_properties.memory_types
             [..self.context.mem_properties.memory_type_count as _]
             .iter()
@@ -42,6 +135,66 @@ impl KewDevice {
                     && memory_type.property_flags & memory_flag == memory_flag
             })
             .map(|(idx, _memory_type)| idx as _)
+            .ok_or(KewError::MEMORY(memory_flag))
+    }
+
+    pub fn find_supported_format(
+        &self,
+        candidates: &[vk::Format],
+        tiling: vk::ImageTiling,
+        features: vk::FormatFeatureFlags,
+    ) -> Option<vk::Format> {
+        candidates.iter().copied().find(|format| {
+            let properties = unsafe {
+                self.context
+                    .instance
+                    .get_physical_device_format_properties(self.context.physical, *format)
+            };
+            match tiling {
+                vk::ImageTiling::LINEAR => properties.linear_tiling_features.contains(features),
+                _ => properties.optimal_tiling_features.contains(features),
+            }
+        })
+    }
+
+    /// Largest sample count not above `requested` usable for both color and depth attachments.
+    pub fn clamp_sample_count(&self, requested: vk::SampleCountFlags) -> vk::SampleCountFlags {
+        let limits = unsafe {
+            self.context
+                .instance
+                .get_physical_device_properties(self.context.physical)
+                .limits
+        };
+        let supported =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let samples = [
+            vk::SampleCountFlags::TYPE_64,
+            vk::SampleCountFlags::TYPE_32,
+            vk::SampleCountFlags::TYPE_16,
+            vk::SampleCountFlags::TYPE_8,
+            vk::SampleCountFlags::TYPE_4,
+            vk::SampleCountFlags::TYPE_2,
+        ]
+        .into_iter()
+        .find(|samples| samples.as_raw() <= requested.as_raw() && supported.contains(*samples))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1);
+        if samples != requested {
+            debug!("sample count {:?} clamped to {:?}", requested, samples);
+        }
+        samples
+    }
+
+    /// First of the preferred depth formats usable as an optimal tiling depth attachment.
+    pub fn find_depth_format(&self) -> Result<vk::Format, KewError> {
+        let format = self
+            .find_supported_format(
+                &PREFERRED_DEPTH_FORMATS,
+                vk::ImageTiling::OPTIMAL,
+                vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT,
+            )
+            .ok_or(KewError::UNSUPPORTED("no depth attachment format"))?;
+        debug!("depth format: {:?}", format);
+        Ok(format)
     }
 }
 
@@ -56,26 +209,70 @@ impl Deref for KewDevice {
 impl Drop for KewDevice {
     fn drop(&mut self) {
         debug!("dropping KewDevice");
+        if let Err(e) = self.pipeline_cache.save(&self.vk_device) {
+            warn!("failed saving pipeline cache: {}", e);
+        }
         unsafe {
+            self.pipeline_cache.destroy(&self.vk_device);
             self.vk_device.destroy_device(None);
         }
     }
 }
 
+/// Sorted `families` without duplicates, e.g. graphics and transfer sharing a family. Concurrent
+/// resources have to list each family once.
+pub fn distinct_families(families: &[u32]) -> Vec<u32> {
+    let mut families = families.to_vec();
+    families.sort_unstable();
+    families.dedup();
+    families
+}
+
+fn supports_bindless(features12: &vk::PhysicalDeviceVulkan12Features) -> bool {
+    [
+        features12.descriptor_indexing,
+        features12.runtime_descriptor_array,
+        features12.descriptor_binding_partially_bound,
+        features12.descriptor_binding_sampled_image_update_after_bind,
+        features12.descriptor_binding_storage_buffer_update_after_bind,
+        features12.descriptor_binding_update_unused_while_pending,
+        features12.shader_sampled_image_array_non_uniform_indexing,
+        features12.shader_storage_buffer_array_non_uniform_indexing,
+    ]
+    .into_iter()
+    .all(|supported| supported == vk::TRUE)
+}
+
 pub struct KewQueueIndices {
     pub gfx_idx: u32,
     pub cmp_idx: u32,
     pub tfr_idx: u32,
-    pub prs_idx: u32,
+    pub prs_idx: Option<u32>,
 }
 
 impl KewQueueIndices {
-    // TODO: add heuristics
     pub fn new(
         context: &KewContext,
         surface_loader: &surface::Instance,
         surface: vk::SurfaceKHR,
-    ) -> Self {
+    ) -> Result<Self, KewError> {
+        let indices = Self::find(context, Some((surface_loader, surface)))?;
+        if indices.prs_idx.is_none() {
+            return Err(KewError::QUEUE("present"));
+        }
+        Ok(indices)
+    }
+
+    /// Queue indices without present support (`prs_idx` is always `None`).
+    pub fn new_headless(context: &KewContext) -> Result<Self, KewError> {
+        Self::find(context, None)
+    }
+
+    // TODO: add heuristics
+    fn find(
+        context: &KewContext,
+        presentation: Option<(&surface::Instance, vk::SurfaceKHR)>,
+    ) -> Result<Self, KewError> {
         let queue_families = unsafe {
             context
                 .instance
@@ -97,13 +294,17 @@ impl KewQueueIndices {
             if tfr_idx.0.is_none() && qfp.queue_flags.contains(vk::QueueFlags::TRANSFER) {
                 tfr_idx.0 = Some(idx);
             }
-            let present_support = unsafe {
-                surface_loader
-                    .get_physical_device_surface_support(context.physical, idx, surface)
-                    .unwrap()
-            };
-            if prs_idx.is_none() && present_support {
-                prs_idx = Some(idx);
+            if let Some((surface_loader, surface)) = presentation {
+                let present_support = unsafe {
+                    surface_loader.get_physical_device_surface_support(
+                        context.physical,
+                        idx,
+                        surface,
+                    )?
+                };
+                if prs_idx.is_none() && present_support {
+                    prs_idx = Some(idx);
+                }
             }
 
             // prefer non-graphics compute queue
@@ -130,21 +331,25 @@ impl KewQueueIndices {
                 continue;
             }
         }
-        match (gfx_idx.0, cmp_idx.0, tfr_idx.0, prs_idx) {
-            (Some(gfx), Some(cmp), Some(tfr), Some(prs)) => Self {
-                gfx_idx: gfx,
-                cmp_idx: cmp,
-                tfr_idx: tfr,
-                prs_idx: prs,
-            },
-            _ => panic!("failed to find required queue families"),
+        Ok(Self {
+            gfx_idx: gfx_idx.0.ok_or(KewError::QUEUE("graphics"))?,
+            cmp_idx: cmp_idx.0.ok_or(KewError::QUEUE("compute"))?,
+            tfr_idx: tfr_idx.0.ok_or(KewError::QUEUE("transfer"))?,
+            prs_idx,
+        })
+    }
+
+    /// Distinct queue families in use, e.g. for resources shared concurrently between them.
+    pub fn families(&self) -> Vec<u32> {
+        let mut indices = vec![self.gfx_idx, self.cmp_idx, self.tfr_idx];
+        if let Some(prs_idx) = self.prs_idx {
+            indices.push(prs_idx);
         }
+        distinct_families(&indices)
     }
 
     fn get_queue_create_infos(&self) -> Vec<vk::DeviceQueueCreateInfo> {
-        let mut indices = vec![self.gfx_idx, self.cmp_idx, self.tfr_idx];
-        indices.dedup();
-        indices
+        self.families()
             .iter()
             .map(|idx| {
                 vk::DeviceQueueCreateInfo::default()
@@ -0,0 +1,87 @@
+use ash::vk;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+#[derive(Debug)]
+pub enum KewError {
+    /// Vulkan call returned an error code.
+    VULKAN(vk::Result),
+    /// Vulkan loader could not be found or loaded.
+    LOADING(ash::LoadingError),
+    IO(io::Error),
+    IMAGE(image::ImageError),
+    /// Model or material file could not be parsed.
+    ASSET(String),
+    /// SPIR-V module could not be parsed or does not match its configuration.
+    SPIRV(String),
+    /// GLSL source could not be compiled to SPIR-V, holds the compiler output.
+    COMPILE(String),
+    /// Shader directory could not be watched for changes.
+    WATCH(notify::Error),
+    /// Required queue family (graphics, compute, transfer or present) is missing.
+    QUEUE(&'static str),
+    /// No memory type satisfies both the resource requirements and the property flags.
+    MEMORY(vk::MemoryPropertyFlags),
+    UNSUPPORTED(&'static str),
+}
+
+impl Display for KewError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KewError::VULKAN(result) => write!(f, "vulkan error: {}", result),
+            KewError::LOADING(e) => write!(f, "failed loading vulkan entry: {}", e),
+            KewError::IO(e) => write!(f, "io error: {}", e),
+            KewError::IMAGE(e) => write!(f, "image error: {}", e),
+            KewError::ASSET(msg) => write!(f, "invalid asset: {}", msg),
+            KewError::SPIRV(msg) => write!(f, "invalid spir-v: {}", msg),
+            KewError::COMPILE(msg) => write!(f, "shader compilation failed: {}", msg),
+            KewError::WATCH(e) => write!(f, "failed watching shaders: {}", e),
+            KewError::QUEUE(family) => write!(f, "missing {} queue family", family),
+            KewError::MEMORY(flags) => write!(f, "missing memory type with {:?}", flags),
+            KewError::UNSUPPORTED(what) => write!(f, "unsupported: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for KewError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KewError::VULKAN(result) => Some(result),
+            KewError::LOADING(e) => Some(e),
+            KewError::IO(e) => Some(e),
+            KewError::IMAGE(e) => Some(e),
+            KewError::WATCH(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<vk::Result> for KewError {
+    fn from(result: vk::Result) -> Self {
+        KewError::VULKAN(result)
+    }
+}
+
+impl From<ash::LoadingError> for KewError {
+    fn from(e: ash::LoadingError) -> Self {
+        KewError::LOADING(e)
+    }
+}
+
+impl From<io::Error> for KewError {
+    fn from(e: io::Error) -> Self {
+        KewError::IO(e)
+    }
+}
+
+impl From<image::ImageError> for KewError {
+    fn from(e: image::ImageError) -> Self {
+        KewError::IMAGE(e)
+    }
+}
+
+impl From<notify::Error> for KewError {
+    fn from(e: notify::Error) -> Self {
+        KewError::WATCH(e)
+    }
+}
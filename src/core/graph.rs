@@ -0,0 +1,1106 @@
+use crate::core::buffer::KewBuffer;
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::image::{aspect_flags, KewImage};
+use crate::core::memory::{align_up, KewAllocation, KewAllocator, KewResourceKind};
+use crate::core::rendering::RenderingAttachment;
+use ash::vk;
+use log::{debug, warn};
+use std::mem;
+
+/// Image declared in a `KewRenderGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphImage(usize);
+
+/// Buffer declared in a `KewRenderGraph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GraphBuffer(usize);
+
+/// How a pass uses an image, which decides the layout the image is moved to before the pass.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageAccess {
+    /// Color or resolve attachment, of dynamic rendering or of a render pass that keeps it in
+    /// `COLOR_ATTACHMENT_OPTIMAL`.
+    COLOR,
+    /// Depth (and stencil) attachment, like `COLOR`.
+    DEPTH,
+    /// Sampled by shaders in the given stages.
+    SAMPLED(vk::PipelineStageFlags),
+    /// Storage image read and written by shaders in the given stages.
+    STORAGE(vk::PipelineStageFlags),
+    /// Source of copies and blits.
+    SOURCE,
+    /// Destination of copies and blits.
+    DESTINATION,
+    /// Presentation, only meaningful as the export of a swapchain image.
+    PRESENT,
+}
+
+impl ImageAccess {
+    fn state(self) -> AccessState {
+        let (layout, stages, access, writes) = match self {
+            ImageAccess::COLOR => (
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                true,
+            ),
+            ImageAccess::DEPTH => (
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                true,
+            ),
+            ImageAccess::SAMPLED(stages) => (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                stages,
+                vk::AccessFlags::SHADER_READ,
+                false,
+            ),
+            ImageAccess::STORAGE(stages) => (
+                vk::ImageLayout::GENERAL,
+                stages,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                true,
+            ),
+            ImageAccess::SOURCE => (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                false,
+            ),
+            ImageAccess::DESTINATION => (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                true,
+            ),
+            ImageAccess::PRESENT => (
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                vk::AccessFlags::empty(),
+                false,
+            ),
+        };
+        AccessState {
+            layout,
+            stages,
+            access,
+            writes,
+        }
+    }
+
+    /// Usage a transient image needs for this access.
+    fn usage(self) -> vk::ImageUsageFlags {
+        match self {
+            ImageAccess::COLOR => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            ImageAccess::DEPTH => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            ImageAccess::SAMPLED(_) => vk::ImageUsageFlags::SAMPLED,
+            ImageAccess::STORAGE(_) => vk::ImageUsageFlags::STORAGE,
+            ImageAccess::SOURCE => vk::ImageUsageFlags::TRANSFER_SRC,
+            ImageAccess::DESTINATION => vk::ImageUsageFlags::TRANSFER_DST,
+            ImageAccess::PRESENT => vk::ImageUsageFlags::empty(),
+        }
+    }
+}
+
+/// How a pass uses a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferAccess {
+    /// Storage buffer read and written by shaders in the given stages.
+    STORAGE(vk::PipelineStageFlags),
+    /// Source of copies.
+    SOURCE,
+    /// Destination of copies.
+    DESTINATION,
+    /// Read by the host once the submission has completed.
+    HOST,
+}
+
+impl BufferAccess {
+    fn state(self) -> AccessState {
+        let (stages, access, writes) = match self {
+            BufferAccess::STORAGE(stages) => (
+                stages,
+                vk::AccessFlags::SHADER_READ | vk::AccessFlags::SHADER_WRITE,
+                true,
+            ),
+            BufferAccess::SOURCE => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                false,
+            ),
+            BufferAccess::DESTINATION => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                true,
+            ),
+            BufferAccess::HOST => (
+                vk::PipelineStageFlags::HOST,
+                vk::AccessFlags::HOST_READ,
+                false,
+            ),
+        };
+        AccessState {
+            layout: vk::ImageLayout::UNDEFINED,
+            stages,
+            access,
+            writes,
+        }
+    }
+}
+
+/// Description of a transient image, its usage is derived from the passes using it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransientImageInfo {
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+}
+
+/// Image owned outside of kew (e.g. a swapchain image) with the layout it is in and the stages
+/// that have to complete before it is used, `UNDEFINED` discards its contents.
+#[derive(Clone, Copy)]
+pub struct ExternalImage {
+    pub attachment: RenderingAttachment,
+    pub layout: vk::ImageLayout,
+    pub stages: vk::PipelineStageFlags,
+}
+
+#[derive(Clone, Copy)]
+struct AccessState {
+    layout: vk::ImageLayout,
+    stages: vk::PipelineStageFlags,
+    access: vk::AccessFlags,
+    writes: bool,
+}
+
+/// What earlier accesses of a resource a later access has to wait for.
+#[derive(Clone, Copy)]
+struct Tracked {
+    layout: vk::ImageLayout,
+    write_stages: vk::PipelineStageFlags,
+    write_access: vk::AccessFlags,
+    /// Stages that have read since the last write (or transition) and see its result.
+    read_stages: vk::PipelineStageFlags,
+}
+
+impl Tracked {
+    fn new(
+        layout: vk::ImageLayout,
+        stages: vk::PipelineStageFlags,
+        access: vk::AccessFlags,
+    ) -> Self {
+        Self {
+            layout,
+            write_stages: stages,
+            write_access: access,
+            read_stages: vk::PipelineStageFlags::empty(),
+        }
+    }
+
+    /// Moves to `state`, returning the source stages and access and the old layout of the
+    /// barrier it needs.
+    fn transition(
+        &mut self,
+        state: AccessState,
+    ) -> Option<(vk::PipelineStageFlags, vk::AccessFlags, vk::ImageLayout)> {
+        let old_layout = self.layout;
+        if state.writes || state.layout != old_layout {
+            // writes and transitions wait for every earlier read and write
+            let src_stages = self.write_stages | self.read_stages;
+            let src_access = self.write_access;
+            *self = match state.writes {
+                true => Self::new(state.layout, state.stages, state.access),
+                // later reads in other stages wait for the transition
+                false => Self {
+                    layout: state.layout,
+                    write_stages: state.stages,
+                    write_access: vk::AccessFlags::empty(),
+                    read_stages: state.stages,
+                },
+            };
+            return (state.layout != old_layout || !src_stages.is_empty())
+                .then_some((src_stages, src_access, old_layout));
+        }
+        let visible = self.read_stages.contains(state.stages);
+        self.read_stages |= state.stages;
+        (!visible && !self.write_stages.is_empty()).then_some((
+            self.write_stages,
+            self.write_access,
+            old_layout,
+        ))
+    }
+
+    /// Stages and accesses a resource aliasing the same memory later has to wait for.
+    fn merge_into(&self, other: &mut Tracked) {
+        other.write_stages |= self.write_stages | self.read_stages;
+        other.write_access |= self.write_access;
+    }
+}
+
+enum ImageSource<'g, 'a> {
+    IMPORTED(&'g mut KewImage<'a>),
+    EXTERNAL,
+    TRANSIENT(TransientImageInfo),
+}
+
+struct ImageEntry<'g, 'a> {
+    name: &'static str,
+    source: ImageSource<'g, 'a>,
+    tracked: Tracked,
+    export: Option<ImageAccess>,
+    /// Handles of external images, and of transient ones once the graph is executed.
+    attachment: Option<RenderingAttachment>,
+}
+
+impl ImageEntry<'_, '_> {
+    fn barrier(
+        &mut self,
+        state: AccessState,
+        barrier: (vk::PipelineStageFlags, vk::AccessFlags, vk::ImageLayout),
+    ) -> vk::ImageMemoryBarrier<'static> {
+        let (src_access, old_layout) = (barrier.1, barrier.2);
+        let (image, subresource) = match &mut self.source {
+            ImageSource::IMPORTED(image) => {
+                image.layout = state.layout;
+                (***image, image.subresource)
+            }
+            _ => {
+                let attachment = self.attachment.unwrap();
+                let subresource = vk::ImageSubresourceRange::default()
+                    .aspect_mask(aspect_flags(attachment.format))
+                    .level_count(1)
+                    .layer_count(1);
+                (attachment.image, subresource)
+            }
+        };
+        vk::ImageMemoryBarrier::default()
+            .old_layout(old_layout)
+            .new_layout(state.layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(subresource)
+            .src_access_mask(src_access)
+            .dst_access_mask(state.access)
+    }
+}
+
+struct BufferEntry<'g, 'a> {
+    name: &'static str,
+    buffer: &'g KewBuffer<'a>,
+    tracked: Tracked,
+    export: Option<BufferAccess>,
+}
+
+type RecordPass<'g, 'a> =
+    Box<dyn FnOnce(vk::CommandBuffer, &GraphResources<'g, 'a>) -> Result<(), KewError> + 'g>;
+
+struct GraphPass<'g, 'a> {
+    name: &'static str,
+    images: Vec<(GraphImage, ImageAccess)>,
+    buffers: Vec<(GraphBuffer, BufferAccess)>,
+    record: RecordPass<'g, 'a>,
+}
+
+/// Resources of a `KewRenderGraph`, handed to passes while they are recorded.
+pub struct GraphResources<'g, 'a> {
+    images: Vec<ImageEntry<'g, 'a>>,
+    buffers: Vec<BufferEntry<'g, 'a>>,
+}
+
+impl<'a> GraphResources<'_, 'a> {
+    /// Image, view and format of `image`, in the layout of the access declared by the pass.
+    /// Fails for imported images without a view.
+    pub fn image(&self, image: GraphImage) -> Result<RenderingAttachment, KewError> {
+        let entry = &self.images[image.0];
+        match &entry.source {
+            ImageSource::IMPORTED(image) => Ok(RenderingAttachment {
+                image: ***image,
+                view: image
+                    .view
+                    .ok_or(KewError::UNSUPPORTED("graph image without a view"))?,
+                format: image.format(),
+            }),
+            _ => Ok(entry.attachment.unwrap()),
+        }
+    }
+
+    /// The imported `KewImage` behind `image`, `None` for external and transient images.
+    pub fn kew_image(&self, image: GraphImage) -> Option<&KewImage<'a>> {
+        match &self.images[image.0].source {
+            ImageSource::IMPORTED(image) => Some(image),
+            _ => None,
+        }
+    }
+
+    pub fn buffer(&self, buffer: GraphBuffer) -> &KewBuffer<'a> {
+        self.buffers[buffer.0].buffer
+    }
+}
+
+/// Passes recorded into one command buffer. Each pass declares how it uses the images and
+/// buffers of the graph, which moves them to the right layouts and inserts the barriers between
+/// passes. Passes whose writes are neither read by a later pass nor land in an imported
+/// resource are culled. Transient images live in a `KewTransientPool`, transient images that
+/// are not used by the same passes share memory.
+///
+/// ```ignore
+/// let mut graph = KewRenderGraph::new(kew_device);
+/// let color = swapchain.import_image(&mut graph, image_idx);
+/// let depth = graph.create_image("depth", depth_info);
+/// graph
+///     .add_pass("scene")
+///     .image(color, ImageAccess::COLOR)
+///     .image(depth, ImageAccess::DEPTH)
+///     .record(|cmd_buffer, resources| { ..; Ok(()) });
+/// unsafe { graph.execute(cmd_buffer, Some(&mut transient_pool))? };
+/// ```
+pub struct KewRenderGraph<'g, 'a> {
+    kew_device: &'a KewDevice,
+    resources: GraphResources<'g, 'a>,
+    passes: Vec<GraphPass<'g, 'a>>,
+}
+
+impl<'g, 'a> KewRenderGraph<'g, 'a> {
+    pub fn new(kew_device: &'a KewDevice) -> Self {
+        Self {
+            kew_device,
+            resources: GraphResources {
+                images: Vec::new(),
+                buffers: Vec::new(),
+            },
+            passes: Vec::new(),
+        }
+    }
+
+    /// Imports `image` in its current layout, which is kept up to date while the graph is
+    /// executed. `last_access` is the access recorded before the graph in the same command
+    /// buffer, `None` if the image is not used before.
+    pub fn import_image(
+        &mut self,
+        name: &'static str,
+        image: &'g mut KewImage<'a>,
+        last_access: Option<ImageAccess>,
+    ) -> GraphImage {
+        let tracked = imported_state(image.layout, last_access.map(ImageAccess::state));
+        self.push_image(name, ImageSource::IMPORTED(image), tracked)
+    }
+
+    pub fn import_external_image(
+        &mut self,
+        name: &'static str,
+        external: ExternalImage,
+    ) -> GraphImage {
+        let tracked = Tracked::new(external.layout, external.stages, vk::AccessFlags::empty());
+        let entry = self.push_image(name, ImageSource::EXTERNAL, tracked);
+        self.resources.images[entry.0].attachment = Some(external.attachment);
+        entry
+    }
+
+    /// Image that only lives during the execution of the graph, its contents start undefined.
+    pub fn create_image(&mut self, name: &'static str, info: TransientImageInfo) -> GraphImage {
+        let tracked = Tracked::new(
+            vk::ImageLayout::UNDEFINED,
+            vk::PipelineStageFlags::empty(),
+            vk::AccessFlags::empty(),
+        );
+        self.push_image(name, ImageSource::TRANSIENT(info), tracked)
+    }
+
+    /// Imports `buffer`, see `import_image` for `last_access`.
+    pub fn import_buffer(
+        &mut self,
+        name: &'static str,
+        buffer: &'g KewBuffer<'a>,
+        last_access: Option<BufferAccess>,
+    ) -> GraphBuffer {
+        let tracked = imported_state(
+            vk::ImageLayout::UNDEFINED,
+            last_access.map(BufferAccess::state),
+        );
+        self.resources.buffers.push(BufferEntry {
+            name,
+            buffer,
+            tracked,
+            export: None,
+        });
+        GraphBuffer(self.resources.buffers.len() - 1)
+    }
+
+    /// Leaves the imported `image` ready for `access` after the last pass.
+    pub fn export_image(&mut self, image: GraphImage, access: ImageAccess) {
+        let entry = &mut self.resources.images[image.0];
+        match entry.source {
+            ImageSource::TRANSIENT(_) => {
+                warn!("export of transient image {} (skipped)", entry.name)
+            }
+            _ => entry.export = Some(access),
+        }
+    }
+
+    /// Leaves the imported `buffer` ready for `access` after the last pass.
+    pub fn export_buffer(&mut self, buffer: GraphBuffer, access: BufferAccess) {
+        self.resources.buffers[buffer.0].export = Some(access);
+    }
+
+    /// Passes are recorded in the order they are added.
+    pub fn add_pass(&mut self, name: &'static str) -> KewPassBuilder<'_, 'g, 'a> {
+        KewPassBuilder {
+            graph: self,
+            name,
+            images: Vec::new(),
+            buffers: Vec::new(),
+        }
+    }
+
+    /// Records the passes that are not culled with their barriers into `cmd_buffer`.
+    /// `transients` holds the transient images and has to stay alive until the command buffer
+    /// has executed, it is only optional for graphs without transient images.
+    pub unsafe fn execute(
+        mut self,
+        cmd_buffer: vk::CommandBuffer,
+        transients: Option<&mut KewTransientPool<'a>>,
+    ) -> Result<(), KewError> {
+        let kept = self.cull();
+        let lifetimes = self.transient_lifetimes(&kept);
+        let passes = mem::take(&mut self.passes);
+
+        // transient images are placed first, aliases wait for the images they replace
+        let mut aliases = vec![Vec::new(); self.resources.images.len()];
+        if !lifetimes.is_empty() {
+            let transients = transients.ok_or(KewError::UNSUPPORTED(
+                "transient images without a transient pool",
+            ))?;
+            let requests = lifetimes
+                .iter()
+                .map(
+                    |(image, usage, lifetime)| match self.resources.images[*image].source {
+                        ImageSource::TRANSIENT(info) => (info, *usage, *lifetime),
+                        _ => unreachable!(),
+                    },
+                )
+                .collect::<Vec<_>>();
+            transients.prepare(&requests)?;
+            for (slot, (image, _, _)) in lifetimes.iter().enumerate() {
+                let entry = &mut self.resources.images[*image];
+                let transient = &transients.images[slot];
+                entry.attachment = Some(RenderingAttachment {
+                    image: **transient,
+                    view: transient.view.unwrap(),
+                    format: transient.format(),
+                });
+                aliases[*image] = transients
+                    .aliases(slot)
+                    .map(|alias| lifetimes[alias].0)
+                    .collect();
+            }
+        }
+
+        for (pass_idx, pass) in passes.into_iter().enumerate() {
+            if !kept[pass_idx] {
+                continue;
+            }
+            let mut barriers = PassBarriers::default();
+            for (image, access) in &pass.images {
+                let first_use = lifetimes.iter().any(|(transient, _, lifetime)| {
+                    *transient == image.0 && lifetime.0 == pass_idx
+                });
+                if first_use {
+                    let mut tracked = self.resources.images[image.0].tracked;
+                    for alias in &aliases[image.0] {
+                        self.resources.images[*alias]
+                            .tracked
+                            .merge_into(&mut tracked);
+                    }
+                    self.resources.images[image.0].tracked = tracked;
+                }
+                barriers.image(&mut self.resources.images[image.0], access.state());
+            }
+            for (buffer, access) in &pass.buffers {
+                barriers.buffer(&mut self.resources.buffers[buffer.0], access.state());
+            }
+            barriers.record(self.kew_device, cmd_buffer);
+            (pass.record)(cmd_buffer, &self.resources)?;
+        }
+
+        let mut barriers = PassBarriers::default();
+        for entry in self.resources.images.iter_mut() {
+            if let Some(access) = entry.export {
+                barriers.image(entry, access.state());
+            }
+        }
+        for entry in self.resources.buffers.iter_mut() {
+            if let Some(access) = entry.export {
+                barriers.buffer(entry, access.state());
+            }
+        }
+        barriers.record(self.kew_device, cmd_buffer);
+        Ok(())
+    }
+
+    fn push_image(
+        &mut self,
+        name: &'static str,
+        source: ImageSource<'g, 'a>,
+        tracked: Tracked,
+    ) -> GraphImage {
+        self.resources.images.push(ImageEntry {
+            name,
+            source,
+            tracked,
+            export: None,
+            attachment: None,
+        });
+        GraphImage(self.resources.images.len() - 1)
+    }
+
+    /// Passes kept, walking back from the imported resources.
+    fn cull(&self) -> Vec<bool> {
+        let needed_images = self
+            .resources
+            .images
+            .iter()
+            .map(|entry| !matches!(entry.source, ImageSource::TRANSIENT(_)))
+            .collect();
+        cull_passes(
+            &self.passes,
+            needed_images,
+            vec![true; self.resources.buffers.len()],
+        )
+    }
+
+    /// Transient images used by kept passes with their usage and first and last pass.
+    fn transient_lifetimes(
+        &self,
+        kept: &[bool],
+    ) -> Vec<(usize, vk::ImageUsageFlags, (usize, usize))> {
+        let mut lifetimes: Vec<(usize, vk::ImageUsageFlags, (usize, usize))> = Vec::new();
+        for (pass_idx, pass) in self.passes.iter().enumerate() {
+            if !kept[pass_idx] {
+                continue;
+            }
+            for (image, access) in &pass.images {
+                if !matches!(
+                    self.resources.images[image.0].source,
+                    ImageSource::TRANSIENT(_)
+                ) {
+                    continue;
+                }
+                match lifetimes.iter_mut().find(|lifetime| lifetime.0 == image.0) {
+                    Some((_, usage, lifetime)) => {
+                        *usage |= access.usage();
+                        lifetime.1 = pass_idx;
+                    }
+                    None => lifetimes.push((image.0, access.usage(), (pass_idx, pass_idx))),
+                }
+            }
+        }
+        lifetimes
+    }
+}
+
+/// Declares the accesses of a pass added by `KewRenderGraph::add_pass`, each resource once.
+pub struct KewPassBuilder<'r, 'g, 'a> {
+    graph: &'r mut KewRenderGraph<'g, 'a>,
+    name: &'static str,
+    images: Vec<(GraphImage, ImageAccess)>,
+    buffers: Vec<(GraphBuffer, BufferAccess)>,
+}
+
+impl<'g, 'a> KewPassBuilder<'_, 'g, 'a> {
+    pub fn image(mut self, image: GraphImage, access: ImageAccess) -> Self {
+        self.images.push((image, access));
+        self
+    }
+
+    pub fn buffer(mut self, buffer: GraphBuffer, access: BufferAccess) -> Self {
+        self.buffers.push((buffer, access));
+        self
+    }
+
+    /// Adds the pass, `record` runs when the graph is executed and its error ends the execution.
+    pub fn record(
+        self,
+        record: impl FnOnce(vk::CommandBuffer, &GraphResources<'g, 'a>) -> Result<(), KewError> + 'g,
+    ) {
+        self.graph.passes.push(GraphPass {
+            name: self.name,
+            images: self.images,
+            buffers: self.buffers,
+            record: Box::new(record),
+        });
+    }
+}
+
+/// Barriers recorded before a pass in a single pipeline barrier.
+#[derive(Default)]
+struct PassBarriers {
+    src_stages: vk::PipelineStageFlags,
+    dst_stages: vk::PipelineStageFlags,
+    images: Vec<vk::ImageMemoryBarrier<'static>>,
+    buffers: Vec<vk::BufferMemoryBarrier<'static>>,
+}
+
+impl PassBarriers {
+    fn image(&mut self, entry: &mut ImageEntry, state: AccessState) {
+        if let Some(barrier) = entry.tracked.transition(state) {
+            self.src_stages |= barrier.0;
+            self.dst_stages |= state.stages;
+            self.images.push(entry.barrier(state, barrier));
+        }
+    }
+
+    fn buffer(&mut self, entry: &mut BufferEntry, state: AccessState) {
+        if let Some((src_stages, src_access, _)) = entry.tracked.transition(state) {
+            self.src_stages |= src_stages;
+            self.dst_stages |= state.stages;
+            self.buffers.push(
+                vk::BufferMemoryBarrier::default()
+                    .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                    .buffer(**entry.buffer)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE)
+                    .src_access_mask(src_access)
+                    .dst_access_mask(state.access),
+            );
+        }
+    }
+
+    unsafe fn record(&self, kew_device: &KewDevice, cmd_buffer: vk::CommandBuffer) {
+        if self.images.is_empty() && self.buffers.is_empty() {
+            return;
+        }
+        let src_stages = match self.src_stages.is_empty() {
+            true => vk::PipelineStageFlags::TOP_OF_PIPE,
+            false => self.src_stages,
+        };
+        kew_device.cmd_pipeline_barrier(
+            cmd_buffer,
+            src_stages,
+            self.dst_stages,
+            vk::DependencyFlags::empty(),
+            &[],
+            &self.buffers,
+            &self.images,
+        );
+    }
+}
+
+/// Passes kept, walking back from the resources that are `needed` after the last pass. A pass
+/// is kept if it writes a needed resource, which makes every resource it uses needed before it.
+fn cull_passes(
+    passes: &[GraphPass],
+    mut needed_images: Vec<bool>,
+    mut needed_buffers: Vec<bool>,
+) -> Vec<bool> {
+    let mut kept = vec![false; passes.len()];
+    for (pass_idx, pass) in passes.iter().enumerate().rev() {
+        let writes_needed = pass
+            .images
+            .iter()
+            .any(|(image, access)| access.state().writes && needed_images[image.0])
+            || pass
+                .buffers
+                .iter()
+                .any(|(buffer, access)| access.state().writes && needed_buffers[buffer.0]);
+        if !writes_needed {
+            debug!("culled render graph pass {}", pass.name);
+            continue;
+        }
+        kept[pass_idx] = true;
+        for (image, _) in &pass.images {
+            needed_images[image.0] = true;
+        }
+        for (buffer, _) in &pass.buffers {
+            needed_buffers[buffer.0] = true;
+        }
+    }
+    kept
+}
+
+fn imported_state(layout: vk::ImageLayout, last_access: Option<AccessState>) -> Tracked {
+    match last_access {
+        Some(state) if state.writes => Tracked::new(layout, state.stages, state.access),
+        Some(state) => Tracked {
+            layout,
+            write_stages: vk::PipelineStageFlags::empty(),
+            write_access: vk::AccessFlags::empty(),
+            read_stages: state.stages,
+        },
+        None => Tracked::new(
+            layout,
+            vk::PipelineStageFlags::empty(),
+            vk::AccessFlags::empty(),
+        ),
+    }
+}
+
+/// Transient image requested by a graph: description, usage and first and last pass.
+type TransientRequest = (TransientImageInfo, vk::ImageUsageFlags, (usize, usize));
+
+/// Memory and images behind the transient images of a `KewRenderGraph`, reused while the graph
+/// requests the same transients. Images used by disjoint ranges of passes are placed in the same
+/// memory. A pool must not be executed again before the previous command buffer using it has
+/// completed, e.g. one pool per frame in flight.
+pub struct KewTransientPool<'a> {
+    kew_device: &'a KewDevice,
+    allocator: &'a KewAllocator<'a>,
+    requests: Vec<TransientRequest>,
+    /// Offset and size of each image in `memory`.
+    ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+    images: Vec<KewImage<'a>>,
+    memory: Option<KewAllocation<'a>>,
+}
+
+impl<'a> KewTransientPool<'a> {
+    pub fn new(kew_device: &'a KewDevice, allocator: &'a KewAllocator<'a>) -> Self {
+        Self {
+            kew_device,
+            allocator,
+            requests: Vec::new(),
+            ranges: Vec::new(),
+            images: Vec::new(),
+            memory: None,
+        }
+    }
+
+    fn prepare(&mut self, requests: &[TransientRequest]) -> Result<(), KewError> {
+        if self.requests == requests {
+            return Ok(());
+        }
+        self.requests.clear();
+        self.images.clear();
+        self.memory = None;
+
+        let mut images = Vec::with_capacity(requests.len());
+        for (info, usage, _) in requests {
+            images.push(KewImage::with_samples(
+                self.kew_device,
+                info.extent.width,
+                info.extent.height,
+                info.format,
+                info.extent.width as vk::DeviceSize * info.extent.height as vk::DeviceSize * 4,
+                *usage,
+                info.samples,
+            )?);
+        }
+        let requirements = images
+            .iter()
+            .map(KewImage::get_memory_requirements)
+            .collect::<Vec<_>>();
+        let lifetimes = requests
+            .iter()
+            .map(|(_, _, lifetime)| *lifetime)
+            .collect::<Vec<_>>();
+        let (offsets, b_size) = place_aliased(&requirements, &lifetimes);
+        let memory_reqs = vk::MemoryRequirements {
+            size: b_size,
+            alignment: requirements
+                .iter()
+                .map(|reqs| reqs.alignment)
+                .max()
+                .unwrap(),
+            memory_type_bits: requirements
+                .iter()
+                .fold(u32::MAX, |bits, reqs| bits & reqs.memory_type_bits),
+        };
+        if memory_reqs.memory_type_bits == 0 {
+            return Err(KewError::UNSUPPORTED(
+                "transient images without a common memory type",
+            ));
+        }
+        let memory = self.allocator.allocate(
+            &memory_reqs,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            KewResourceKind::OPTIMAL,
+        )?;
+        for (image, offset) in images.iter_mut().zip(&offsets) {
+            image.bind_aliased_memory(&memory, *offset)?;
+            image.recreate_image_view()?;
+        }
+        debug!(
+            "placed {} transient images in {} bytes ({} bytes unaliased)",
+            images.len(),
+            b_size,
+            requirements
+                .iter()
+                .map(|reqs| reqs.size)
+                .sum::<vk::DeviceSize>()
+        );
+
+        self.ranges = offsets
+            .into_iter()
+            .zip(&requirements)
+            .map(|(offset, reqs)| (offset, reqs.size))
+            .collect();
+        self.images = images;
+        self.memory = Some(memory);
+        self.requests = requests.to_vec();
+        Ok(())
+    }
+
+    /// Images used before `slot` whose memory `slot` reuses.
+    fn aliases(&self, slot: usize) -> impl Iterator<Item = usize> + '_ {
+        let (offset, b_size) = self.ranges[slot];
+        let first_pass = self.requests[slot].2 .0;
+        self.ranges
+            .iter()
+            .enumerate()
+            .filter(move |(other, (other_offset, other_b_size))| {
+                self.requests[*other].2 .1 < first_pass
+                    && *other_offset < offset + b_size
+                    && offset < other_offset + other_b_size
+            })
+            .map(|(other, _)| other)
+    }
+}
+
+/// Offsets of resources live from their first to their last pass and the memory they need in
+/// total. Resources whose passes do not overlap may share memory, larger ones are placed first.
+fn place_aliased(
+    requirements: &[vk::MemoryRequirements],
+    lifetimes: &[(usize, usize)],
+) -> (Vec<vk::DeviceSize>, vk::DeviceSize) {
+    let mut order = (0..requirements.len()).collect::<Vec<_>>();
+    order.sort_by_key(|idx| std::cmp::Reverse(requirements[*idx].size));
+
+    let mut offsets = vec![0; requirements.len()];
+    let mut placed: Vec<usize> = Vec::with_capacity(requirements.len());
+    for idx in order {
+        let reqs = requirements[idx];
+        let live = placed
+            .iter()
+            .copied()
+            .filter(|other| {
+                lifetimes[*other].0 <= lifetimes[idx].1 && lifetimes[idx].0 <= lifetimes[*other].1
+            })
+            .collect::<Vec<_>>();
+        // the lowest offset after a live resource that does not overlap any of them
+        let mut candidates = live
+            .iter()
+            .map(|other| offsets[*other] + requirements[*other].size)
+            .chain([0])
+            .map(|offset| align_up(offset, reqs.alignment))
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+        offsets[idx] = candidates
+            .into_iter()
+            .find(|offset| {
+                live.iter().all(|other| {
+                    offset + reqs.size <= offsets[*other]
+                        || offsets[*other] + requirements[*other].size <= *offset
+                })
+            })
+            .unwrap();
+        placed.push(idx);
+    }
+    let b_size = offsets
+        .iter()
+        .zip(requirements)
+        .map(|(offset, reqs)| offset + reqs.size)
+        .max()
+        .unwrap_or(0);
+    (offsets, b_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAGMENT: vk::PipelineStageFlags = vk::PipelineStageFlags::FRAGMENT_SHADER;
+    const COMPUTE: vk::PipelineStageFlags = vk::PipelineStageFlags::COMPUTE_SHADER;
+
+    fn reqs(size: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::MemoryRequirements {
+        vk::MemoryRequirements {
+            size,
+            alignment,
+            memory_type_bits: 1,
+        }
+    }
+
+    fn overlap(a: (usize, usize), b: (usize, usize)) -> bool {
+        a.0 <= b.1 && b.0 <= a.1
+    }
+
+    /// Resources of pseudo random sizes, alignments and lifetimes.
+    fn random_resources(count: usize) -> (Vec<vk::MemoryRequirements>, Vec<(usize, usize)>) {
+        let mut state = 0x2545_f491_u64;
+        let mut next = move |bound: u64| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1);
+            (state >> 33) % bound
+        };
+        (0..count)
+            .map(|_| {
+                let resource = reqs(1 + next(4096), 1 << next(9));
+                let first = next(8) as usize;
+                (resource, (first, first + next(4) as usize))
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn overlapping_lifetimes_never_share_memory() {
+        let (requirements, lifetimes) = random_resources(40);
+        let (offsets, b_size) = place_aliased(&requirements, &lifetimes);
+        for a in 0..requirements.len() {
+            assert!(offsets[a] + requirements[a].size <= b_size);
+            for b in a + 1..requirements.len() {
+                if overlap(lifetimes[a], lifetimes[b]) {
+                    assert!(
+                        offsets[a] + requirements[a].size <= offsets[b]
+                            || offsets[b] + requirements[b].size <= offsets[a],
+                        "resources {} and {} are live together and overlap",
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_respect_alignment() {
+        let (requirements, lifetimes) = random_resources(40);
+        let (offsets, _) = place_aliased(&requirements, &lifetimes);
+        for (offset, reqs) in offsets.iter().zip(&requirements) {
+            assert_eq!(offset % reqs.alignment, 0);
+        }
+
+        // the second resource would fit at 100 but has to start at 256
+        let requirements = [reqs(100, 4), reqs(50, 256)];
+        let (offsets, b_size) = place_aliased(&requirements, &[(0, 1), (1, 2)]);
+        assert_eq!(offsets, [0, 256]);
+        assert_eq!(b_size, 306);
+    }
+
+    #[test]
+    fn disjoint_lifetimes_share_memory() {
+        let requirements = [reqs(1024, 256), reqs(512, 256)];
+        let (offsets, b_size) = place_aliased(&requirements, &[(0, 1), (2, 3)]);
+        assert_eq!(offsets, [0, 0]);
+        assert_eq!(b_size, 1024);
+    }
+
+    #[test]
+    fn read_after_write_waits_for_the_write() {
+        let mut tracked = imported_state(vk::ImageLayout::UNDEFINED, None);
+        let (_, _, old_layout) = tracked
+            .transition(ImageAccess::STORAGE(COMPUTE).state())
+            .unwrap();
+        assert_eq!(old_layout, vk::ImageLayout::UNDEFINED);
+
+        let (src_stages, src_access, old_layout) = tracked
+            .transition(ImageAccess::SAMPLED(FRAGMENT).state())
+            .unwrap();
+        assert_eq!(src_stages, COMPUTE);
+        assert!(src_access.contains(vk::AccessFlags::SHADER_WRITE));
+        assert_eq!(old_layout, vk::ImageLayout::GENERAL);
+        assert_eq!(tracked.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+
+        let mut tracked = imported_state(vk::ImageLayout::UNDEFINED, None);
+        tracked.transition(BufferAccess::DESTINATION.state());
+        let (src_stages, src_access, _) = tracked.transition(BufferAccess::HOST.state()).unwrap();
+        assert_eq!(src_stages, vk::PipelineStageFlags::TRANSFER);
+        assert_eq!(src_access, vk::AccessFlags::TRANSFER_WRITE);
+    }
+
+    #[test]
+    fn read_after_read_in_the_same_stage_needs_no_barrier() {
+        let mut tracked = imported_state(
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            Some(ImageAccess::DESTINATION.state()),
+        );
+        assert!(tracked
+            .transition(ImageAccess::SAMPLED(FRAGMENT).state())
+            .is_some());
+        assert!(tracked
+            .transition(ImageAccess::SAMPLED(FRAGMENT).state())
+            .is_none());
+
+        let mut tracked = imported_state(
+            vk::ImageLayout::UNDEFINED,
+            Some(BufferAccess::DESTINATION.state()),
+        );
+        assert!(tracked.transition(BufferAccess::SOURCE.state()).is_some());
+        assert!(tracked.transition(BufferAccess::SOURCE.state()).is_none());
+    }
+
+    #[test]
+    fn write_after_read_waits_for_the_reads() {
+        let mut tracked = imported_state(
+            vk::ImageLayout::UNDEFINED,
+            Some(BufferAccess::SOURCE.state()),
+        );
+        let (src_stages, src_access, _) = tracked
+            .transition(BufferAccess::STORAGE(COMPUTE).state())
+            .unwrap();
+        assert_eq!(src_stages, vk::PipelineStageFlags::TRANSFER);
+        // reads only need an execution dependency
+        assert!(src_access.is_empty());
+    }
+
+    fn pass(
+        name: &'static str,
+        images: &[(usize, ImageAccess)],
+        buffers: &[(usize, BufferAccess)],
+    ) -> GraphPass<'static, 'static> {
+        GraphPass {
+            name,
+            images: images
+                .iter()
+                .map(|(image, access)| (GraphImage(*image), *access))
+                .collect(),
+            buffers: buffers
+                .iter()
+                .map(|(buffer, access)| (GraphBuffer(*buffer), *access))
+                .collect(),
+            record: Box::new(|_, _| Ok(())),
+        }
+    }
+
+    #[test]
+    fn passes_with_unused_writes_are_culled() {
+        // images 0 and 1 are transient, image 2 and buffer 0 are imported
+        let passes = [
+            pass("unused", &[(0, ImageAccess::COLOR)], &[]),
+            pass("only read by unused", &[(1, ImageAccess::COLOR)], &[]),
+            pass(
+                "unused reader",
+                &[(1, ImageAccess::SAMPLED(FRAGMENT)), (0, ImageAccess::COLOR)],
+                &[],
+            ),
+            pass("shade", &[(2, ImageAccess::COLOR)], &[]),
+            pass(
+                "readback",
+                &[(2, ImageAccess::SOURCE)],
+                &[(0, BufferAccess::DESTINATION)],
+            ),
+            pass("read only", &[(2, ImageAccess::SAMPLED(FRAGMENT))], &[]),
+        ];
+        let kept = cull_passes(&passes, vec![false, false, true], vec![true]);
+        assert_eq!(kept, [false, false, false, true, true, false]);
+    }
+
+    #[test]
+    fn passes_writing_read_transients_are_kept() {
+        let passes = [
+            pass("shadow", &[(0, ImageAccess::DEPTH)], &[]),
+            pass(
+                "shade",
+                &[(0, ImageAccess::SAMPLED(FRAGMENT)), (1, ImageAccess::COLOR)],
+                &[],
+            ),
+        ];
+        assert_eq!(
+            cull_passes(&passes, vec![false, true], vec![]),
+            [true, true]
+        );
+    }
+}
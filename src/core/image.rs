@@ -1,6 +1,8 @@
 use crate::core::buffer::KewBuffer;
-use crate::core::device::KewDevice;
-use crate::core::memory::{KewMemory, KewMemoryBinding};
+use crate::core::device::{distinct_families, KewDevice};
+use crate::core::error::KewError;
+use crate::core::memory::KewAllocation;
+use crate::core::sampler::KewSampler;
 use ash::vk;
 use log;
 use std::ops::Deref;
@@ -8,14 +10,19 @@ use log::{debug, warn};
 
 pub struct KewImage<'a> {
     kew_device: &'a KewDevice,
-    m_bind: Option<KewMemoryBinding<'a>>,
+    m_bind: Option<KewAllocation<'a>>,
     vk_image: vk::Image,
     format: vk::Format,
+    concurrent: bool,
     pub b_size: vk::DeviceSize,
     pub extent: vk::Extent3D,
     pub layout: vk::ImageLayout,
     pub subresource: vk::ImageSubresourceRange,
     pub view: Option<vk::ImageView>,
+    /// Queue family that owns the contents, `None` until the first `acquire_barrier`.
+    /// Exclusive images have to be released and acquired to move between families, releasing
+    /// one without owner is an error.
+    pub owner: Option<u32>,
 }
 
 impl<'a> KewImage<'a> {
@@ -26,37 +33,134 @@ impl<'a> KewImage<'a> {
         format: vk::Format,
         b_size: vk::DeviceSize,
         usage: vk::ImageUsageFlags,
-    ) -> Self {
+    ) -> Result<Self, KewError> {
+        Self::with_samples(
+            kew_device,
+            image_dx,
+            image_dy,
+            format,
+            b_size,
+            usage,
+            vk::SampleCountFlags::TYPE_1,
+        )
+    }
+
+    /// Same as `new` for multisampled attachments.
+    pub fn with_samples(
+        kew_device: &'a KewDevice,
+        image_dx: u32,
+        image_dy: u32,
+        format: vk::Format,
+        b_size: vk::DeviceSize,
+        usage: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self, KewError> {
+        Self::create(
+            kew_device,
+            image_dx,
+            image_dy,
+            format,
+            b_size,
+            usage,
+            samples,
+            1,
+            &[],
+        )
+    }
+
+    /// Same as `new` with `mip_levels` levels, see `mip_level_count` for a full chain.
+    pub fn with_mip_levels(
+        kew_device: &'a KewDevice,
+        image_dx: u32,
+        image_dy: u32,
+        format: vk::Format,
+        b_size: vk::DeviceSize,
+        usage: vk::ImageUsageFlags,
+        mip_levels: u32,
+    ) -> Result<Self, KewError> {
+        Self::create(
+            kew_device,
+            image_dx,
+            image_dy,
+            format,
+            b_size,
+            usage,
+            vk::SampleCountFlags::TYPE_1,
+            mip_levels,
+            &[],
+        )
+    }
+
+    /// Same as `new` for an image used concurrently by `families` (e.g.
+    /// `KewQueueIndices::families`), which never needs ownership transfers. Falls back to
+    /// exclusive sharing for less than two distinct families.
+    pub fn new_shared(
+        kew_device: &'a KewDevice,
+        image_dx: u32,
+        image_dy: u32,
+        format: vk::Format,
+        b_size: vk::DeviceSize,
+        usage: vk::ImageUsageFlags,
+        families: &[u32],
+    ) -> Result<Self, KewError> {
+        Self::create(
+            kew_device,
+            image_dx,
+            image_dy,
+            format,
+            b_size,
+            usage,
+            vk::SampleCountFlags::TYPE_1,
+            1,
+            families,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        kew_device: &'a KewDevice,
+        image_dx: u32,
+        image_dy: u32,
+        format: vk::Format,
+        b_size: vk::DeviceSize,
+        usage: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags,
+        mip_levels: u32,
+        families: &[u32],
+    ) -> Result<Self, KewError> {
         let extent = vk::Extent3D::default()
             .width(image_dx)
             .depth(1)
             .height(image_dy);
 
+        let families = distinct_families(families);
+        let concurrent = families.len() > 1;
         let create_info = vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
             .extent(extent)
-            .mip_levels(1)
+            .mip_levels(mip_levels)
             .array_layers(1)
             .format(format)
             .tiling(vk::ImageTiling::OPTIMAL)
             .initial_layout(vk::ImageLayout::UNDEFINED)
             .usage(usage)
-            .samples(vk::SampleCountFlags::TYPE_1)
-            .sharing_mode(vk::SharingMode::EXCLUSIVE);
-        let vk_image = unsafe {
-            kew_device
-                .create_image(&create_info, None)
-                .expect("failed to create image")
+            .samples(samples);
+        let create_info = match concurrent {
+            true => create_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&families),
+            false => create_info.sharing_mode(vk::SharingMode::EXCLUSIVE),
         };
+        let vk_image = unsafe { kew_device.create_image(&create_info, None)? };
 
         let subresource_range = vk::ImageSubresourceRange::default()
-            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .aspect_mask(aspect_flags(format))
             .base_mip_level(0)
-            .level_count(1)
+            .level_count(mip_levels)
             .base_array_layer(0)
             .layer_count(1);
 
-        Self {
+        Ok(Self {
             vk_image,
             kew_device,
             m_bind: None,
@@ -64,37 +168,57 @@ impl<'a> KewImage<'a> {
             extent,
             layout: vk::ImageLayout::UNDEFINED,
             format,
+            concurrent,
             subresource: subresource_range,
             view: None,
-        }
+            owner: None,
+        })
     }
 
-    pub fn recreate_image_view(&mut self) {
+    pub fn recreate_image_view(&mut self) -> Result<(), KewError> {
         let create_info = vk::ImageViewCreateInfo::default()
             .image(self.vk_image)
             .view_type(vk::ImageViewType::TYPE_2D)
             .format(self.format)
             .subresource_range(self.subresource);
-        self.view = unsafe {
-            Some(
-                self.kew_device
-                    .create_image_view(&create_info, None)
-                    .unwrap(),
-            )
-        };
+        if let Some(view) = self.view.take() {
+            unsafe { self.kew_device.destroy_image_view(view, None) };
+        }
+        self.view = unsafe { Some(self.kew_device.create_image_view(&create_info, None)?) };
+        Ok(())
     }
 
-    pub fn bind_memory(&mut self, memory: &'a KewMemory, offset: vk::DeviceSize) {
+    pub fn bind_memory(&mut self, allocation: KewAllocation<'a>) -> Result<(), KewError> {
         if self.m_bind.is_none() {
-            self.m_bind = Some(KewMemoryBinding { memory, offset });
             unsafe {
-                self.kew_device
-                    .bind_image_memory(self.vk_image, memory.memory, offset)
-                    .expect("failed to bind memory")
+                self.kew_device.bind_image_memory(
+                    self.vk_image,
+                    allocation.memory,
+                    allocation.offset,
+                )?
             };
+            self.m_bind = Some(allocation);
         } else {
             warn!("image already bound to memory (skipped)")
         }
+        Ok(())
+    }
+
+    /// Binds the image at `offset` into `allocation` without taking it, for images sharing
+    /// memory with others. The allocation has to outlive the image.
+    pub fn bind_aliased_memory(
+        &mut self,
+        allocation: &KewAllocation,
+        offset: vk::DeviceSize,
+    ) -> Result<(), KewError> {
+        unsafe {
+            self.kew_device.bind_image_memory(
+                self.vk_image,
+                allocation.memory,
+                allocation.offset + offset,
+            )?
+        };
+        Ok(())
     }
 
     pub fn get_memory_barrier(
@@ -114,6 +238,168 @@ impl<'a> KewImage<'a> {
             .dst_access_mask(dst_access_flags)
     }
 
+    /// Barrier releasing the image from its owner to `dst_family`, recorded on the owning queue
+    /// after `src_access`. The transition to `dst_layout` has to be repeated by the matching
+    /// `acquire_barrier`. `None` if no ownership transfer is needed, fails if the owner is
+    /// unknown.
+    pub fn release_barrier(
+        &self,
+        dst_family: u32,
+        dst_layout: vk::ImageLayout,
+        src_access: vk::AccessFlags,
+    ) -> Result<Option<vk::ImageMemoryBarrier<'static>>, KewError> {
+        if !self.concurrent && self.owner.is_none() {
+            return Err(KewError::QUEUE("owner"));
+        }
+        Ok(self.transfer_from(dst_family).map(|src_family| {
+            self.ownership_barrier(src_family, dst_family, dst_layout)
+                .src_access_mask(src_access)
+        }))
+    }
+
+    /// Barrier acquiring the image for `dst_family` in `dst_layout` before `dst_access`,
+    /// recorded on that family's queue after the submission holding the release has been waited
+    /// for. Without an ownership transfer this is a plain layout transition.
+    pub fn acquire_barrier(
+        &mut self,
+        dst_family: u32,
+        dst_layout: vk::ImageLayout,
+        src_access: vk::AccessFlags,
+        dst_access: vk::AccessFlags,
+    ) -> vk::ImageMemoryBarrier<'static> {
+        let barrier = match self.transfer_from(dst_family) {
+            Some(src_family) => self.ownership_barrier(src_family, dst_family, dst_layout),
+            None => self
+                .ownership_barrier(
+                    vk::QUEUE_FAMILY_IGNORED,
+                    vk::QUEUE_FAMILY_IGNORED,
+                    dst_layout,
+                )
+                .src_access_mask(src_access),
+        };
+        self.owner = Some(dst_family);
+        self.layout = dst_layout;
+        barrier.dst_access_mask(dst_access)
+    }
+
+    /// Owner the contents have to be transferred from for `dst_family` to use them.
+    fn transfer_from(&self, dst_family: u32) -> Option<u32> {
+        self.owner
+            .filter(|owner| !self.concurrent && *owner != dst_family)
+    }
+
+    fn ownership_barrier(
+        &self,
+        src_family: u32,
+        dst_family: u32,
+        dst_layout: vk::ImageLayout,
+    ) -> vk::ImageMemoryBarrier<'static> {
+        vk::ImageMemoryBarrier::default()
+            .old_layout(self.layout)
+            .new_layout(dst_layout)
+            .src_queue_family_index(src_family)
+            .dst_queue_family_index(dst_family)
+            .image(self.vk_image)
+            .subresource_range(self.subresource)
+    }
+
+    /// Fills mip levels 1.. by blitting each level from the one above. Expects level 0 to be
+    /// written and every level in `TRANSFER_DST_OPTIMAL`, leaves them `SHADER_READ_ONLY_OPTIMAL`.
+    /// The format has to support linear filtered blits.
+    pub unsafe fn generate_mipmaps(&mut self, cmd_buffer: vk::CommandBuffer) {
+        let level_barrier = |level: u32, old_layout, new_layout, src_access, dst_access| {
+            vk::ImageMemoryBarrier::default()
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.vk_image)
+                .subresource_range(self.subresource.base_mip_level(level).level_count(1))
+                .src_access_mask(src_access)
+                .dst_access_mask(dst_access)
+        };
+        let level_offset = |level: u32| {
+            vk::Offset3D::default()
+                .x((self.extent.width >> level).max(1) as i32)
+                .y((self.extent.height >> level).max(1) as i32)
+                .z(1)
+        };
+        let level_layers = |level: u32| {
+            vk::ImageSubresourceLayers::default()
+                .aspect_mask(self.subresource.aspect_mask)
+                .mip_level(level)
+                .base_array_layer(self.subresource.base_array_layer)
+                .layer_count(self.subresource.layer_count)
+        };
+
+        for level in 1..self.subresource.level_count {
+            let to_src = level_barrier(
+                level - 1,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::TRANSFER_READ,
+            );
+            self.kew_device.cmd_pipeline_barrier(
+                cmd_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_src],
+            );
+            let blit = vk::ImageBlit::default()
+                .src_subresource(level_layers(level - 1))
+                .src_offsets([vk::Offset3D::default(), level_offset(level - 1)])
+                .dst_subresource(level_layers(level))
+                .dst_offsets([vk::Offset3D::default(), level_offset(level)]);
+            self.kew_device.cmd_blit_image(
+                cmd_buffer,
+                self.vk_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.vk_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[blit],
+                vk::Filter::LINEAR,
+            );
+        }
+
+        // every level but the last has been a blit source
+        let last_level = self.subresource.level_count - 1;
+        let to_shader = (0..=last_level)
+            .map(|level| {
+                let (old_layout, src_access) = match level == last_level {
+                    true => (
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_WRITE,
+                    ),
+                    false => (
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::AccessFlags::TRANSFER_READ,
+                    ),
+                };
+                level_barrier(
+                    level,
+                    old_layout,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    src_access,
+                    vk::AccessFlags::SHADER_READ,
+                )
+            })
+            .collect::<Vec<_>>();
+        self.kew_device.cmd_pipeline_barrier(
+            cmd_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::FRAGMENT_SHADER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &to_shader,
+        );
+        self.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+    }
+
     pub fn copy_to_buffer(&self, buffer: &KewBuffer, cmd_buffer: vk::CommandBuffer) {
         let subresource_info = vk::ImageSubresourceLayers::default()
             .aspect_mask(self.subresource.aspect_mask)
@@ -148,22 +434,56 @@ impl<'a> KewImage<'a> {
         }
     }
 
-    // TODO: allow passing sampler for sharing between images
-    pub fn descriptor_info(&mut self) -> vk::DescriptorImageInfo {
-        let view = self.view.unwrap_or_else(|| {
-            warn!("view missing for descriptor info (recreated view)");
-            self.recreate_image_view();
-            self.view.unwrap()
-        });
-        vk::DescriptorImageInfo::default()
+    /// Storage images pass no sampler, sampled images the one they are read with.
+    pub fn descriptor_info(
+        &mut self,
+        sampler: Option<&KewSampler>,
+    ) -> Result<vk::DescriptorImageInfo, KewError> {
+        let view = match self.view {
+            Some(view) => view,
+            None => {
+                warn!("view missing for descriptor info (recreated view)");
+                self.recreate_image_view()?;
+                self.view.unwrap()
+            }
+        };
+        Ok(vk::DescriptorImageInfo::default()
             .image_layout(self.layout)
             .image_view(view)
-            .sampler(vk::Sampler::default())
+            .sampler(sampler.map_or(vk::Sampler::null(), |sampler| **sampler)))
     }
 
     pub fn get_memory_requirements(&self) -> vk::MemoryRequirements {
         unsafe { self.kew_device.get_image_memory_requirements(self.vk_image) }
     }
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.subresource.level_count
+    }
+}
+
+/// Number of levels in a full mip chain down to 1x1.
+pub fn mip_level_count(image_dx: u32, image_dy: u32) -> u32 {
+    u32::BITS - image_dx.max(image_dy).max(1).leading_zeros()
+}
+
+pub fn aspect_flags(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
 }
 
 impl Deref for KewImage<'_> {
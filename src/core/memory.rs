@@ -1,65 +1,330 @@
 use crate::core::device::KewDevice;
+use crate::core::error::KewError;
 use ash::vk;
 use log::{debug, warn};
 use std::ffi::c_void;
 use std::ptr;
 use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::Mutex;
 
-pub struct KewMemoryBinding<'a> {
-    pub memory: &'a KewMemory<'a>,
-    pub offset: vk::DeviceSize,
+const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// Tiling of the resource bound to an allocation. Linear and optimal resources are kept in
+/// separate blocks whenever the device reports a `bufferImageGranularity` above one byte.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KewResourceKind {
+    LINEAR,
+    OPTIMAL,
 }
 
-pub struct KewMemory<'a> {
-    pub kew_device: &'a KewDevice,
-    pub memory: vk::DeviceMemory,
-    pub b_size: vk::DeviceSize,
-    pub m_type: u32,
+#[derive(Debug)]
+pub struct KewHeapStats {
+    pub heap_idx: u32,
+    pub heap_size: vk::DeviceSize,
+    pub block_count: usize,
+    pub block_b_size: vk::DeviceSize,
+    pub used_b_size: vk::DeviceSize,
+    pub allocation_count: usize,
+}
+
+struct KewMemoryBlock {
+    id: u64,
+    memory: vk::DeviceMemory,
+    m_type: u32,
+    kind: Option<KewResourceKind>,
+    b_size: vk::DeviceSize,
     mapped: AtomicPtr<c_void>,
+    dedicated: bool,
+    free_ranges: Vec<(vk::DeviceSize, vk::DeviceSize)>,
+    allocation_count: usize,
 }
 
-impl<'a> KewMemory<'a> {
-    pub fn new(
-        kew_device: &'a KewDevice,
-        b_size: u64,
-        memory_type: u32,
-    ) -> Self {
-        let info = vk::MemoryAllocateInfo::default()
-            .allocation_size(b_size)
-            .memory_type_index(memory_type);
-        let memory = unsafe { kew_device.allocate_memory(&info, None).unwrap() };
+impl KewMemoryBlock {
+    fn suballocate(
+        &mut self,
+        b_size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let (idx, offset) = self
+            .free_ranges
+            .iter()
+            .enumerate()
+            .find_map(|(idx, range)| {
+                let offset = align_up(range.0, alignment);
+                (offset + b_size <= range.0 + range.1).then_some((idx, offset))
+            })?;
+
+        let (range_offset, range_size) = self.free_ranges.remove(idx);
+        let tail_offset = offset + b_size;
+        let tail_size = range_offset + range_size - tail_offset;
+        if tail_size > 0 {
+            self.free_ranges.insert(idx, (tail_offset, tail_size));
+        }
+        if offset > range_offset {
+            self.free_ranges
+                .insert(idx, (range_offset, offset - range_offset));
+        }
+        self.allocation_count += 1;
+        Some(offset)
+    }
+
+    fn release(&mut self, offset: vk::DeviceSize, b_size: vk::DeviceSize) {
+        let idx = self.free_ranges.partition_point(|range| range.0 < offset);
+        self.free_ranges.insert(idx, (offset, b_size));
+
+        // coalesce with the following and preceding free ranges
+        if idx + 1 < self.free_ranges.len() {
+            let next = self.free_ranges[idx + 1];
+            if offset + b_size == next.0 {
+                self.free_ranges[idx].1 += next.1;
+                self.free_ranges.remove(idx + 1);
+            }
+        }
+        if idx > 0 {
+            let prev = self.free_ranges[idx - 1];
+            if prev.0 + prev.1 == offset {
+                self.free_ranges[idx - 1].1 += self.free_ranges[idx].1;
+                self.free_ranges.remove(idx);
+            }
+        }
+        self.allocation_count -= 1;
+    }
+
+    fn used_b_size(&self) -> vk::DeviceSize {
+        self.b_size
+            - self
+                .free_ranges
+                .iter()
+                .map(|range| range.1)
+                .sum::<vk::DeviceSize>()
+    }
+}
+
+/// Sub-allocates resources from large per memory type blocks. Blocks emptied by frees are kept
+/// as a pool for later allocations until the allocator is dropped, only dedicated blocks of
+/// single oversized resources are freed right away.
+pub struct KewAllocator<'a> {
+    kew_device: &'a KewDevice,
+    block_b_size: vk::DeviceSize,
+    granularity: vk::DeviceSize,
+    blocks: Mutex<(u64, Vec<KewMemoryBlock>)>,
+}
+
+impl<'a> KewAllocator<'a> {
+    pub fn new(kew_device: &'a KewDevice) -> Self {
+        Self::with_block_size(kew_device, DEFAULT_BLOCK_SIZE)
+    }
+
+    pub fn with_block_size(kew_device: &'a KewDevice, block_b_size: vk::DeviceSize) -> Self {
+        let granularity = unsafe {
+            kew_device
+                .context
+                .instance
+                .get_physical_device_properties(kew_device.context.physical)
+                .limits
+                .buffer_image_granularity
+        };
+        debug!("allocator buffer image granularity: {}", granularity);
         Self {
             kew_device,
+            block_b_size,
+            granularity,
+            blocks: Mutex::new((0, Vec::new())),
+        }
+    }
+
+    pub fn allocate(
+        &'a self,
+        memory_reqs: &vk::MemoryRequirements,
+        memory_flags: vk::MemoryPropertyFlags,
+        kind: KewResourceKind,
+    ) -> Result<KewAllocation<'a>, KewError> {
+        let m_type = self
+            .kew_device
+            .find_memory_type(memory_reqs, memory_flags)?;
+        let kind = match self.granularity > 1 {
+            true => Some(kind),
+            false => None,
+        };
+
+        let mut guard = self.blocks.lock().unwrap();
+        let (next_id, blocks) = &mut *guard;
+        let reuse = blocks
+            .iter_mut()
+            .filter(|block| block.m_type == m_type && block.kind == kind && !block.dedicated)
+            .find_map(|block| {
+                block
+                    .suballocate(memory_reqs.size, memory_reqs.alignment)
+                    .map(|offset| (block.id, offset))
+            });
+        let (block_id, offset) = match reuse {
+            Some(found) => found,
+            None => {
+                let dedicated = memory_reqs.size > self.block_b_size;
+                let b_size = match dedicated {
+                    true => memory_reqs.size,
+                    false => self.block_b_size,
+                };
+                let mut block = self.allocate_block(*next_id, m_type, kind, b_size, dedicated)?;
+                *next_id += 1;
+                let offset = block
+                    .suballocate(memory_reqs.size, memory_reqs.alignment)
+                    .unwrap();
+                let id = block.id;
+                blocks.push(block);
+                (id, offset)
+            }
+        };
+
+        let block = blocks.iter().find(|block| block.id == block_id).unwrap();
+        let block_mapped = block.mapped.load(Ordering::SeqCst);
+        let mapped = match block_mapped.is_null() {
+            true => ptr::null_mut(),
+            false => unsafe { (block_mapped as *mut u8).add(offset as usize) as *mut c_void },
+        };
+        Ok(KewAllocation {
+            allocator: self,
+            block_id,
+            memory: block.memory,
+            offset,
+            b_size: memory_reqs.size,
+            m_type,
+            mapped: AtomicPtr::new(mapped),
+        })
+    }
+
+    fn allocate_block(
+        &self,
+        id: u64,
+        m_type: u32,
+        kind: Option<KewResourceKind>,
+        b_size: vk::DeviceSize,
+        dedicated: bool,
+    ) -> Result<KewMemoryBlock, KewError> {
+        let info = vk::MemoryAllocateInfo::default()
+            .allocation_size(b_size)
+            .memory_type_index(m_type);
+        let memory = unsafe { self.kew_device.allocate_memory(&info, None)? };
+
+        let property_flags =
+            self.kew_device.context.mem_properties.memory_types[m_type as usize].property_flags;
+        let mapped = match property_flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            true => unsafe {
+                match self.kew_device.map_memory(
+                    memory,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                ) {
+                    Ok(mapped) => mapped,
+                    Err(e) => {
+                        self.kew_device.free_memory(memory, None);
+                        return Err(e.into());
+                    }
+                }
+            },
+            false => ptr::null_mut(),
+        };
+        debug!(
+            "allocated memory block {} (type: {}, size: {}, dedicated: {})",
+            id, m_type, b_size, dedicated
+        );
+        Ok(KewMemoryBlock {
+            id,
             memory,
-            mapped: AtomicPtr::new(ptr::null_mut()),
-            m_type: memory_type,
+            m_type,
+            kind,
             b_size,
+            mapped: AtomicPtr::new(mapped),
+            dedicated,
+            free_ranges: vec![(0, b_size)],
+            allocation_count: 0,
+        })
+    }
+
+    fn free(&self, block_id: u64, offset: vk::DeviceSize, b_size: vk::DeviceSize) {
+        let mut guard = self.blocks.lock().unwrap();
+        let blocks = &mut guard.1;
+        let idx = blocks
+            .iter()
+            .position(|block| block.id == block_id)
+            .expect("freed allocation from unknown memory block");
+        blocks[idx].release(offset, b_size);
+        if blocks[idx].dedicated && blocks[idx].allocation_count == 0 {
+            let block = blocks.remove(idx);
+            self.free_block(block);
         }
     }
 
-    pub unsafe fn map(&self, b_size: vk::DeviceSize, offset: vk::DeviceSize) {
-        if self.mapped.load(Ordering::SeqCst).is_null() {
-            let mapped = self
-                .kew_device
-                .map_memory(self.memory, offset, b_size, vk::MemoryMapFlags::empty())
-                .expect("failed to map memory");
-            self.mapped.store(mapped, Ordering::SeqCst);
-        } else {
-            warn!("map call on mapped memory (skipped call)")
+    fn free_block(&self, block: KewMemoryBlock) {
+        debug!("freeing memory block {}", block.id);
+        unsafe {
+            if !block.mapped.load(Ordering::SeqCst).is_null() {
+                self.kew_device.unmap_memory(block.memory);
+            }
+            self.kew_device.free_memory(block.memory, None);
+        }
+    }
+
+    pub fn stats(&self) -> Vec<KewHeapStats> {
+        let mem_properties = &self.kew_device.context.mem_properties;
+        let mut stats = mem_properties.memory_heaps[..mem_properties.memory_heap_count as usize]
+            .iter()
+            .enumerate()
+            .map(|(idx, heap)| KewHeapStats {
+                heap_idx: idx as u32,
+                heap_size: heap.size,
+                block_count: 0,
+                block_b_size: 0,
+                used_b_size: 0,
+                allocation_count: 0,
+            })
+            .collect::<Vec<_>>();
+
+        let guard = self.blocks.lock().unwrap();
+        for block in guard.1.iter() {
+            let heap_idx = mem_properties.memory_types[block.m_type as usize].heap_index;
+            let heap_stats = &mut stats[heap_idx as usize];
+            heap_stats.block_count += 1;
+            heap_stats.block_b_size += block.b_size;
+            heap_stats.used_b_size += block.used_b_size();
+            heap_stats.allocation_count += block.allocation_count;
         }
+        stats
     }
+}
 
-    #[allow(dead_code)]
-    pub fn unmap(&self) {
-        if !self.mapped.load(Ordering::SeqCst).is_null() {
-            unsafe {
-                self.kew_device.unmap_memory(self.memory);
+impl Drop for KewAllocator<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewAllocator");
+        let blocks = std::mem::take(&mut self.blocks.get_mut().unwrap().1);
+        for block in blocks {
+            if block.allocation_count > 0 {
+                warn!(
+                    "memory block {} freed with {} live allocation(s)",
+                    block.id, block.allocation_count
+                );
             }
-            self.mapped.store(ptr::null_mut(), Ordering::SeqCst);
-        } else {
-            warn!("unmap call on unmapped memory (skipped call)")
+            self.free_block(block);
         }
     }
+}
+
+/// Sub-allocation returned by [`KewAllocator::allocate`], released back to its block on drop.
+pub struct KewAllocation<'a> {
+    allocator: &'a KewAllocator<'a>,
+    block_id: u64,
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub b_size: vk::DeviceSize,
+    pub m_type: u32,
+    mapped: AtomicPtr<c_void>,
+}
+
+impl KewAllocation<'_> {
+    pub fn is_mapped(&self) -> bool {
+        !self.mapped.load(Ordering::SeqCst).is_null()
+    }
 
     pub unsafe fn rd_visible_mem<T: Copy>(
         &self,
@@ -68,7 +333,10 @@ impl<'a> KewMemory<'a> {
         offset: vk::DeviceSize,
     ) {
         let mapped = self.mapped.load(Ordering::SeqCst);
-        assert!(!mapped.is_null());
+        assert!(
+            !mapped.is_null(),
+            "attempted rd_visible_mem on unmapped memory"
+        );
         assert!(
             b_size + offset <= self.b_size,
             "rd_visible_mem out of bounds: mem_size: {}, data_size: {}, data_offset: {}",
@@ -109,11 +377,122 @@ impl<'a> KewMemory<'a> {
     }
 }
 
-impl Drop for KewMemory<'_> {
+impl Drop for KewAllocation<'_> {
     fn drop(&mut self) {
-        debug!("dropping KewMemory");
-        unsafe {
-            self.kew_device.free_memory(self.memory, None);
+        self.allocator.free(self.block_id, self.offset, self.b_size);
+    }
+}
+
+pub fn align_up(offset: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    match alignment {
+        0 | 1 => offset,
+        _ => (offset + alignment - 1) & !(alignment - 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(b_size: vk::DeviceSize) -> KewMemoryBlock {
+        KewMemoryBlock {
+            id: 0,
+            memory: vk::DeviceMemory::null(),
+            m_type: 0,
+            kind: None,
+            b_size,
+            mapped: AtomicPtr::new(ptr::null_mut()),
+            dedicated: false,
+            free_ranges: vec![(0, b_size)],
+            allocation_count: 0,
+        }
+    }
+
+    #[test]
+    fn alignment_padding_stays_free() {
+        let mut block = block(1024);
+        assert_eq!(block.suballocate(10, 1), Some(0));
+        assert_eq!(block.suballocate(100, 256), Some(256));
+        assert_eq!(block.free_ranges, vec![(10, 246), (356, 668)]);
+        // the padding head is handed out to allocations that fit
+        assert_eq!(block.suballocate(200, 2), Some(10));
+        assert_eq!(block.free_ranges, vec![(210, 46), (356, 668)]);
+    }
+
+    #[test]
+    fn release_coalesces_with_previous_range() {
+        let mut block = block(300);
+        let first = block.suballocate(100, 1).unwrap();
+        let second = block.suballocate(100, 1).unwrap();
+        block.suballocate(100, 1).unwrap();
+        block.release(first, 100);
+        block.release(second, 100);
+        assert_eq!(block.free_ranges, vec![(0, 200)]);
+    }
+
+    #[test]
+    fn release_coalesces_with_next_range() {
+        let mut block = block(300);
+        block.suballocate(100, 1).unwrap();
+        let second = block.suballocate(100, 1).unwrap();
+        let third = block.suballocate(100, 1).unwrap();
+        block.release(third, 100);
+        block.release(second, 100);
+        assert_eq!(block.free_ranges, vec![(100, 200)]);
+    }
+
+    #[test]
+    fn release_coalesces_with_both_ranges() {
+        let mut block = block(400);
+        let first = block.suballocate(100, 1).unwrap();
+        let second = block.suballocate(100, 1).unwrap();
+        let third = block.suballocate(100, 1).unwrap();
+        block.release(first, 100);
+        block.release(third, 100);
+        assert_eq!(block.free_ranges, vec![(0, 100), (200, 200)]);
+        block.release(second, 100);
+        assert_eq!(block.free_ranges, vec![(0, 400)]);
+    }
+
+    #[test]
+    fn freed_range_is_reused() {
+        let mut block = block(300);
+        block.suballocate(100, 1).unwrap();
+        let second = block.suballocate(100, 1).unwrap();
+        block.suballocate(100, 1).unwrap();
+        assert_eq!(block.suballocate(1, 1), None);
+        block.release(second, 100);
+        assert_eq!(block.suballocate(64, 64), Some(128));
+        assert_eq!(block.free_ranges, vec![(100, 28), (192, 8)]);
+    }
+
+    #[test]
+    fn used_size_matches_live_allocations() {
+        let mut block = block(4096);
+        let mut live: Vec<(vk::DeviceSize, vk::DeviceSize)> = Vec::new();
+        for _ in 0..4 {
+            for b_size in [100, 300, 50] {
+                live.push((block.suballocate(b_size, 16).unwrap(), b_size));
+            }
+            // free every other allocation, newest first
+            let mut idx = live.len();
+            while idx >= 2 {
+                idx -= 2;
+                let (offset, b_size) = live.remove(idx);
+                block.release(offset, b_size);
+            }
+            assert_eq!(
+                block.used_b_size(),
+                live.iter()
+                    .map(|(_, b_size)| b_size)
+                    .sum::<vk::DeviceSize>()
+            );
+            assert_eq!(block.allocation_count, live.len());
+        }
+        for (offset, b_size) in live {
+            block.release(offset, b_size);
         }
+        assert_eq!(block.used_b_size(), 0);
+        assert_eq!(block.free_ranges, vec![(0, 4096)]);
     }
 }
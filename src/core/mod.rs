@@ -1,18 +1,40 @@
 use ash::vk;
 
+pub mod bindless;
 pub mod buffer;
+pub mod cache;
 pub mod command;
+pub mod compiler;
+pub mod compute;
 pub mod context;
 pub mod descriptor;
 pub mod device;
+pub mod error;
+pub mod graph;
 pub mod image;
 pub mod memory;
 pub mod model;
+pub mod obj;
 pub mod pipeline;
+pub mod queue;
+pub mod reflect;
+pub mod rendering;
+pub mod sampler;
+pub mod scene;
 pub mod shader;
 pub mod surface;
 pub mod swapchain;
+pub mod sync;
+pub mod target;
+pub mod texture;
+pub mod transfer;
 
 const ENABLE_VALIDATION_LAYERS: bool = cfg!(debug_assertions);
 const PREFERRED_SURFACE_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
 const PREFERRED_SURFACE_COLORS: vk::ColorSpaceKHR = vk::ColorSpaceKHR::SRGB_NONLINEAR;
+const PIPELINE_CACHE_PATH: &str = "./shader/cache/pipeline.bin";
+const PREFERRED_DEPTH_FORMATS: [vk::Format; 3] = [
+    vk::Format::D32_SFLOAT_S8_UINT,
+    vk::Format::D24_UNORM_S8_UINT,
+    vk::Format::D32_SFLOAT,
+];
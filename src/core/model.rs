@@ -47,17 +47,6 @@ impl<T: KewVertex + Clone + Copy> KewModelVertexData<T> {
     pub fn index_data_size(&self) -> u64 {
         self.indices.len() as u64 * 4
     }
-
-    pub unsafe fn write_to_memory(
-        &self,
-        vrt_buffer: &KewBuffer,
-        idx_buffer: &KewBuffer,
-        vrt_offset: u64,
-        idx_offset: u64,
-    ) {
-        vrt_buffer.wr_visible_mem(&self.vertices, self.vertex_data_size(), vrt_offset);
-        idx_buffer.wr_visible_mem(&self.indices, self.index_data_size(), idx_offset);
-    }
 }
 
 impl KewModelVertexData<FlatVertex> {
@@ -75,9 +64,27 @@ impl KewModelVertexData<FlatVertex> {
     }
 }
 
+/// Smooth per position normals, averaged over the faces around each position.
+pub fn generate_normals(positions: &[[f32; 3]], triangles: &[[usize; 3]]) -> Vec<[f32; 3]> {
+    let mut normals = vec![Vector::<f32, 3>::default(); positions.len()];
+    for corners in triangles {
+        let [a, b, c] = corners.map(|corner| Vector::from(positions[corner]));
+        // area weighted, the cross product length is twice the triangle area
+        let face_normal = (b - a).cross(&(c - a));
+        for corner in corners {
+            normals[*corner] += face_normal;
+        }
+    }
+    normals
+        .into_iter()
+        .map(|normal| normal.normalize().into())
+        .collect()
+}
+
 pub enum VertexType {
     NULL,
     FLAT,
+    MESH,
 }
 
 impl VertexType {
@@ -85,6 +92,7 @@ impl VertexType {
         match self {
             VertexType::NULL => None,
             VertexType::FLAT => Some(FlatVertex::bind_descriptions()),
+            VertexType::MESH => Some(Vertex::bind_descriptions()),
         }
     }
 
@@ -94,6 +102,7 @@ impl VertexType {
         match self {
             VertexType::NULL => None,
             VertexType::FLAT => Some(FlatVertex::attr_descriptions()),
+            VertexType::MESH => Some(Vertex::attr_descriptions()),
         }
     }
 }
@@ -146,6 +155,8 @@ impl FlatVertex {
 
 impl KewVertex for FlatVertex {}
 
+#[derive(Clone, Copy)]
+#[repr(C)]
 pub struct Vertex {
     pub position: Vector<f32, 3>,
     pub normal: Vector<f32, 3>,
@@ -154,18 +165,17 @@ pub struct Vertex {
 }
 
 impl Vertex {
-    pub fn bind_descriptions() -> Vec<vk::VertexInputBindingDescription> {
-        let vertex_size = size_of::<Vertex>() as u32;
-
-        vec![vk::VertexInputBindingDescription::default()
-            .binding(0)
-            .stride(vertex_size)
-            .input_rate(vk::VertexInputRate::VERTEX)]
+    const fn bind_descriptions() -> &'static [vk::VertexInputBindingDescription] {
+        &[vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: size_of::<Self>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        }]
     }
 
-    pub fn attr_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
-        let vector3_size = size_of::<Vector<f32, 3>>() as u32;
-        vec![
+    const fn attr_descriptions() -> &'static [vk::VertexInputAttributeDescription] {
+        const VECTOR3_SIZE: u32 = size_of::<Vector<f32, 3>>() as u32;
+        &[
             vk::VertexInputAttributeDescription {
                 location: 0,
                 binding: 0,
@@ -176,20 +186,22 @@ impl Vertex {
                 location: 1,
                 binding: 0,
                 format: vk::Format::R32G32B32_SFLOAT,
-                offset: vector3_size,
+                offset: VECTOR3_SIZE,
             },
             vk::VertexInputAttributeDescription {
                 location: 2,
                 binding: 0,
                 format: vk::Format::R32G32B32_SFLOAT,
-                offset: 2 * vector3_size,
+                offset: 2 * VECTOR3_SIZE,
             },
             vk::VertexInputAttributeDescription {
                 location: 3,
                 binding: 0,
                 format: vk::Format::R32G32_SFLOAT,
-                offset: 3 * vector3_size,
+                offset: 3 * VECTOR3_SIZE,
             },
         ]
     }
 }
+
+impl KewVertex for Vertex {}
@@ -0,0 +1,342 @@
+use crate::core::error::KewError;
+use crate::core::model::{generate_normals, KewModelVertexData, Vertex};
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+/// Subset of an MTL material that maps onto `Vertex`.
+#[derive(Clone, Debug)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub diffuse: [f32; 3],
+    pub diffuse_texture: Option<PathBuf>,
+}
+
+/// Position, texture coordinate and normal indices of one face corner (zero based).
+type ObjCorner = (usize, Option<usize>, Option<usize>);
+
+impl KewModelVertexData<Vertex> {
+    /// Loads a Wavefront OBJ file and the materials of its `mtllib`s. Polygons are fan
+    /// triangulated, identical corners share an index and missing normals are generated by
+    /// averaging the normals of the faces around each position.
+    pub fn from_obj(path: &Path) -> Result<(Self, Vec<ObjMaterial>), KewError> {
+        let source = fs::read_to_string(path)?;
+        let parse_err = |line: usize, msg: &str| {
+            KewError::ASSET(format!("{}:{}: {}", path.display(), line + 1, msg))
+        };
+
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut colors: Vec<Option<[f32; 3]>> = Vec::new();
+        let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut materials: Vec<ObjMaterial> = Vec::new();
+        let mut material_color = DEFAULT_COLOR;
+        let mut triangles: Vec<([ObjCorner; 3], [f32; 3])> = Vec::new();
+
+        for (line_idx, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+            let values = tokens.collect::<Vec<_>>();
+            match keyword {
+                "v" => {
+                    let floats = parse_floats(&values)
+                        .ok_or_else(|| parse_err(line_idx, "invalid vertex position"))?;
+                    match floats.len() {
+                        // xyz followed by the common rgb vertex color extension
+                        6 => colors.push(Some([floats[3], floats[4], floats[5]])),
+                        3 | 4 => colors.push(None),
+                        _ => return Err(parse_err(line_idx, "invalid vertex position")),
+                    }
+                    positions.push([floats[0], floats[1], floats[2]]);
+                }
+                "vt" => {
+                    let floats = parse_floats(&values)
+                        .filter(|floats| !floats.is_empty())
+                        .ok_or_else(|| parse_err(line_idx, "invalid texture coordinate"))?;
+                    // obj puts the texture origin at the bottom left
+                    tex_coords.push([floats[0], 1.0 - floats.get(1).copied().unwrap_or(0.0)]);
+                }
+                "vn" => {
+                    let floats = parse_floats(&values)
+                        .filter(|floats| floats.len() == 3)
+                        .ok_or_else(|| parse_err(line_idx, "invalid vertex normal"))?;
+                    normals.push([floats[0], floats[1], floats[2]]);
+                }
+                "f" => {
+                    if values.len() < 3 {
+                        return Err(parse_err(line_idx, "face with less than three vertices"));
+                    }
+                    let corners = values
+                        .iter()
+                        .map(|corner| {
+                            parse_corner(corner, positions.len(), tex_coords.len(), normals.len())
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| parse_err(line_idx, "invalid face index"))?;
+                    for idx in 1..corners.len() - 1 {
+                        triangles
+                            .push(([corners[0], corners[idx], corners[idx + 1]], material_color));
+                    }
+                }
+                "mtllib" => {
+                    let directory = path.parent().unwrap_or(Path::new("."));
+                    for library in values {
+                        materials.extend(parse_mtl(&directory.join(library))?);
+                    }
+                }
+                "usemtl" => {
+                    material_color = match materials
+                        .iter()
+                        .find(|material| Some(&material.name.as_str()) == values.first())
+                    {
+                        Some(material) => material.diffuse,
+                        None => {
+                            warn!("{}: unknown material {:?}", path.display(), values.first());
+                            DEFAULT_COLOR
+                        }
+                    };
+                }
+                "o" | "g" | "s" => (),
+                _ => debug!("{}: skipped obj statement {}", path.display(), keyword),
+            }
+        }
+
+        let smooth_normals = generate_normals(
+            &positions,
+            &triangles
+                .iter()
+                .map(|(corners, _)| corners.map(|corner| corner.0))
+                .collect::<Vec<_>>(),
+        );
+        let mut vertices = Vec::new();
+        let mut indices = Vec::with_capacity(triangles.len() * 3);
+        let mut unique: HashMap<(ObjCorner, [u32; 3]), u32> = HashMap::new();
+        for (corners, color) in &triangles {
+            for corner in corners {
+                let (position, tex_coord, normal) = *corner;
+                let color = colors[position].unwrap_or(*color);
+                let key = (*corner, color.map(f32::to_bits));
+                let idx = *unique.entry(key).or_insert_with(|| {
+                    vertices.push(Vertex {
+                        position: positions[position].into(),
+                        normal: normal
+                            .map(|normal| normals[normal])
+                            .unwrap_or(smooth_normals[position])
+                            .into(),
+                        color: color.into(),
+                        texture: tex_coord
+                            .map(|tex_coord| tex_coords[tex_coord])
+                            .unwrap_or([0.0, 0.0])
+                            .into(),
+                    });
+                    vertices.len() as u32 - 1
+                });
+                indices.push(idx);
+            }
+        }
+        debug!(
+            "loaded {} ({} vertices, {} indices, {} materials)",
+            path.display(),
+            vertices.len(),
+            indices.len(),
+            materials.len()
+        );
+        Ok((Self { vertices, indices }, materials))
+    }
+}
+
+fn parse_floats(values: &[&str]) -> Option<Vec<f32>> {
+    values.iter().map(|value| value.parse().ok()).collect()
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`, resolving negative (relative) indices.
+fn parse_corner(
+    corner: &str,
+    position_count: usize,
+    tex_coord_count: usize,
+    normal_count: usize,
+) -> Option<ObjCorner> {
+    let resolve = |value: Option<&str>, count: usize| -> Option<Option<usize>> {
+        match value {
+            None | Some("") => Some(None),
+            Some(value) => {
+                let idx = value.parse::<i64>().ok()?;
+                let idx = match idx {
+                    0 => return None,
+                    idx if idx < 0 => count as i64 + idx,
+                    idx => idx - 1,
+                };
+                (0..count as i64)
+                    .contains(&idx)
+                    .then_some(Some(idx as usize))
+            }
+        }
+    };
+    let mut parts = corner.split('/');
+    let position = resolve(parts.next(), position_count)??;
+    let tex_coord = resolve(parts.next(), tex_coord_count)?;
+    let normal = resolve(parts.next(), normal_count)?;
+    Some((position, tex_coord, normal))
+}
+
+fn parse_mtl(path: &Path) -> Result<Vec<ObjMaterial>, KewError> {
+    let source = fs::read_to_string(path)?;
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        let values = tokens.collect::<Vec<_>>();
+        if keyword == "newmtl" {
+            materials.push(ObjMaterial {
+                name: values.join(" "),
+                diffuse: DEFAULT_COLOR,
+                diffuse_texture: None,
+            });
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => continue,
+        };
+        match keyword {
+            "Kd" => {
+                material.diffuse = parse_floats(&values)
+                    .and_then(|floats| floats.try_into().ok())
+                    .ok_or_else(|| {
+                        KewError::ASSET(format!(
+                            "{}:{}: invalid diffuse color",
+                            path.display(),
+                            line_idx + 1
+                        ))
+                    })?;
+            }
+            "map_Kd" => {
+                // options come before the file name
+                material.diffuse_texture = values
+                    .last()
+                    .map(|file| path.parent().unwrap_or(Path::new(".")).join(file));
+            }
+            _ => (),
+        }
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::vector::Vector;
+    use std::env;
+    use std::process;
+
+    /// Writes `source` to a file in the temp directory, unique per test process.
+    fn write_temp(name: &str, source: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("kew-obj-{}-{}", process::id(), name));
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    fn load(name: &str, source: &str) -> Result<KewModelVertexData<Vertex>, KewError> {
+        let path = write_temp(name, source);
+        let result = KewModelVertexData::from_obj(&path).map(|(data, _)| data);
+        fs::remove_file(path).unwrap();
+        result
+    }
+
+    #[test]
+    fn triangulates_polygons_as_fans() {
+        let data = load(
+            "quad.obj",
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n",
+        )
+        .unwrap();
+        assert_eq!(data.vertices.len(), 4);
+        assert_eq!(data.indices, [0, 1, 2, 0, 2, 3]);
+        for vertex in &data.vertices {
+            assert_eq!(vertex.normal, Vector::new([0.0, 0.0, 1.0]));
+        }
+    }
+
+    #[test]
+    fn resolves_relative_indices_and_flips_tex_coords() {
+        let data = load(
+            "relative.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0.25 0.25\nvn 0 1 0\nf -3/-1/1 -2/1/-1 -1//1\n",
+        )
+        .unwrap();
+        assert_eq!(data.indices, [0, 1, 2]);
+        assert_eq!(data.vertices[0].texture, Vector::new([0.25, 0.75]));
+        assert_eq!(data.vertices[2].texture, Vector::new([0.0, 0.0]));
+        assert!(data
+            .vertices
+            .iter()
+            .all(|vertex| vertex.normal == Vector::new([0.0, 1.0, 0.0])));
+    }
+
+    #[test]
+    fn colors_vertices_from_materials() {
+        let mtl = write_temp("colors.mtl", "newmtl red\nKd 1 0 0\nmap_Kd -bm 1 red.png\n");
+        let mtl_name = mtl.file_name().unwrap().to_str().unwrap();
+        let obj = write_temp(
+            "colors.obj",
+            &format!(
+                "mtllib {}\nv 0 0 0\nv 1 0 0\nv 0 1 0 0 0 1\nusemtl red\nf 1 2 3\n",
+                mtl_name
+            ),
+        );
+        let (data, materials) = KewModelVertexData::from_obj(&obj).unwrap();
+        fs::remove_file(obj).unwrap();
+        fs::remove_file(&mtl).unwrap();
+
+        assert_eq!(materials.len(), 1);
+        assert_eq!(materials[0].name, "red");
+        assert_eq!(
+            materials[0].diffuse_texture,
+            Some(mtl.parent().unwrap().join("red.png"))
+        );
+        assert_eq!(data.vertices[0].color, Vector::new([1.0, 0.0, 0.0]));
+        // the vertex color extension overrides the material
+        assert_eq!(data.vertices[2].color, Vector::new([0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn rejects_malformed_statements() {
+        let malformed = [
+            "v 0 0\n",
+            "v 0 0 zero\n",
+            "vn 0 1\n",
+            "v 0 0 0\nv 1 0 0\nf 1 2\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 0 1 2\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2 3\n",
+        ];
+        for (idx, source) in malformed.iter().enumerate() {
+            let result = load(&format!("malformed-{}.obj", idx), source);
+            assert!(result.is_err(), "accepted {:?}", source);
+        }
+    }
+
+    #[test]
+    fn parses_corner_forms() {
+        assert_eq!(parse_corner("2", 3, 0, 0), Some((1, None, None)));
+        assert_eq!(parse_corner("2/1", 3, 1, 0), Some((1, Some(0), None)));
+        assert_eq!(parse_corner("2//1", 3, 0, 1), Some((1, None, Some(0))));
+        assert_eq!(
+            parse_corner("-1/-1/-2", 3, 2, 2),
+            Some((2, Some(1), Some(0)))
+        );
+        assert_eq!(parse_corner("/1", 3, 1, 0), None);
+        assert_eq!(parse_corner("4", 3, 0, 0), None);
+    }
+}
@@ -1,38 +1,50 @@
 use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::image::aspect_flags;
 use crate::core::model::VertexType;
 use crate::core::shader::KewShader;
 use ash::vk;
-use log::debug;
+use log::{debug, warn};
+use std::mem::size_of_val;
+use std::slice;
 
 pub struct KewCmpPipeline<'a> {
     kew_device: &'a KewDevice,
     layout: vk::PipelineLayout,
     pipeline: vk::Pipeline,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
 }
 
 impl<'a> KewCmpPipeline<'a> {
-    pub fn new(kew_device: &'a KewDevice, shader: &KewShader) -> Self {
+    pub fn new(kew_device: &'a KewDevice, shader: &KewShader) -> Result<Self, KewError> {
+        let push_constant_ranges = shader.push_constant_range.into_iter().collect::<Vec<_>>();
+        let descriptor_set_layouts = KewShader::pipeline_set_layouts(kew_device, &[shader])?;
         let layout = unsafe {
-            let descriptor_set_layouts = &[shader.descriptor_set_layout];
-            let create_info =
-                vk::PipelineLayoutCreateInfo::default().set_layouts(descriptor_set_layouts);
-            kew_device
-                .create_pipeline_layout(&create_info, None)
-                .expect("failed to create pipeline layout")
+            let create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&descriptor_set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+            kew_device.create_pipeline_layout(&create_info, None)?
+        };
+        let pipeline = match Self::create_pipeline(&kew_device, layout, shader.shader_stage_info) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                unsafe { kew_device.destroy_pipeline_layout(layout, None) };
+                return Err(e);
+            }
         };
-        let pipeline = Self::create_pipeline(&kew_device, layout, shader.shader_stage_info);
-        Self {
+        Ok(Self {
             kew_device,
             layout,
             pipeline,
-        }
+            push_constant_ranges,
+        })
     }
 
     fn create_pipeline(
         kew_device: &KewDevice,
         layout: vk::PipelineLayout,
         comp_shader_stage: vk::PipelineShaderStageCreateInfo,
-    ) -> vk::Pipeline {
+    ) -> Result<vk::Pipeline, KewError> {
         let create_info = vk::ComputePipelineCreateInfo::default()
             .flags(vk::PipelineCreateFlags::empty())
             .stage(comp_shader_stage)
@@ -40,11 +52,12 @@ impl<'a> KewCmpPipeline<'a> {
         unsafe {
             kew_device
                 .create_compute_pipelines(
-                    vk::PipelineCache::null(),
+                    *kew_device.pipeline_cache,
                     std::slice::from_ref(&create_info),
                     None,
                 )
-                .expect("failed to create compute pipeline")[0]
+                .map(|pipelines| pipelines[0])
+                .map_err(|(_, e)| e.into())
         }
     }
 
@@ -63,6 +76,23 @@ impl<'a> KewCmpPipeline<'a> {
             &[],
         )
     }
+
+    /// Writes `data` to the push constants at `offset` for the stages of the ranges it touches.
+    pub unsafe fn push_constants<T: Copy>(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        offset: u32,
+        data: &[T],
+    ) {
+        push_constants(
+            self.kew_device,
+            cmd_buffer,
+            self.layout,
+            &self.push_constant_ranges,
+            offset,
+            data,
+        );
+    }
 }
 
 impl Drop for KewCmpPipeline<'_> {
@@ -96,29 +126,90 @@ pub struct PrimitiveState {
     pub front_face: vk::FrontFace,
 }
 
+pub struct MultisampleState {
+    /// Requested sample count, clamped to what the device supports.
+    pub samples: vk::SampleCountFlags,
+    /// Minimum fraction of samples shaded per fragment, `None` disables sample shading.
+    pub sample_shading: Option<f32>,
+    pub alpha_to_coverage: bool,
+}
+
+pub struct StencilState {
+    pub fail_op: vk::StencilOp,
+    pub pass_op: vk::StencilOp,
+    pub depth_fail_op: vk::StencilOp,
+    pub compare_op: vk::CompareOp,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+    pub reference: u32,
+}
+
+impl StencilState {
+    fn op_state(&self) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: self.fail_op,
+            pass_op: self.pass_op,
+            depth_fail_op: self.depth_fail_op,
+            compare_op: self.compare_op,
+            compare_mask: self.compare_mask,
+            write_mask: self.write_mask,
+            reference: self.reference,
+        }
+    }
+}
+
+pub struct DepthStencilState {
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub compare_op: vk::CompareOp,
+    pub stencil_front: Option<StencilState>,
+    /// `None` uses `stencil_front` for back faces as well.
+    pub stencil_back: Option<StencilState>,
+}
+
 pub struct GfxPipelineConfig {
     pub primitive: PrimitiveState,
     pub color_targets: &'static [ColorTarget],
-    // multisample
-    // depth stencil
+    pub multisample: MultisampleState,
+    /// `None` disables depth and stencil tests.
+    pub depth_stencil: Option<DepthStencilState>,
     pub vertex_type: VertexType,
 }
 
+/// Formats of the attachments a pipeline for dynamic rendering draws into.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttachmentFormats {
+    pub color: Vec<vk::Format>,
+    pub depth: Option<vk::Format>,
+}
+
+/// What a `KewGfxPipeline` renders into.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PipelineTarget {
+    /// Subpass 0 of a render pass, the pipeline only works with compatible render passes.
+    PASS(vk::RenderPass),
+    /// Dynamic rendering, the pipeline works with every target whose attachments have these
+    /// formats and the sample count of the pipeline. Needs `KewDevice::dynamic_rendering`.
+    DYNAMIC(AttachmentFormats),
+}
+
 pub struct KewGfxPipeline<'a> {
     pub kew_device: &'a KewDevice,
     pipeline: vk::Pipeline,
     pipeline_layout: vk::PipelineLayout,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
 }
 
 impl<'a> KewGfxPipeline<'a> {
+    /// `layout` has to declare the push constant ranges of both shaders.
     pub fn new(
         kew_device: &'a KewDevice,
         config: &GfxPipelineConfig,
         layout: vk::PipelineLayout,
         vert_shader: &KewShader,
         frag_shader: &KewShader,
-        render_pass: &vk::RenderPass,
-    ) -> Self {
+        target: &PipelineTarget,
+    ) -> Result<Self, KewError> {
         let pstages = [vert_shader.shader_stage_info, frag_shader.shader_stage_info];
         let dstates = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
 
@@ -143,9 +234,20 @@ impl<'a> KewGfxPipeline<'a> {
             .cull_mode(config.primitive.cull_mode)
             .front_face(config.primitive.front_face)
             .depth_bias_enable(false);
-        let mus = vk::PipelineMultisampleStateCreateInfo::default()
-            .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+        let mut mus = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(kew_device.clamp_sample_count(config.multisample.samples))
+            .alpha_to_coverage_enable(config.multisample.alpha_to_coverage)
             .sample_shading_enable(false);
+        if let Some(min_sample_shading) = config.multisample.sample_shading {
+            match kew_device.features.sample_rate_shading == vk::TRUE {
+                true => {
+                    mus = mus
+                        .sample_shading_enable(true)
+                        .min_sample_shading(min_sample_shading)
+                }
+                false => warn!("sample rate shading unsupported (disabled)"),
+            }
+        }
 
         let mut blend_attachments = Vec::with_capacity(config.color_targets.len());
         for target in config.color_targets {
@@ -169,6 +271,24 @@ impl<'a> KewGfxPipeline<'a> {
             blend_attachments.push(attachment);
         }
         debug!("loaded {} blend attachment(s)", blend_attachments.len());
+
+        let mut dss = vk::PipelineDepthStencilStateCreateInfo::default();
+        if let Some(depth_stencil) = &config.depth_stencil {
+            dss = dss
+                .depth_test_enable(depth_stencil.depth_test)
+                .depth_write_enable(depth_stencil.depth_write)
+                .depth_compare_op(depth_stencil.compare_op)
+                .depth_bounds_test_enable(false)
+                .min_depth_bounds(0.0)
+                .max_depth_bounds(1.0);
+            if let Some(stencil) = &depth_stencil.stencil_front {
+                dss = dss.stencil_test_enable(true).front(stencil.op_state());
+            }
+            let stencil_back = depth_stencil.stencil_back.as_ref();
+            if let Some(stencil) = stencil_back.or(depth_stencil.stencil_front.as_ref()) {
+                dss = dss.stencil_test_enable(true).back(stencil.op_state());
+            }
+        }
         let cbl = vk::PipelineColorBlendStateCreateInfo::default().attachments(&blend_attachments);
         let dys = vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dstates);
 
@@ -180,20 +300,41 @@ impl<'a> KewGfxPipeline<'a> {
             .viewport_state(&vps)
             .rasterization_state(&ras)
             .multisample_state(&mus)
+            .depth_stencil_state(&dss)
             .color_blend_state(&cbl)
-            .dynamic_state(&dys)
-            .render_pass(*render_pass);
+            .dynamic_state(&dys);
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::default();
+        let create_info = match target {
+            PipelineTarget::PASS(render_pass) => create_info.render_pass(*render_pass),
+            PipelineTarget::DYNAMIC(formats) => {
+                if !kew_device.dynamic_rendering {
+                    return Err(KewError::UNSUPPORTED("dynamic rendering"));
+                }
+                rendering_info = rendering_info.color_attachment_formats(&formats.color);
+                if let Some(depth_format) = formats.depth {
+                    rendering_info = rendering_info.depth_attachment_format(depth_format);
+                    if aspect_flags(depth_format).contains(vk::ImageAspectFlags::STENCIL) {
+                        rendering_info = rendering_info.stencil_attachment_format(depth_format);
+                    }
+                }
+                create_info.push_next(&mut rendering_info)
+            }
+        };
 
         let pipeline = unsafe {
             kew_device
-                .create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
-                .unwrap()[0]
+                .create_graphics_pipelines(*kew_device.pipeline_cache, &[create_info], None)
+                .map_err(|(_, e)| e)?[0]
         };
-        Self {
+        Ok(Self {
             kew_device,
             pipeline,
             pipeline_layout: layout,
-        }
+            push_constant_ranges: [vert_shader, frag_shader]
+                .iter()
+                .filter_map(|shader| shader.push_constant_range)
+                .collect(),
+        })
     }
 
     pub unsafe fn bind_pipeline(&self, cmd_buffer: vk::CommandBuffer) {
@@ -218,6 +359,23 @@ impl<'a> KewGfxPipeline<'a> {
             &[],
         )
     }
+
+    /// Writes `data` to the push constants at `offset` for the stages of the ranges it touches.
+    pub unsafe fn push_constants<T: Copy>(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        offset: u32,
+        data: &[T],
+    ) {
+        push_constants(
+            self.kew_device,
+            cmd_buffer,
+            self.pipeline_layout,
+            &self.push_constant_ranges,
+            offset,
+            data,
+        );
+    }
 }
 
 impl Drop for KewGfxPipeline<'_> {
@@ -230,3 +388,67 @@ impl Drop for KewGfxPipeline<'_> {
         }
     }
 }
+
+/// Splits `data` at the range boundaries of the layout and pushes every piece with exactly
+/// the stages of the ranges containing it, pieces outside of all ranges are skipped.
+unsafe fn push_constants<T: Copy>(
+    kew_device: &KewDevice,
+    cmd_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout,
+    ranges: &[vk::PushConstantRange],
+    offset: u32,
+    data: &[T],
+) {
+    let bytes = slice::from_raw_parts(data.as_ptr() as *const u8, size_of_val(data));
+    let end = offset + bytes.len() as u32;
+    let mut bounds = ranges
+        .iter()
+        .flat_map(|range| [range.offset, range.offset + range.size])
+        .chain([offset, end])
+        .filter(|bound| (offset..=end).contains(bound))
+        .collect::<Vec<_>>();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut pending: Option<(u32, u32, vk::ShaderStageFlags)> = None;
+    for piece in bounds.windows(2) {
+        let stages = ranges
+            .iter()
+            .filter(|range| range.offset <= piece[0] && piece[1] <= range.offset + range.size)
+            .fold(vk::ShaderStageFlags::empty(), |stages, range| {
+                stages | range.stage_flags
+            });
+        if stages.is_empty() {
+            warn!(
+                "push constants {}..{} outside of the layout (skipped)",
+                piece[0], piece[1]
+            );
+        }
+        pending = match pending {
+            Some((start, _, pending_stages)) if pending_stages == stages => {
+                Some((start, piece[1], stages))
+            }
+            _ => {
+                push_piece(kew_device, cmd_buffer, layout, bytes, offset, pending);
+                Some((piece[0], piece[1], stages))
+            }
+        };
+    }
+    push_piece(kew_device, cmd_buffer, layout, bytes, offset, pending);
+}
+
+unsafe fn push_piece(
+    kew_device: &KewDevice,
+    cmd_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout,
+    bytes: &[u8],
+    offset: u32,
+    piece: Option<(u32, u32, vk::ShaderStageFlags)>,
+) {
+    if let Some((start, end, stages)) = piece {
+        if !stages.is_empty() {
+            let piece_bytes = &bytes[(start - offset) as usize..(end - offset) as usize];
+            kew_device.cmd_push_constants(cmd_buffer, layout, stages, start, piece_bytes);
+        }
+    }
+}
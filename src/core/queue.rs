@@ -0,0 +1,106 @@
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::sync::{KewFence, SyncPoint};
+use ash::vk;
+use std::ops::Deref;
+
+/// First queue of a queue family, every submission goes through it.
+pub struct KewQueue<'a> {
+    kew_device: &'a KewDevice,
+    vk_queue: vk::Queue,
+    pub family_idx: u32,
+}
+
+impl<'a> KewQueue<'a> {
+    pub fn new(kew_device: &'a KewDevice, family_idx: u32) -> Self {
+        let vk_queue = unsafe { kew_device.get_device_queue(family_idx, 0) };
+        Self {
+            kew_device,
+            vk_queue,
+            family_idx,
+        }
+    }
+
+    /// Submits `cmd_buffers` once every `wait` point is reached at its stage, signals the
+    /// `signal` points when done and returns a fence for the host to wait on.
+    pub fn submit(
+        &self,
+        cmd_buffers: &[vk::CommandBuffer],
+        wait: &[(SyncPoint, vk::PipelineStageFlags)],
+        signal: &[SyncPoint],
+    ) -> Result<KewFence<'a>, KewError> {
+        let fence = KewFence::new(self.kew_device, false)?;
+        self.submit_raw(cmd_buffers, wait, signal, *fence)?;
+        Ok(fence)
+    }
+
+    /// Same as `submit` with a fence owned by the caller, which is reset first.
+    pub fn submit_with_fence(
+        &self,
+        cmd_buffers: &[vk::CommandBuffer],
+        wait: &[(SyncPoint, vk::PipelineStageFlags)],
+        signal: &[SyncPoint],
+        fence: &KewFence,
+    ) -> Result<(), KewError> {
+        fence.reset()?;
+        self.submit_raw(cmd_buffers, wait, signal, **fence)
+    }
+
+    pub fn wait_idle(&self) -> Result<(), KewError> {
+        unsafe { self.kew_device.queue_wait_idle(self.vk_queue)? };
+        Ok(())
+    }
+
+    fn submit_raw(
+        &self,
+        cmd_buffers: &[vk::CommandBuffer],
+        wait: &[(SyncPoint, vk::PipelineStageFlags)],
+        signal: &[SyncPoint],
+        fence: vk::Fence,
+    ) -> Result<(), KewError> {
+        let wait_semaphores = wait
+            .iter()
+            .map(|(point, _)| point.semaphore())
+            .collect::<Vec<_>>();
+        let wait_values = wait
+            .iter()
+            .map(|(point, _)| point.value())
+            .collect::<Vec<_>>();
+        let wait_stages = wait.iter().map(|(_, stage)| *stage).collect::<Vec<_>>();
+        let signal_semaphores = signal
+            .iter()
+            .map(|point| point.semaphore())
+            .collect::<Vec<_>>();
+        let signal_values = signal.iter().map(|point| point.value()).collect::<Vec<_>>();
+        // binary semaphores ignore their values, devices without timelines do not know the struct
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::default()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+        let mut submit_info = vk::SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(cmd_buffers)
+            .signal_semaphores(&signal_semaphores);
+        if wait
+            .iter()
+            .map(|(point, _)| point)
+            .chain(signal)
+            .any(|point| matches!(point, SyncPoint::TIMELINE(..)))
+        {
+            submit_info = submit_info.push_next(&mut timeline_info);
+        }
+        unsafe {
+            self.kew_device
+                .queue_submit(self.vk_queue, &[submit_info], fence)?
+        };
+        Ok(())
+    }
+}
+
+impl Deref for KewQueue<'_> {
+    type Target = vk::Queue;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_queue
+    }
+}
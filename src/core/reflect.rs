@@ -0,0 +1,864 @@
+use crate::core::error::KewError;
+use ash::vk;
+use std::collections::HashMap;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+/// Deepest type nesting followed when sizing blocks, guards against cyclic malformed types.
+const MAX_TYPE_DEPTH: u32 = 64;
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Zero for runtime sized arrays.
+    pub descriptor_count: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReflectedVertexInput {
+    pub location: u32,
+    pub format: vk::Format,
+}
+
+/// Interface of a single SPIR-V entry point, parsed from the module words.
+#[derive(Clone, Debug)]
+pub struct KewShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub bindings: Vec<ReflectedBinding>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    pub vertex_inputs: Vec<ReflectedVertexInput>,
+    pub local_size: Option<[u32; 3]>,
+}
+
+#[derive(Clone)]
+enum SpirvType {
+    Scalar {
+        width: u32,
+        float: bool,
+        signed: bool,
+    },
+    Vector {
+        component: u32,
+        count: u32,
+    },
+    Matrix {
+        column: u32,
+        count: u32,
+    },
+    Image {
+        dim: u32,
+        sampled: u32,
+    },
+    Sampler,
+    SampledImage,
+    Array {
+        element: u32,
+        length: u32,
+    },
+    RuntimeArray {
+        element: u32,
+    },
+    Struct {
+        members: Vec<u32>,
+    },
+    Pointer {
+        pointee: u32,
+    },
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    built_in: bool,
+    block: bool,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+}
+
+impl KewShaderReflection {
+    pub fn parse(code: &[u32], entry_name: &str) -> Result<Self, KewError> {
+        if code.len() < 5 || code[0] != SPIRV_MAGIC {
+            return Err(KewError::SPIRV("missing spir-v magic number".to_string()));
+        }
+
+        let mut entry: Option<(u32, vk::ShaderStageFlags)> = None;
+        let mut local_sizes: Vec<(u32, [u32; 3])> = Vec::new();
+        let mut types: HashMap<u32, SpirvType> = HashMap::new();
+        let mut constants: HashMap<u32, u32> = HashMap::new();
+        let mut decorations: HashMap<u32, Decorations> = HashMap::new();
+        let mut member_offsets: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut member_matrix_strides: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut member_built_ins: HashMap<u32, bool> = HashMap::new();
+        let mut variables: Vec<(u32, u32, u32)> = Vec::new();
+
+        let mut idx = 5;
+        while idx < code.len() {
+            let word_count = (code[idx] >> 16) as usize;
+            let opcode = code[idx] & 0xffff;
+            if word_count == 0 || idx + word_count > code.len() {
+                return Err(KewError::SPIRV(format!(
+                    "malformed instruction at word {}",
+                    idx
+                )));
+            }
+            let ops = &code[idx + 1..idx + word_count];
+            if ops.len() < required_operands(opcode, ops) {
+                return Err(KewError::SPIRV(format!(
+                    "truncated instruction {} at word {}",
+                    opcode, idx
+                )));
+            }
+            match opcode {
+                OP_ENTRY_POINT => {
+                    let name = parse_string(&ops[2..]);
+                    if name == entry_name {
+                        entry = Some((ops[1], execution_model_stage(ops[0])?));
+                    }
+                }
+                OP_EXECUTION_MODE if ops[1] == EXECUTION_MODE_LOCAL_SIZE => {
+                    local_sizes.push((ops[0], [ops[2], ops[3], ops[4]]));
+                }
+                OP_TYPE_INT => {
+                    types.insert(
+                        ops[0],
+                        SpirvType::Scalar {
+                            width: ops[1],
+                            float: false,
+                            signed: ops[2] == 1,
+                        },
+                    );
+                }
+                OP_TYPE_FLOAT => {
+                    types.insert(
+                        ops[0],
+                        SpirvType::Scalar {
+                            width: ops[1],
+                            float: true,
+                            signed: true,
+                        },
+                    );
+                }
+                OP_TYPE_VECTOR => {
+                    types.insert(
+                        ops[0],
+                        SpirvType::Vector {
+                            component: ops[1],
+                            count: ops[2],
+                        },
+                    );
+                }
+                OP_TYPE_MATRIX => {
+                    types.insert(
+                        ops[0],
+                        SpirvType::Matrix {
+                            column: ops[1],
+                            count: ops[2],
+                        },
+                    );
+                }
+                OP_TYPE_IMAGE => {
+                    types.insert(
+                        ops[0],
+                        SpirvType::Image {
+                            dim: ops[2],
+                            sampled: ops[6],
+                        },
+                    );
+                }
+                OP_TYPE_SAMPLER => {
+                    types.insert(ops[0], SpirvType::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    types.insert(ops[0], SpirvType::SampledImage);
+                }
+                OP_TYPE_ARRAY => {
+                    types.insert(
+                        ops[0],
+                        SpirvType::Array {
+                            element: ops[1],
+                            length: ops[2],
+                        },
+                    );
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    types.insert(ops[0], SpirvType::RuntimeArray { element: ops[1] });
+                }
+                OP_TYPE_STRUCT => {
+                    types.insert(
+                        ops[0],
+                        SpirvType::Struct {
+                            members: ops[1..].to_vec(),
+                        },
+                    );
+                }
+                OP_TYPE_POINTER => {
+                    types.insert(ops[0], SpirvType::Pointer { pointee: ops[2] });
+                }
+                OP_CONSTANT => {
+                    constants.insert(ops[1], ops[2]);
+                }
+                OP_VARIABLE => {
+                    variables.push((ops[1], ops[0], ops[2]));
+                }
+                OP_DECORATE => {
+                    let entry = decorations.entry(ops[0]).or_default();
+                    match ops[1] {
+                        DECORATION_DESCRIPTOR_SET => entry.set = Some(ops[2]),
+                        DECORATION_BINDING => entry.binding = Some(ops[2]),
+                        DECORATION_LOCATION => entry.location = Some(ops[2]),
+                        DECORATION_BUILT_IN => entry.built_in = true,
+                        DECORATION_BLOCK => entry.block = true,
+                        DECORATION_BUFFER_BLOCK => entry.buffer_block = true,
+                        DECORATION_ARRAY_STRIDE => entry.array_stride = Some(ops[2]),
+                        _ => (),
+                    }
+                }
+                OP_MEMBER_DECORATE => match ops[2] {
+                    DECORATION_OFFSET => {
+                        member_offsets.insert((ops[0], ops[1]), ops[3]);
+                    }
+                    DECORATION_MATRIX_STRIDE => {
+                        member_matrix_strides.insert((ops[0], ops[1]), ops[3]);
+                    }
+                    DECORATION_BUILT_IN => {
+                        member_built_ins.insert(ops[0], true);
+                    }
+                    _ => (),
+                },
+                _ => (),
+            }
+            idx += word_count;
+        }
+
+        let (entry_id, stage) = entry
+            .ok_or_else(|| KewError::SPIRV(format!("entry point {} not found", entry_name)))?;
+        let module = SpirvModule {
+            types,
+            constants,
+            decorations,
+            member_offsets,
+            member_matrix_strides,
+        };
+
+        let mut bindings = Vec::new();
+        let mut push_constant_ranges = Vec::new();
+        let mut vertex_inputs = Vec::new();
+        for (id, pointer_type, storage_class) in variables {
+            let pointee = match module.types.get(&pointer_type) {
+                Some(SpirvType::Pointer { pointee }) => *pointee,
+                _ => continue,
+            };
+            let decoration = module.decorations.get(&id);
+            match storage_class {
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let (set, binding) = match decoration {
+                        Some(Decorations {
+                            set: Some(set),
+                            binding: Some(binding),
+                            ..
+                        }) => (*set, *binding),
+                        _ => continue,
+                    };
+                    let (element, descriptor_count) = module.unwrap_array(pointee);
+                    let descriptor_type = module
+                        .descriptor_type(element, storage_class)
+                        .ok_or_else(|| {
+                            KewError::SPIRV(format!(
+                                "unsupported descriptor at set {} binding {}",
+                                set, binding
+                            ))
+                        })?;
+                    bindings.push(ReflectedBinding {
+                        set,
+                        binding,
+                        descriptor_type,
+                        descriptor_count,
+                    });
+                }
+                STORAGE_PUSH_CONSTANT => {
+                    let offset = module.first_member_offset(pointee);
+                    let size = module
+                        .type_size(pointee, 0)?
+                        .checked_sub(offset)
+                        .ok_or_else(|| {
+                            KewError::SPIRV(format!(
+                                "push constant block {} ends before its first member",
+                                pointee
+                            ))
+                        })?;
+                    push_constant_ranges.push(
+                        vk::PushConstantRange::default()
+                            .stage_flags(stage)
+                            .offset(offset)
+                            .size(size),
+                    );
+                }
+                STORAGE_INPUT if stage == vk::ShaderStageFlags::VERTEX => {
+                    let location = match decoration {
+                        Some(Decorations {
+                            location: Some(location),
+                            built_in: false,
+                            ..
+                        }) => *location,
+                        _ => continue,
+                    };
+                    if member_built_ins.contains_key(&pointee) {
+                        continue;
+                    }
+                    vertex_inputs.push(ReflectedVertexInput {
+                        location,
+                        format: module.vertex_format(pointee).ok_or_else(|| {
+                            KewError::SPIRV(format!(
+                                "unsupported vertex input at location {}",
+                                location
+                            ))
+                        })?,
+                    });
+                }
+                _ => (),
+            }
+        }
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+        vertex_inputs.sort_by_key(|input| input.location);
+
+        let local_size = local_sizes
+            .iter()
+            .find(|(target, _)| *target == entry_id)
+            .map(|(_, size)| *size);
+
+        Ok(Self {
+            stage,
+            bindings,
+            push_constant_ranges,
+            vertex_inputs,
+            local_size,
+        })
+    }
+
+    pub fn set_bindings(&self, set: u32) -> impl Iterator<Item = &ReflectedBinding> {
+        self.bindings
+            .iter()
+            .filter(move |binding| binding.set == set)
+    }
+
+    pub fn set_layout_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+        self.set_bindings(set)
+            .map(|binding| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(binding.binding)
+                    .descriptor_type(binding.descriptor_type)
+                    .descriptor_count(binding.descriptor_count)
+                    .stage_flags(self.stage)
+            })
+            .collect()
+    }
+
+    /// Checks hand-written bindings of `set` against the module.
+    pub fn validate_bindings(
+        &self,
+        stage: vk::ShaderStageFlags,
+        set: u32,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+    ) -> Result<(), KewError> {
+        if stage != self.stage {
+            return Err(KewError::SPIRV(format!(
+                "configured stage {:?} does not match module stage {:?}",
+                stage, self.stage
+            )));
+        }
+        for reflected in self.set_bindings(set) {
+            let configured = bindings
+                .iter()
+                .find(|binding| binding.binding == reflected.binding)
+                .ok_or_else(|| {
+                    KewError::SPIRV(format!(
+                        "binding {} ({:?}) missing from configuration",
+                        reflected.binding, reflected.descriptor_type
+                    ))
+                })?;
+            if configured.descriptor_type != reflected.descriptor_type
+                || (reflected.descriptor_count != 0
+                    && configured.descriptor_count != reflected.descriptor_count)
+            {
+                return Err(KewError::SPIRV(format!(
+                    "binding {} configured as {}x {:?}, module declares {}x {:?}",
+                    reflected.binding,
+                    configured.descriptor_count,
+                    configured.descriptor_type,
+                    reflected.descriptor_count,
+                    reflected.descriptor_type
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `range` covers the push constant block of the module.
+    pub fn validate_push_constants(&self, range: &vk::PushConstantRange) -> Result<(), KewError> {
+        for reflected in &self.push_constant_ranges {
+            if range.offset > reflected.offset
+                || range.offset + range.size < reflected.offset + reflected.size
+            {
+                return Err(KewError::SPIRV(format!(
+                    "push constants configured as bytes {}..{}, module reads bytes {}..{}",
+                    range.offset,
+                    range.offset + range.size,
+                    reflected.offset,
+                    reflected.offset + reflected.size
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct SpirvModule {
+    types: HashMap<u32, SpirvType>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    member_offsets: HashMap<(u32, u32), u32>,
+    member_matrix_strides: HashMap<(u32, u32), u32>,
+}
+
+impl SpirvModule {
+    fn unwrap_array(&self, type_id: u32) -> (u32, u32) {
+        match self.types.get(&type_id) {
+            Some(SpirvType::Array { element, length }) => {
+                (*element, self.constants.get(length).copied().unwrap_or(1))
+            }
+            Some(SpirvType::RuntimeArray { element }) => (*element, 0),
+            _ => (type_id, 1),
+        }
+    }
+
+    fn descriptor_type(&self, type_id: u32, storage_class: u32) -> Option<vk::DescriptorType> {
+        match (self.types.get(&type_id)?, storage_class) {
+            (SpirvType::SampledImage, _) => Some(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
+            (SpirvType::Sampler, _) => Some(vk::DescriptorType::SAMPLER),
+            (SpirvType::Image { dim, sampled }, _) => Some(match (*dim, *sampled) {
+                (DIM_BUFFER, 2) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                (DIM_BUFFER, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                (DIM_SUBPASS_DATA, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+                (_, 2) => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            }),
+            (SpirvType::Struct { .. }, STORAGE_STORAGE_BUFFER) => {
+                Some(vk::DescriptorType::STORAGE_BUFFER)
+            }
+            (SpirvType::Struct { .. }, STORAGE_UNIFORM) => {
+                let decoration = self.decorations.get(&type_id)?;
+                match (decoration.block, decoration.buffer_block) {
+                    (_, true) => Some(vk::DescriptorType::STORAGE_BUFFER),
+                    (true, _) => Some(vk::DescriptorType::UNIFORM_BUFFER),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn type_size(&self, type_id: u32, depth: u32) -> Result<u32, KewError> {
+        if depth > MAX_TYPE_DEPTH {
+            return Err(KewError::SPIRV(format!("type {} nested too deep", type_id)));
+        }
+        Ok(match self.types.get(&type_id) {
+            Some(SpirvType::Scalar { width, .. }) => width / 8,
+            Some(SpirvType::Vector { component, count }) => self
+                .type_size(*component, depth + 1)?
+                .saturating_mul(*count),
+            Some(SpirvType::Matrix { column, count }) => {
+                self.type_size(*column, depth + 1)?.saturating_mul(*count)
+            }
+            Some(SpirvType::Array { element, length }) => {
+                let stride = match self
+                    .decorations
+                    .get(&type_id)
+                    .and_then(|decoration| decoration.array_stride)
+                {
+                    Some(stride) => stride,
+                    None => self.type_size(*element, depth + 1)?,
+                };
+                stride.saturating_mul(self.constants.get(length).copied().unwrap_or(1))
+            }
+            Some(SpirvType::Struct { members }) => {
+                let mut size = 0;
+                for (idx, member) in members.iter().enumerate() {
+                    let key = (type_id, idx as u32);
+                    let offset = self.member_offsets.get(&key).copied().unwrap_or(0);
+                    let member_size =
+                        match (self.types.get(member), self.member_matrix_strides.get(&key)) {
+                            (Some(SpirvType::Matrix { count, .. }), Some(stride)) => {
+                                stride.saturating_mul(*count)
+                            }
+                            _ => self.type_size(*member, depth + 1)?,
+                        };
+                    size = size.max(offset.saturating_add(member_size));
+                }
+                size
+            }
+            _ => 0,
+        })
+    }
+
+    /// Offset of the first member of a struct, blocks of later stages may skip the members of
+    /// earlier ones.
+    fn first_member_offset(&self, type_id: u32) -> u32 {
+        match self.types.get(&type_id) {
+            Some(SpirvType::Struct { members }) => (0..members.len() as u32)
+                .map(|idx| {
+                    self.member_offsets
+                        .get(&(type_id, idx))
+                        .copied()
+                        .unwrap_or(0)
+                })
+                .min()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    fn vertex_format(&self, type_id: u32) -> Option<vk::Format> {
+        let (component, count) = match self.types.get(&type_id)? {
+            SpirvType::Vector { component, count } => (*component, *count),
+            SpirvType::Scalar { .. } => (type_id, 1),
+            _ => return None,
+        };
+        let (width, float, signed) = match self.types.get(&component)? {
+            SpirvType::Scalar {
+                width,
+                float,
+                signed,
+            } => (*width, *float, *signed),
+            _ => return None,
+        };
+        if width != 32 {
+            return None;
+        }
+        Some(match (float, signed, count) {
+            (true, _, 1) => vk::Format::R32_SFLOAT,
+            (true, _, 2) => vk::Format::R32G32_SFLOAT,
+            (true, _, 3) => vk::Format::R32G32B32_SFLOAT,
+            (true, _, 4) => vk::Format::R32G32B32A32_SFLOAT,
+            (false, true, 1) => vk::Format::R32_SINT,
+            (false, true, 2) => vk::Format::R32G32_SINT,
+            (false, true, 3) => vk::Format::R32G32B32_SINT,
+            (false, true, 4) => vk::Format::R32G32B32A32_SINT,
+            (false, false, 1) => vk::Format::R32_UINT,
+            (false, false, 2) => vk::Format::R32G32_UINT,
+            (false, false, 3) => vk::Format::R32G32B32_UINT,
+            (false, false, 4) => vk::Format::R32G32B32A32_UINT,
+            _ => return None,
+        })
+    }
+}
+
+fn execution_model_stage(model: u32) -> Result<vk::ShaderStageFlags, KewError> {
+    match model {
+        0 => Ok(vk::ShaderStageFlags::VERTEX),
+        1 => Ok(vk::ShaderStageFlags::TESSELLATION_CONTROL),
+        2 => Ok(vk::ShaderStageFlags::TESSELLATION_EVALUATION),
+        3 => Ok(vk::ShaderStageFlags::GEOMETRY),
+        4 => Ok(vk::ShaderStageFlags::FRAGMENT),
+        5 => Ok(vk::ShaderStageFlags::COMPUTE),
+        _ => Err(KewError::SPIRV(format!(
+            "unsupported execution model {}",
+            model
+        ))),
+    }
+}
+
+/// Operand words the parser reads from an instruction, depending on its decoration or mode.
+fn required_operands(opcode: u32, ops: &[u32]) -> usize {
+    match opcode {
+        OP_TYPE_SAMPLER | OP_TYPE_STRUCT => 1,
+        OP_TYPE_FLOAT | OP_TYPE_SAMPLED_IMAGE | OP_TYPE_RUNTIME_ARRAY => 2,
+        OP_ENTRY_POINT | OP_TYPE_INT | OP_TYPE_VECTOR | OP_TYPE_MATRIX | OP_TYPE_ARRAY
+        | OP_TYPE_POINTER | OP_CONSTANT | OP_VARIABLE => 3,
+        OP_TYPE_IMAGE => 7,
+        OP_EXECUTION_MODE => match ops.get(1) {
+            Some(&EXECUTION_MODE_LOCAL_SIZE) => 5,
+            _ => 2,
+        },
+        OP_DECORATE => match ops.get(1) {
+            Some(
+                &(DECORATION_DESCRIPTOR_SET
+                | DECORATION_BINDING
+                | DECORATION_LOCATION
+                | DECORATION_ARRAY_STRIDE),
+            ) => 3,
+            _ => 2,
+        },
+        OP_MEMBER_DECORATE => match ops.get(2) {
+            Some(&(DECORATION_OFFSET | DECORATION_MATRIX_STRIDE)) => 4,
+            _ => 3,
+        },
+        _ => 0,
+    }
+}
+
+fn parse_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|byte| *byte != 0)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    const OP_CAPABILITY: u32 = 17;
+    const DIM_2D: u32 = 1;
+
+    fn inst(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn string(value: &str) -> Vec<u32> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize(value.len() / 4 * 4 + 4, 0);
+        bytes
+            .chunks(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    fn module(instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![SPIRV_MAGIC, 0x0001_0000, 0, 64, 0];
+        instructions.iter().for_each(|inst| words.extend(inst));
+        words
+    }
+
+    fn entry_point(model: u32, id: u32) -> Vec<u32> {
+        inst(
+            OP_ENTRY_POINT,
+            &[&[model, id][..], &string("main")].concat(),
+        )
+    }
+
+    /// Storage image, storage buffer, sampler array and push constants of a compute shader.
+    fn compute_module() -> Vec<u32> {
+        module(&[
+            inst(OP_CAPABILITY, &[1]),
+            entry_point(5, 1),
+            inst(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 16, 8, 1]),
+            inst(OP_DECORATE, &[15, DECORATION_DESCRIPTOR_SET, 0]),
+            inst(OP_DECORATE, &[15, DECORATION_BINDING, 0]),
+            inst(OP_DECORATE, &[10, DECORATION_BLOCK]),
+            inst(OP_MEMBER_DECORATE, &[10, 0, DECORATION_OFFSET, 0]),
+            inst(OP_DECORATE, &[12, DECORATION_DESCRIPTOR_SET, 0]),
+            inst(OP_DECORATE, &[12, DECORATION_BINDING, 1]),
+            inst(OP_DECORATE, &[19, DECORATION_DESCRIPTOR_SET, 1]),
+            inst(OP_DECORATE, &[19, DECORATION_BINDING, 2]),
+            inst(OP_DECORATE, &[20, DECORATION_BLOCK]),
+            inst(OP_MEMBER_DECORATE, &[20, 0, DECORATION_OFFSET, 16]),
+            inst(OP_MEMBER_DECORATE, &[20, 1, DECORATION_OFFSET, 32]),
+            inst(OP_TYPE_INT, &[2, 32, 0]),
+            inst(OP_TYPE_FLOAT, &[3, 32]),
+            inst(OP_TYPE_VECTOR, &[4, 3, 4]),
+            inst(OP_TYPE_RUNTIME_ARRAY, &[5, 4]),
+            inst(OP_TYPE_STRUCT, &[10, 5]),
+            inst(OP_TYPE_POINTER, &[11, STORAGE_STORAGE_BUFFER, 10]),
+            inst(OP_VARIABLE, &[11, 12, STORAGE_STORAGE_BUFFER]),
+            inst(OP_TYPE_IMAGE, &[13, 3, DIM_2D, 0, 0, 0, 2, 4]),
+            inst(OP_TYPE_POINTER, &[14, STORAGE_UNIFORM_CONSTANT, 13]),
+            inst(OP_VARIABLE, &[14, 15, STORAGE_UNIFORM_CONSTANT]),
+            inst(OP_TYPE_SAMPLED_IMAGE, &[16, 13]),
+            inst(OP_CONSTANT, &[2, 17, 4]),
+            inst(OP_TYPE_ARRAY, &[18, 16, 17]),
+            inst(OP_TYPE_POINTER, &[21, STORAGE_UNIFORM_CONSTANT, 18]),
+            inst(OP_VARIABLE, &[21, 19, STORAGE_UNIFORM_CONSTANT]),
+            inst(OP_TYPE_STRUCT, &[20, 3, 4]),
+            inst(OP_TYPE_POINTER, &[22, STORAGE_PUSH_CONSTANT, 20]),
+            inst(OP_VARIABLE, &[22, 23, STORAGE_PUSH_CONSTANT]),
+        ])
+    }
+
+    #[test]
+    fn parses_compute_interface() {
+        let reflection = KewShaderReflection::parse(&compute_module(), "main").unwrap();
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(reflection.local_size, Some([16, 8, 1]));
+        let bindings = reflection
+            .bindings
+            .iter()
+            .map(|b| (b.set, b.binding, b.descriptor_type, b.descriptor_count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            bindings,
+            [
+                (0, 0, vk::DescriptorType::STORAGE_IMAGE, 1),
+                (0, 1, vk::DescriptorType::STORAGE_BUFFER, 1),
+                (1, 2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+            ]
+        );
+        let ranges = &reflection.push_constant_ranges;
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].offset, ranges[0].size), (16, 32));
+    }
+
+    #[test]
+    fn parses_vertex_inputs() {
+        let code = module(&[
+            entry_point(0, 1),
+            inst(OP_DECORATE, &[6, DECORATION_LOCATION, 1]),
+            inst(OP_DECORATE, &[8, DECORATION_LOCATION, 0]),
+            inst(OP_TYPE_FLOAT, &[3, 32]),
+            inst(OP_TYPE_VECTOR, &[4, 3, 3]),
+            inst(OP_TYPE_POINTER, &[5, STORAGE_INPUT, 4]),
+            inst(OP_VARIABLE, &[5, 6, STORAGE_INPUT]),
+            inst(OP_TYPE_POINTER, &[7, STORAGE_INPUT, 3]),
+            inst(OP_VARIABLE, &[7, 8, STORAGE_INPUT]),
+        ]);
+        let reflection = KewShaderReflection::parse(&code, "main").unwrap();
+        let inputs = reflection
+            .vertex_inputs
+            .iter()
+            .map(|input| (input.location, input.format))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            [
+                (0, vk::Format::R32_SFLOAT),
+                (1, vk::Format::R32G32B32_SFLOAT)
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_missing_magic() {
+        let mut code = compute_module();
+        code[0] = 0;
+        assert!(KewShaderReflection::parse(&code, "main").is_err());
+        assert!(KewShaderReflection::parse(&code[..3], "main").is_err());
+    }
+
+    #[test]
+    fn rejects_missing_entry_point() {
+        assert!(KewShaderReflection::parse(&compute_module(), "other").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_module() {
+        let code = compute_module();
+        for len in 6..code.len() {
+            // every prefix either parses or errors, none may panic
+            let _ = KewShaderReflection::parse(&code[..len], "main");
+        }
+        assert!(KewShaderReflection::parse(&code[..code.len() - 1], "main").is_err());
+    }
+
+    #[test]
+    fn rejects_zero_word_count() {
+        let mut code = compute_module();
+        code.push(0);
+        assert!(KewShaderReflection::parse(&code, "main").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_operands() {
+        let short = [
+            inst(OP_DECORATE, &[15, DECORATION_BINDING]),
+            inst(OP_MEMBER_DECORATE, &[20, 0, DECORATION_OFFSET]),
+            inst(OP_EXECUTION_MODE, &[1, EXECUTION_MODE_LOCAL_SIZE, 16]),
+            inst(OP_TYPE_IMAGE, &[13, 3, DIM_2D]),
+            inst(OP_VARIABLE, &[14, 15]),
+            inst(OP_ENTRY_POINT, &[5, 1]),
+        ];
+        for inst in short {
+            let code = module(&[entry_point(5, 1), inst]);
+            assert!(KewShaderReflection::parse(&code, "main").is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_cyclic_push_constant_block() {
+        let code = module(&[
+            entry_point(5, 1),
+            inst(OP_TYPE_STRUCT, &[20, 20]),
+            inst(OP_TYPE_POINTER, &[22, STORAGE_PUSH_CONSTANT, 20]),
+            inst(OP_VARIABLE, &[22, 23, STORAGE_PUSH_CONSTANT]),
+        ]);
+        assert!(KewShaderReflection::parse(&code, "main").is_err());
+    }
+
+    #[test]
+    fn reflects_img_push_constants() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("shader/compiled/img.comp.spv");
+        let code = fs::read(path)
+            .unwrap()
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect::<Vec<_>>();
+        let reflection = KewShaderReflection::parse(&code, "main").unwrap();
+        let ranges = &reflection.push_constant_ranges;
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].offset, ranges[0].size), (0, 8));
+        assert_eq!(ranges[0].stage_flags, vk::ShaderStageFlags::COMPUTE);
+    }
+
+    #[test]
+    fn reflects_compiled_shaders() {
+        let compiled = Path::new(env!("CARGO_MANIFEST_DIR")).join("shader/compiled");
+        for entry in fs::read_dir(compiled).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|extension| extension == "spv") {
+                let bytes = fs::read(&path).unwrap();
+                let code = bytes
+                    .chunks_exact(4)
+                    .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+                    .collect::<Vec<_>>();
+                if let Err(e) = KewShaderReflection::parse(&code, "main") {
+                    panic!("{}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+}
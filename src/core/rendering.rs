@@ -0,0 +1,162 @@
+use crate::core::device::KewDevice;
+use crate::core::image::aspect_flags;
+use ash::vk;
+
+/// Image and view of an attachment of `begin_rendering`.
+#[derive(Clone, Copy)]
+pub struct RenderingAttachment {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+}
+
+/// How a pass draws into its attachments: inside a render pass, clearing them with
+/// `clear_values` in attachment order, or with dynamic rendering, see `begin_rendering`.
+pub enum RenderingMode {
+    PASS {
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        clear_values: Vec<vk::ClearValue>,
+    },
+    DYNAMIC {
+        color_attachments: Vec<(RenderingAttachment, Option<RenderingAttachment>)>,
+        depth_attachment: Option<RenderingAttachment>,
+    },
+}
+
+/// Records `record` between the begin and the end of `mode` over `extent`, with a viewport and
+/// scissor covering it.
+pub unsafe fn record_rendering(
+    kew_device: &KewDevice,
+    cmd_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+    mode: RenderingMode,
+    record: impl FnOnce(vk::CommandBuffer),
+) {
+    match mode {
+        RenderingMode::PASS {
+            render_pass,
+            framebuffer,
+            clear_values,
+        } => {
+            let begin_info = vk::RenderPassBeginInfo::default()
+                .render_pass(render_pass)
+                .framebuffer(framebuffer)
+                .render_area(vk::Rect2D::default().extent(extent))
+                .clear_values(&clear_values);
+            kew_device.cmd_begin_render_pass(cmd_buffer, &begin_info, vk::SubpassContents::INLINE);
+            set_viewport(kew_device, cmd_buffer, extent);
+            record(cmd_buffer);
+            kew_device.cmd_end_render_pass(cmd_buffer);
+        }
+        RenderingMode::DYNAMIC {
+            color_attachments,
+            depth_attachment,
+        } => {
+            begin_rendering(
+                kew_device,
+                cmd_buffer,
+                extent,
+                &color_attachments,
+                depth_attachment,
+            );
+            record(cmd_buffer);
+            kew_device.cmd_end_rendering(cmd_buffer);
+        }
+    }
+}
+
+/// Clear values of render pass attachments, in attachment order without the resolved ones.
+pub fn clear_values(color_count: usize, depth: bool) -> Vec<vk::ClearValue> {
+    let mut clear_vals = vec![
+        vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.01, 0.01, 0.01, 0.01],
+            },
+        };
+        color_count
+    ];
+    if depth {
+        clear_vals.push(vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        });
+    }
+    clear_vals
+}
+
+/// Begins dynamic rendering over `extent` with cleared attachments and a viewport and scissor
+/// covering it. The attachments have to be in attachment layouts already, e.g. moved there by
+/// the `KewRenderGraph` recording the pass. Each color attachment is resolved into its resolve
+/// attachment if it has one.
+pub unsafe fn begin_rendering(
+    kew_device: &KewDevice,
+    cmd_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+    color_attachments: &[(RenderingAttachment, Option<RenderingAttachment>)],
+    depth_attachment: Option<RenderingAttachment>,
+) {
+    let color_infos = color_attachments
+        .iter()
+        .map(|(attachment, resolve)| {
+            let info = vk::RenderingAttachmentInfo::default()
+                .image_view(attachment.view)
+                .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                .load_op(vk::AttachmentLoadOp::CLEAR)
+                .clear_value(vk::ClearValue {
+                    color: vk::ClearColorValue {
+                        float32: [0.01, 0.01, 0.01, 0.01],
+                    },
+                });
+            match resolve {
+                Some(resolve) => info
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .resolve_mode(vk::ResolveModeFlags::AVERAGE)
+                    .resolve_image_view(resolve.view)
+                    .resolve_image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+                None => info.store_op(vk::AttachmentStoreOp::STORE),
+            }
+        })
+        .collect::<Vec<_>>();
+    let depth_info = depth_attachment.map(|attachment| {
+        vk::RenderingAttachmentInfo::default()
+            .image_view(attachment.view)
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            })
+    });
+    let render_area = vk::Rect2D::default().extent(extent);
+    let mut rendering_info = vk::RenderingInfo::default()
+        .render_area(render_area)
+        .layer_count(1)
+        .color_attachments(&color_infos);
+    if let (Some(attachment), Some(depth_info)) = (depth_attachment, &depth_info) {
+        rendering_info = rendering_info.depth_attachment(depth_info);
+        if aspect_flags(attachment.format).contains(vk::ImageAspectFlags::STENCIL) {
+            rendering_info = rendering_info.stencil_attachment(depth_info);
+        }
+    }
+    kew_device.cmd_begin_rendering(cmd_buffer, &rendering_info);
+    set_viewport(kew_device, cmd_buffer, extent);
+}
+
+unsafe fn set_viewport(
+    kew_device: &KewDevice,
+    cmd_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+) {
+    let viewport = vk::Viewport::default()
+        .width(extent.width as f32)
+        .height(extent.height as f32)
+        .max_depth(1.0);
+    kew_device.cmd_set_viewport(cmd_buffer, 0, &[viewport]);
+    kew_device.cmd_set_scissor(cmd_buffer, 0, &[vk::Rect2D::default().extent(extent)]);
+}
@@ -0,0 +1,76 @@
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use ash::vk;
+use log::debug;
+use std::ops::Deref;
+
+/// Filtering and addressing of a `KewSampler`, shared by every image it samples.
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerConfig {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    /// Used for `u`, `v` and `w`.
+    pub address_mode: vk::SamplerAddressMode,
+    /// Clamped to the device limit, ignored when the device lacks sampler anisotropy.
+    pub max_anisotropy: Option<f32>,
+}
+
+pub struct KewSampler<'a> {
+    kew_device: &'a KewDevice,
+    vk_sampler: vk::Sampler,
+}
+
+impl<'a> KewSampler<'a> {
+    /// Samples every mip level the image view exposes.
+    pub fn new(kew_device: &'a KewDevice, config: &SamplerConfig) -> Result<Self, KewError> {
+        let max_anisotropy = match config.max_anisotropy {
+            Some(anisotropy) if kew_device.features.sampler_anisotropy == vk::TRUE => {
+                let limit = unsafe {
+                    kew_device
+                        .context
+                        .instance
+                        .get_physical_device_properties(kew_device.context.physical)
+                        .limits
+                        .max_sampler_anisotropy
+                };
+                Some(anisotropy.clamp(1.0, limit))
+            }
+            _ => None,
+        };
+        let create_info = vk::SamplerCreateInfo::default()
+            .mag_filter(config.mag_filter)
+            .min_filter(config.min_filter)
+            .mipmap_mode(config.mipmap_mode)
+            .address_mode_u(config.address_mode)
+            .address_mode_v(config.address_mode)
+            .address_mode_w(config.address_mode)
+            .anisotropy_enable(max_anisotropy.is_some())
+            .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+            .min_lod(0.0)
+            .max_lod(vk::LOD_CLAMP_NONE)
+            .border_color(vk::BorderColor::FLOAT_OPAQUE_BLACK);
+        let vk_sampler = unsafe { kew_device.create_sampler(&create_info, None)? };
+        Ok(Self {
+            kew_device,
+            vk_sampler,
+        })
+    }
+}
+
+impl Deref for KewSampler<'_> {
+    type Target = vk::Sampler;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_sampler
+    }
+}
+
+impl Drop for KewSampler<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewSampler");
+        unsafe {
+            self.kew_device.destroy_sampler(self.vk_sampler, None);
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::core::error::KewError;
+use crate::core::model::{generate_normals, KewModelVertexData, Vertex};
+use crate::core::texture::TextureEncoding;
+use crate::math::matrix::Matrix;
+use image::{DynamicImage, GrayImage, RgbImage, RgbaImage};
+use log::{debug, warn};
+use std::path::Path;
+
+/// Metallic-roughness material. Textures index into `KewScene::images`.
+#[derive(Clone, Debug)]
+pub struct KewMaterial {
+    pub name: Option<String>,
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: [f32; 3],
+    pub base_color_texture: Option<usize>,
+    pub metallic_roughness_texture: Option<usize>,
+    pub normal_texture: Option<usize>,
+    pub occlusion_texture: Option<usize>,
+    pub emissive_texture: Option<usize>,
+}
+
+pub struct KewPrimitive {
+    pub data: KewModelVertexData<Vertex>,
+    pub material: Option<usize>,
+}
+
+pub struct KewMesh {
+    pub name: Option<String>,
+    pub primitives: Vec<KewPrimitive>,
+}
+
+/// Node with a transform relative to its parent.
+pub struct KewSceneNode {
+    pub name: Option<String>,
+    pub transform: Matrix<f32, 4, 4>,
+    pub mesh: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+pub struct KewScene {
+    pub nodes: Vec<KewSceneNode>,
+    pub roots: Vec<usize>,
+    pub meshes: Vec<KewMesh>,
+    pub materials: Vec<KewMaterial>,
+    pub images: Vec<DynamicImage>,
+}
+
+impl KewScene {
+    /// Imports the default (or first) scene of a `.gltf` or `.glb` file with its buffers and images.
+    pub fn from_gltf(path: &Path) -> Result<Self, KewError> {
+        let asset_err = |e: gltf::Error| KewError::ASSET(format!("{}: {}", path.display(), e));
+        let (document, buffers, images) = gltf::import(path).map_err(asset_err)?;
+
+        let materials = document
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                KewMaterial {
+                    name: material.name().map(str::to_string),
+                    base_color: pbr.base_color_factor(),
+                    metallic: pbr.metallic_factor(),
+                    roughness: pbr.roughness_factor(),
+                    emissive: material.emissive_factor(),
+                    base_color_texture: pbr
+                        .base_color_texture()
+                        .map(|info| info.texture().source().index()),
+                    metallic_roughness_texture: pbr
+                        .metallic_roughness_texture()
+                        .map(|info| info.texture().source().index()),
+                    normal_texture: material
+                        .normal_texture()
+                        .map(|normal| normal.texture().source().index()),
+                    occlusion_texture: material
+                        .occlusion_texture()
+                        .map(|occlusion| occlusion.texture().source().index()),
+                    emissive_texture: material
+                        .emissive_texture()
+                        .map(|info| info.texture().source().index()),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut meshes = Vec::new();
+        for mesh in document.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    warn!(
+                        "{}: skipped {:?} primitive of mesh {}",
+                        path.display(),
+                        primitive.mode(),
+                        mesh.index()
+                    );
+                    continue;
+                }
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions = reader
+                    .read_positions()
+                    .ok_or_else(|| {
+                        KewError::ASSET(format!(
+                            "{}: primitive of mesh {} without positions",
+                            path.display(),
+                            mesh.index()
+                        ))
+                    })?
+                    .collect::<Vec<_>>();
+                let indices = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                let normals = match reader.read_normals() {
+                    Some(normals) => normals.collect::<Vec<_>>(),
+                    None => generate_normals(
+                        &positions,
+                        &indices
+                            .chunks_exact(3)
+                            .map(|corners| {
+                                [
+                                    corners[0] as usize,
+                                    corners[1] as usize,
+                                    corners[2] as usize,
+                                ]
+                            })
+                            .collect::<Vec<_>>(),
+                    ),
+                };
+                let base_color = primitive
+                    .material()
+                    .pbr_metallic_roughness()
+                    .base_color_factor();
+                let colors = match reader.read_colors(0) {
+                    Some(colors) => colors.into_rgb_f32().collect::<Vec<_>>(),
+                    None => vec![[base_color[0], base_color[1], base_color[2]]; positions.len()],
+                };
+                let tex_coords = match reader.read_tex_coords(0) {
+                    Some(tex_coords) => tex_coords.into_f32().collect::<Vec<_>>(),
+                    None => vec![[0.0, 0.0]; positions.len()],
+                };
+
+                let vertices = (0..positions.len())
+                    .map(|idx| Vertex {
+                        position: positions[idx].into(),
+                        normal: normals[idx].into(),
+                        color: colors[idx].into(),
+                        texture: tex_coords[idx].into(),
+                    })
+                    .collect();
+                primitives.push(KewPrimitive {
+                    data: KewModelVertexData { vertices, indices },
+                    material: primitive.material().index(),
+                });
+            }
+            meshes.push(KewMesh {
+                name: mesh.name().map(str::to_string),
+                primitives,
+            });
+        }
+
+        let nodes = document
+            .nodes()
+            .map(|node| KewSceneNode {
+                name: node.name().map(str::to_string),
+                transform: node.transform().matrix().into(),
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                children: node.children().map(|child| child.index()).collect(),
+            })
+            .collect();
+        let roots = match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+            None => Vec::new(),
+        };
+
+        let images = images
+            .into_iter()
+            .enumerate()
+            .map(|(idx, data)| {
+                dynamic_image(data).ok_or_else(|| {
+                    KewError::ASSET(format!(
+                        "{}: image {} has an unsupported pixel format",
+                        path.display(),
+                        idx
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        debug!(
+            "imported {} ({} nodes, {} meshes, {} materials, {} images)",
+            path.display(),
+            document.nodes().len(),
+            meshes.len(),
+            materials.len(),
+            images.len()
+        );
+        Ok(Self {
+            nodes,
+            roots,
+            meshes,
+            materials,
+            images,
+        })
+    }
+
+    /// Transform of every node relative to the scene root, indexed like `nodes`.
+    pub fn world_transforms(&self) -> Vec<Matrix<f32, 4, 4>> {
+        let mut world = vec![Matrix::identity(); self.nodes.len()];
+        let mut pending = self
+            .roots
+            .iter()
+            .map(|root| (*root, Matrix::identity()))
+            .collect::<Vec<_>>();
+        while let Some((idx, parent)) = pending.pop() {
+            world[idx] = parent * self.nodes[idx].transform;
+            for child in &self.nodes[idx].children {
+                pending.push((*child, world[idx]));
+            }
+        }
+        world
+    }
+
+    /// Images referenced as base color or emissive texture hold sRGB colors, the others data.
+    pub fn image_encoding(&self, image: usize) -> TextureEncoding {
+        let is_color = self.materials.iter().any(|material| {
+            material.base_color_texture == Some(image) || material.emissive_texture == Some(image)
+        });
+        match is_color {
+            true => TextureEncoding::SRGB,
+            false => TextureEncoding::LINEAR,
+        }
+    }
+
+    /// Every primitive reachable from the roots with its vertices moved to world space.
+    pub fn flatten(&self) -> Vec<KewPrimitive> {
+        let world = self.world_transforms();
+        let mut flattened = Vec::new();
+        let mut pending = self.roots.clone();
+        while let Some(idx) = pending.pop() {
+            pending.extend(&self.nodes[idx].children);
+            let mesh = match self.nodes[idx].mesh {
+                Some(mesh) => &self.meshes[mesh],
+                None => continue,
+            };
+            for primitive in &mesh.primitives {
+                let normal_matrix = world[idx].normal_matrix();
+                let vertices = primitive
+                    .data
+                    .vertices
+                    .iter()
+                    .map(|vertex| Vertex {
+                        position: world[idx].transform_point(vertex.position),
+                        normal: (normal_matrix * vertex.normal).normalize(),
+                        ..*vertex
+                    })
+                    .collect();
+                flattened.push(KewPrimitive {
+                    data: KewModelVertexData {
+                        vertices,
+                        indices: primitive.data.indices.clone(),
+                    },
+                    material: primitive.material,
+                });
+            }
+        }
+        flattened
+    }
+}
+
+fn dynamic_image(data: gltf::image::Data) -> Option<DynamicImage> {
+    let image = match data.format {
+        gltf::image::Format::R8G8B8A8 => {
+            DynamicImage::ImageRgba8(RgbaImage::from_raw(data.width, data.height, data.pixels)?)
+        }
+        gltf::image::Format::R8G8B8 => {
+            DynamicImage::ImageRgb8(RgbImage::from_raw(data.width, data.height, data.pixels)?)
+        }
+        gltf::image::Format::R8 => {
+            DynamicImage::ImageLuma8(GrayImage::from_raw(data.width, data.height, data.pixels)?)
+        }
+        _ => return None,
+    };
+    Some(image)
+}
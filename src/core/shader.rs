@@ -1,6 +1,9 @@
 use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::reflect::KewShaderReflection;
 use ash::vk;
 use log::debug;
+use std::ops::{Deref, DerefMut};
 use std::{ffi::CStr, fs::File};
 
 pub struct DescriptorSetLayoutBindingInfo {
@@ -9,24 +12,47 @@ pub struct DescriptorSetLayoutBindingInfo {
     pub stage_flags: vk::ShaderStageFlags,
 }
 
+/// Byte range of the push constant block a stage reads.
+pub struct PushConstantInfo {
+    pub offset: u32,
+    pub size: u32,
+}
+
 pub struct ShaderStageConfig<const N: usize> {
     pub entry_name: &'static CStr,
+    /// SPIR-V binary for `KewShader::new`, or GLSL source when compiled by `KewShaderCompiler`.
     pub path: &'static str,
-    pub bindings: [DescriptorSetLayoutBindingInfo; N],
+    /// Descriptor set of the stage, the pipeline layout decides where it is bound.
+    pub set: u32,
+    /// Bindings of `set` numbered 0..N, checked against the module. `None` takes them from the module.
+    pub bindings: Option<[DescriptorSetLayoutBindingInfo; N]>,
+    /// Push constant range of the stage, checked against the module. `None` takes it from the module.
+    pub push_constants: Option<PushConstantInfo>,
     pub stage: vk::ShaderStageFlags,
     pub create_flags: vk::PipelineShaderStageCreateFlags,
 }
 
 impl<const N: usize> ShaderStageConfig<N> {
-    pub fn build_dset_layout_bindings(&self) -> [vk::DescriptorSetLayoutBinding; N] {
+    pub fn build_dset_layout_bindings(&self) -> Option<[vk::DescriptorSetLayoutBinding; N]> {
+        let infos = self.bindings.as_ref()?;
         let mut bindings = [vk::DescriptorSetLayoutBinding::default(); N];
         for i in 0..N {
             bindings[i] = bindings[i].binding(i as u32);
-            bindings[i] = bindings[i].descriptor_type(self.bindings[i].descriptor_type);
-            bindings[i] = bindings[i].descriptor_count(self.bindings[i].descriptor_count);
-            bindings[i] = bindings[i].stage_flags(self.bindings[i].stage_flags);
+            bindings[i] = bindings[i].descriptor_type(infos[i].descriptor_type);
+            bindings[i] = bindings[i].descriptor_count(infos[i].descriptor_count);
+            bindings[i] = bindings[i].stage_flags(infos[i].stage_flags);
         }
-        bindings
+        Some(bindings)
+    }
+
+    pub fn build_push_constant_range(&self) -> Option<vk::PushConstantRange> {
+        let info = self.push_constants.as_ref()?;
+        Some(
+            vk::PushConstantRange::default()
+                .stage_flags(self.stage)
+                .offset(info.offset)
+                .size(info.size),
+        )
     }
 }
 
@@ -34,86 +60,238 @@ pub struct KewShader<'a> {
     kew_device: &'a KewDevice,
     bindings: Vec<vk::DescriptorSetLayoutBinding<'a>>,
     pub shader_module: vk::ShaderModule,
+    /// Set configured by `ShaderStageConfig::set`.
+    pub set: u32,
+    /// Layout of `set`, written through `write_buffer` and `write_image`.
     pub descriptor_set_layout: vk::DescriptorSetLayout,
+    /// Layouts of every set the module declares, including `set`, ordered by set number.
+    pub set_layouts: Vec<(u32, vk::DescriptorSetLayout)>,
+    pub push_constant_range: Option<vk::PushConstantRange>,
     pub shader_stage_info: vk::PipelineShaderStageCreateInfo<'a>,
+    pub reflection: KewShaderReflection,
 }
 
 impl<'a> KewShader<'a> {
     pub fn new<const S: usize>(
         kew_device: &'a KewDevice,
         stage_config: &'a ShaderStageConfig<S>,
-    ) -> Self {
-        let bindings = stage_config.build_dset_layout_bindings();
-        let create_info =
-            vk::DescriptorSetLayoutCreateInfo::default().bindings(bindings.as_slice());
-        let descriptor_set_layout = unsafe {
-            kew_device
-                .create_descriptor_set_layout(&create_info, None)
-                .expect("failed to create descriptor set layout")
+    ) -> Result<Self, KewError> {
+        let code = Self::read_code(stage_config.path)?;
+        Self::with_code(kew_device, stage_config, &code)
+    }
+
+    /// Same as `new` with the SPIR-V already loaded, e.g. by `KewShaderCompiler`.
+    pub fn with_code<const S: usize>(
+        kew_device: &'a KewDevice,
+        stage_config: &'a ShaderStageConfig<S>,
+        code: &[u32],
+    ) -> Result<Self, KewError> {
+        let entry_name = stage_config.entry_name.to_string_lossy();
+        let reflection = KewShaderReflection::parse(code, &entry_name)
+            .map_err(|e| in_file(stage_config.path, e))?;
+        let bindings = match stage_config.build_dset_layout_bindings() {
+            Some(bindings) => {
+                reflection
+                    .validate_bindings(stage_config.stage, stage_config.set, &bindings)
+                    .map_err(|e| in_file(stage_config.path, e))?;
+                bindings.to_vec()
+            }
+            None => reflection.set_layout_bindings(stage_config.set),
+        };
+        let push_constant_range = match stage_config.build_push_constant_range() {
+            Some(range) => {
+                reflection
+                    .validate_push_constants(&range)
+                    .map_err(|e| in_file(stage_config.path, e))?;
+                Some(range)
+            }
+            None => reflection.push_constant_ranges.first().copied(),
         };
-        let shader_module = Self::create_shader_module(&kew_device, stage_config.path);
+        let mut sets = reflection
+            .bindings
+            .iter()
+            .map(|binding| binding.set)
+            .chain([stage_config.set])
+            .collect::<Vec<_>>();
+        sets.sort_unstable();
+        sets.dedup();
+
+        let create_info = vk::ShaderModuleCreateInfo::default().code(code);
+        let shader_module = unsafe { kew_device.create_shader_module(&create_info, None)? };
+        // sets other than the configured one take their bindings from the module
+        let mut set_layouts = Vec::with_capacity(sets.len());
+        for set in sets {
+            let set_bindings = match set == stage_config.set {
+                true => bindings.clone(),
+                false => reflection.set_layout_bindings(set),
+            };
+            let create_info =
+                vk::DescriptorSetLayoutCreateInfo::default().bindings(set_bindings.as_slice());
+            match unsafe { kew_device.create_descriptor_set_layout(&create_info, None) } {
+                Ok(layout) => set_layouts.push((set, layout)),
+                Err(e) => unsafe {
+                    for (_, layout) in set_layouts {
+                        kew_device.destroy_descriptor_set_layout(layout, None);
+                    }
+                    kew_device.destroy_shader_module(shader_module, None);
+                    return Err(e.into());
+                },
+            }
+        }
+        let descriptor_set_layout = set_layouts
+            .iter()
+            .find(|(set, _)| *set == stage_config.set)
+            .unwrap()
+            .1;
 
         let shader_stage_info = vk::PipelineShaderStageCreateInfo::default()
             .flags(stage_config.create_flags)
-            .stage(stage_config.stage)
+            .stage(reflection.stage)
             .module(shader_module)
             .name(stage_config.entry_name);
 
-        Self {
+        Ok(Self {
             kew_device,
             shader_module,
+            set: stage_config.set,
             descriptor_set_layout,
+            set_layouts,
+            push_constant_range,
             shader_stage_info,
-            bindings: bindings.to_vec(),
+            reflection,
+            bindings,
+        })
+    }
+
+    fn read_code(path: &'static str) -> Result<Vec<u32>, KewError> {
+        let mut file = File::open(path)?;
+        ash::util::read_spv(&mut file).map_err(|e| KewError::SPIRV(format!("{}: {}", path, e)))
+    }
+
+    /// Set layouts of a pipeline built from `shaders`, indexed by set number. Sets are ordered
+    /// by update frequency (e.g. global data in set 0, materials in set 1, objects in set 2) so
+    /// rebinding a set keeps the sets before it bound. A set declared by several shaders gets a
+    /// merged layout visible to all of them, its sets have to be allocated from that layout.
+    pub fn pipeline_set_layouts(
+        kew_device: &'a KewDevice,
+        shaders: &[&KewShader],
+    ) -> Result<KewSetLayouts<'a>, KewError> {
+        let set_count = shaders
+            .iter()
+            .flat_map(|shader| shader.set_layouts.iter().map(|(set, _)| set + 1))
+            .max()
+            .unwrap_or(0);
+        let mut set_layouts = KewSetLayouts {
+            kew_device,
+            layouts: Vec::with_capacity(set_count as usize),
+            merged: Vec::new(),
+        };
+        for set in 0..set_count {
+            let declaring = shaders
+                .iter()
+                .filter(|shader| shader.declares_set(set))
+                .collect::<Vec<_>>();
+            let layout = match declaring.as_slice() {
+                [shader] => shader.set_layout(set).unwrap(),
+                // configured sets without bindings
+                [] => shaders
+                    .iter()
+                    .find_map(|shader| shader.set_layout(set))
+                    .ok_or_else(|| {
+                        KewError::SPIRV(format!("descriptor set {} is not declared", set))
+                    })?,
+                shared => {
+                    let declared = shared
+                        .iter()
+                        .map(|shader| shader.set_bindings(set))
+                        .collect::<Vec<_>>();
+                    let bindings = merge_bindings(set, &declared)?;
+                    let create_info =
+                        vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+                    let layout =
+                        unsafe { kew_device.create_descriptor_set_layout(&create_info, None)? };
+                    set_layouts.merged.push(layout);
+                    layout
+                }
+            };
+            set_layouts.layouts.push(layout);
         }
+        Ok(set_layouts)
     }
 
-    fn create_shader_module(kew_device: &KewDevice, path: &'static str) -> vk::ShaderModule {
-        let mut file = File::open(path).expect("failed to open shader file");
-        let code = ash::util::read_spv(&mut file).expect("failed to read shader file");
+    pub fn set_layout(&self, set: u32) -> Option<vk::DescriptorSetLayout> {
+        self.set_layouts
+            .iter()
+            .find(|(layout_set, _)| *layout_set == set)
+            .map(|(_, layout)| *layout)
+    }
 
-        let create_info = vk::ShaderModuleCreateInfo::default().code(&code);
-        unsafe {
-            kew_device
-                .create_shader_module(&create_info, None)
-                .unwrap_or_else(|e| panic!("Failed to create shader module: {}", e))
+    fn set_bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding<'a>> {
+        match set == self.set {
+            true => self.bindings.clone(),
+            false => self.reflection.set_layout_bindings(set),
         }
     }
 
-    pub fn write_buffer(
-        &self,
-        binding: usize,
-        buffer_info: vk::DescriptorBufferInfo,
-        set: &vk::DescriptorSet,
-    ) {
-        let infos = vec![buffer_info];
-        let writes = vec![vk::WriteDescriptorSet::default()
-            .descriptor_type(self.bindings.get(binding).unwrap().descriptor_type)
-            .dst_binding(binding as u32)
-            .dst_set(*set)
-            .buffer_info(&infos)];
-        unsafe {
-            self.kew_device
-                .update_descriptor_sets(writes.as_slice(), &[]);
+    fn declares_set(&self, set: u32) -> bool {
+        match set == self.set {
+            true => !self.bindings.is_empty(),
+            false => self.set_layout(set).is_some(),
         }
     }
 
-    pub fn write_image(
-        &self,
-        binding: usize,
-        image_info: vk::DescriptorImageInfo,
-        set: &vk::DescriptorSet,
-    ) {
-        let infos = vec![image_info];
-        let writes = vec![vk::WriteDescriptorSet::default()
-            .descriptor_type(self.bindings.get(binding).unwrap().descriptor_type)
-            .dst_binding(binding as u32)
-            .dst_set(*set)
-            .image_info(&infos)];
-        unsafe {
-            self.kew_device
-                .update_descriptor_sets(writes.as_slice(), &[])
+    pub fn descriptor_type(&self, binding: usize) -> vk::DescriptorType {
+        self.bindings
+            .iter()
+            .find(|layout_binding| layout_binding.binding == binding as u32)
+            .unwrap()
+            .descriptor_type
+    }
+}
+
+/// Bindings of a set declared by several stages, visible to every stage declaring them.
+fn merge_bindings<'b>(
+    set: u32,
+    declared: &[Vec<vk::DescriptorSetLayoutBinding<'b>>],
+) -> Result<Vec<vk::DescriptorSetLayoutBinding<'b>>, KewError> {
+    let mut merged: Vec<vk::DescriptorSetLayoutBinding> = Vec::new();
+    for binding in declared.iter().flatten() {
+        let existing = match merged
+            .iter_mut()
+            .find(|merged| merged.binding == binding.binding)
+        {
+            Some(existing) => existing,
+            None => {
+                merged.push(*binding);
+                continue;
+            }
+        };
+        // runtime arrays are reflected with a count of zero
+        let counts_match = existing.descriptor_count == binding.descriptor_count
+            || existing.descriptor_count == 0
+            || binding.descriptor_count == 0;
+        if existing.descriptor_type != binding.descriptor_type || !counts_match {
+            return Err(KewError::SPIRV(format!(
+                "descriptor set {} binding {} declared as {}x {:?} and {}x {:?}",
+                set,
+                binding.binding,
+                existing.descriptor_count,
+                existing.descriptor_type,
+                binding.descriptor_count,
+                binding.descriptor_type
+            )));
         }
+        existing.descriptor_count = existing.descriptor_count.max(binding.descriptor_count);
+        existing.stage_flags |= binding.stage_flags;
+    }
+    merged.sort_by_key(|binding| binding.binding);
+    Ok(merged)
+}
+
+fn in_file(path: &str, e: KewError) -> KewError {
+    match e {
+        KewError::SPIRV(msg) => KewError::SPIRV(format!("{}: {}", path, msg)),
+        e => e,
     }
 }
 
@@ -123,8 +301,106 @@ impl Drop for KewShader<'_> {
         unsafe {
             self.kew_device
                 .destroy_shader_module(self.shader_module, None);
-            self.kew_device
-                .destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+            for (_, layout) in &self.set_layouts {
+                self.kew_device.destroy_descriptor_set_layout(*layout, None);
+            }
+        }
+    }
+}
+
+/// Set layouts of a pipeline from `KewShader::pipeline_set_layouts`, indexed by set number.
+/// Layouts merged from several shaders are owned and destroyed with it.
+pub struct KewSetLayouts<'a> {
+    kew_device: &'a KewDevice,
+    layouts: Vec<vk::DescriptorSetLayout>,
+    merged: Vec<vk::DescriptorSetLayout>,
+}
+
+impl Deref for KewSetLayouts<'_> {
+    type Target = Vec<vk::DescriptorSetLayout>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.layouts
+    }
+}
+
+impl DerefMut for KewSetLayouts<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.layouts
+    }
+}
+
+impl Drop for KewSetLayouts<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewSetLayouts");
+        unsafe {
+            for layout in &self.merged {
+                self.kew_device.destroy_descriptor_set_layout(*layout, None);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: u32,
+        stage_flags: vk::ShaderStageFlags,
+    ) -> vk::DescriptorSetLayoutBinding<'static> {
+        vk::DescriptorSetLayoutBinding::default()
+            .binding(binding)
+            .descriptor_type(descriptor_type)
+            .descriptor_count(descriptor_count)
+            .stage_flags(stage_flags)
+    }
+
+    #[test]
+    fn merges_shared_bindings() {
+        let uniform = vk::DescriptorType::UNIFORM_BUFFER;
+        let sampler = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
+        let (vert, frag) = (vk::ShaderStageFlags::VERTEX, vk::ShaderStageFlags::FRAGMENT);
+        let merged = merge_bindings(
+            0,
+            &[
+                vec![binding(0, uniform, 1, vert)],
+                vec![binding(1, sampler, 0, frag), binding(0, uniform, 1, frag)],
+            ],
+        )
+        .unwrap();
+        let merged = merged
+            .iter()
+            .map(|b| {
+                (
+                    b.binding,
+                    b.descriptor_type,
+                    b.descriptor_count,
+                    b.stage_flags,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            merged,
+            [(0, uniform, 1, vert | frag), (1, sampler, 0, frag)]
+        );
+    }
+
+    #[test]
+    fn rejects_conflicting_bindings() {
+        let (vert, frag) = (vk::ShaderStageFlags::VERTEX, vk::ShaderStageFlags::FRAGMENT);
+        let sampler = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
+        let conflicting_type = [
+            vec![binding(0, vk::DescriptorType::UNIFORM_BUFFER, 1, vert)],
+            vec![binding(0, vk::DescriptorType::STORAGE_BUFFER, 1, frag)],
+        ];
+        assert!(merge_bindings(0, &conflicting_type).is_err());
+        let conflicting_count = [
+            vec![binding(2, sampler, 4, vert)],
+            vec![binding(2, sampler, 8, frag)],
+        ];
+        assert!(merge_bindings(0, &conflicting_count).is_err());
+    }
+}
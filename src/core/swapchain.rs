@@ -1,18 +1,42 @@
 use crate::core::context::KewContext;
 use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::graph::{
+    ExternalImage, GraphImage, ImageAccess, KewRenderGraph, TransientImageInfo,
+};
+use crate::core::image::KewImage;
+use crate::core::memory::{KewAllocator, KewResourceKind};
+use crate::core::pipeline::{AttachmentFormats, PipelineTarget};
+use crate::core::queue::KewQueue;
+use crate::core::rendering::{self, RenderingAttachment, RenderingMode};
+use crate::core::sync::{KewFence, KewSemaphore, SyncPoint};
 use crate::core::{PREFERRED_SURFACE_COLORS, PREFERRED_SURFACE_FORMAT};
 use ash::khr::{surface, swapchain};
 use ash::vk;
 use log;
-use log::{debug, warn};
+use log::{debug, error, warn};
+use std::time::Duration;
 
 pub const MAX_IN_FLIGHT_FRAMES: usize = 2;
 
 type FrameAttachment = (vk::Image, vk::ImageView);
 struct KewFrameBundle<'a> {
     kew_device: &'a KewDevice,
+    /// Null with dynamic rendering, the attachments are then transient images of the graph.
     framebuffer: vk::Framebuffer,
     swapchain_attachment: FrameAttachment,
+    _depth_attachment: Option<KewImage<'a>>,
+    _msaa_attachment: Option<KewImage<'a>>,
+}
+
+impl KewFrameBundle<'_> {
+    fn swapchain_attachment(&self, format: vk::Format) -> RenderingAttachment {
+        RenderingAttachment {
+            image: self.swapchain_attachment.0,
+            view: self.swapchain_attachment.1,
+            format,
+        }
+    }
 }
 
 impl Drop for KewFrameBundle<'_> {
@@ -27,100 +51,87 @@ impl Drop for KewFrameBundle<'_> {
 
 pub struct KewSwapchain<'a> {
     kew_device: &'a KewDevice,
-    present_queue: vk::Queue,
+    allocator: &'a KewAllocator<'a>,
+    present_queue: KewQueue<'a>,
+    surface_loader: surface::Instance,
+    surface: vk::SurfaceKHR,
+    surface_format: vk::SurfaceFormatKHR,
     swapchain_loader: swapchain::Device,
     swapchain: vk::SwapchainKHR,
     swapchain_extent: vk::Extent2D,
     frame_bundles: Vec<KewFrameBundle<'a>>,
-    image_available_semaphores: [vk::Semaphore; MAX_IN_FLIGHT_FRAMES],
-    render_finished_semaphores: [vk::Semaphore; MAX_IN_FLIGHT_FRAMES],
-    frame_in_flight_fences: [vk::Fence; MAX_IN_FLIGHT_FRAMES],
+    image_available_semaphores: Vec<KewSemaphore<'a>>,
+    render_finished_semaphores: Vec<KewSemaphore<'a>>,
+    frame_in_flight_fences: Vec<KewFence<'a>>,
     pub image_format: vk::Format,
+    pub depth_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
     pub render_pass: vk::RenderPass,
 }
 
 impl<'a> KewSwapchain<'a> {
     pub fn new(
         kew_device: &'a KewDevice,
+        allocator: &'a KewAllocator<'a>,
         surface_loader: &surface::Instance,
         surface: vk::SurfaceKHR,
         window_extent: vk::Extent2D,
         prs_queue_idx: u32,
-    ) -> Self {
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self, KewError> {
         unsafe {
+            let samples = kew_device.clamp_sample_count(samples);
             let surface_format =
-                Self::pick_surface_format(&kew_device.context, surface_loader, surface);
-            let render_pass = Self::create_render_pass(&kew_device, surface_format.format);
-            let capabilities = surface_loader
-                .get_physical_device_surface_capabilities(kew_device.context.physical, surface)
-                .unwrap();
-            let swapchain_extent = match capabilities.current_extent.width {
-                u32::MAX => window_extent,
-                _ => capabilities.current_extent,
-            };
-            let present_mode = surface_loader
-                .get_physical_device_surface_present_modes(kew_device.context.physical, surface)
-                .unwrap()
-                .iter()
-                .map(|pm| *pm)
-                .find(|present_mode| *present_mode == vk::PresentModeKHR::IMMEDIATE)
-                .unwrap_or_else(|| {
-                    warn!("desired present mode unavailable (default FIFO)");
-                    vk::PresentModeKHR::FIFO
-                });
-            let present_queue = kew_device.get_device_queue(prs_queue_idx, 0);
-
-
-            let create_info = vk::SwapchainCreateInfoKHR::default()
-                .surface(surface)
-                .min_image_count(capabilities.min_image_count + 1)
-                .image_color_space(surface_format.color_space)
-                .image_format(surface_format.format)
-                .image_extent(swapchain_extent)
-                .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-                .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
-                .pre_transform(capabilities.current_transform)
-                .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-                .present_mode(present_mode)
-                .clipped(true)
-                .image_array_layers(1);
+                Self::pick_surface_format(&kew_device.context, surface_loader, surface)?;
+            let depth_format = kew_device.find_depth_format()?;
+            let render_pass = Self::create_render_pass(
+                &kew_device,
+                surface_format.format,
+                depth_format,
+                samples,
+            )?;
+            let present_queue = KewQueue::new(kew_device, prs_queue_idx);
+
             let swapchain_loader = swapchain::Device::new(&kew_device.context.instance, kew_device);
-            let swapchain = swapchain_loader
-                .create_swapchain(&create_info, None)
-                .expect("failed to create swapchain");
+            let (swapchain, swapchain_extent) = Self::create_swapchain(
+                &kew_device.context,
+                surface_loader,
+                surface,
+                surface_format,
+                &swapchain_loader,
+                window_extent,
+                vk::SwapchainKHR::null(),
+            )?;
 
             let frame_bundles = Self::create_frame_bundles(
                 &kew_device,
+                allocator,
                 swapchain_extent,
                 &swapchain_loader,
                 swapchain,
                 surface_format.format,
+                depth_format,
+                samples,
                 render_pass,
-            );
-
-            let mut image_available_semaphores: [vk::Semaphore; MAX_IN_FLIGHT_FRAMES] =
-                [vk::Semaphore::null(); MAX_IN_FLIGHT_FRAMES];
-            let mut render_finished_semaphores: [vk::Semaphore; MAX_IN_FLIGHT_FRAMES] =
-                [vk::Semaphore::null(); MAX_IN_FLIGHT_FRAMES];
-            let semaphore_info = vk::SemaphoreCreateInfo::default();
-            let mut frame_in_flight_fences: [vk::Fence; MAX_IN_FLIGHT_FRAMES] =
-                [vk::Fence::null(); MAX_IN_FLIGHT_FRAMES];
-            let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
-            for i in 0..MAX_IN_FLIGHT_FRAMES {
-                image_available_semaphores[i] = kew_device
-                    .create_semaphore(&semaphore_info, None)
-                    .expect("failed to create image available semaphore");
-                render_finished_semaphores[i] = kew_device
-                    .create_semaphore(&semaphore_info, None)
-                    .expect("failed to create render finished semaphore");
-                frame_in_flight_fences[i] = kew_device
-                    .create_fence(&fence_info, None)
-                    .expect("failed to create frame in flight fences");
+            )?;
+
+            let mut image_available_semaphores = Vec::with_capacity(MAX_IN_FLIGHT_FRAMES);
+            let mut render_finished_semaphores = Vec::with_capacity(MAX_IN_FLIGHT_FRAMES);
+            let mut frame_in_flight_fences = Vec::with_capacity(MAX_IN_FLIGHT_FRAMES);
+            for _ in 0..MAX_IN_FLIGHT_FRAMES {
+                image_available_semaphores.push(KewSemaphore::new(kew_device)?);
+                render_finished_semaphores.push(KewSemaphore::new(kew_device)?);
+                // signaled so the first wait on a frame that was never submitted passes
+                frame_in_flight_fences.push(KewFence::new(kew_device, true)?);
             }
 
-            Self {
+            Ok(Self {
                 kew_device,
+                allocator,
                 present_queue,
+                surface_loader: surface_loader.clone(),
+                surface,
+                surface_format,
                 swapchain_loader,
                 swapchain,
                 swapchain_extent,
@@ -129,96 +140,256 @@ impl<'a> KewSwapchain<'a> {
                 render_finished_semaphores,
                 frame_in_flight_fences,
                 image_format: surface_format.format,
+                depth_format,
+                samples,
                 render_pass,
-            }
+            })
         }
     }
 
-    pub unsafe fn begin_render_pass(&self, cmd_buffer: vk::CommandBuffer, image_idx: usize) {
-        let clear_vals = [vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.01, 0.01, 0.01, 0.01],
+    /// Rebuilds the swapchain and its frame bundles for a new window extent. The old
+    /// swapchain handle is passed on to the driver so presentation resources can be reused.
+    pub unsafe fn recreate(&mut self, window_extent: vk::Extent2D) -> Result<(), KewError> {
+        self.kew_device.device_wait_idle()?;
+        self.frame_bundles.clear();
+
+        let old_swapchain = self.swapchain;
+        let (swapchain, swapchain_extent) = Self::create_swapchain(
+            &self.kew_device.context,
+            &self.surface_loader,
+            self.surface,
+            self.surface_format,
+            &self.swapchain_loader,
+            window_extent,
+            old_swapchain,
+        )?;
+        self.swapchain_loader.destroy_swapchain(old_swapchain, None);
+
+        self.swapchain = swapchain;
+        self.swapchain_extent = swapchain_extent;
+        self.frame_bundles = Self::create_frame_bundles(
+            self.kew_device,
+            self.allocator,
+            swapchain_extent,
+            &self.swapchain_loader,
+            swapchain,
+            self.surface_format.format,
+            self.depth_format,
+            self.samples,
+            self.render_pass,
+        )?;
+        debug!(
+            "recreated swapchain ({}x{})",
+            swapchain_extent.width, swapchain_extent.height
+        );
+        Ok(())
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.swapchain_extent
+    }
+
+    unsafe fn create_swapchain(
+        context: &KewContext,
+        surface_loader: &surface::Instance,
+        surface: vk::SurfaceKHR,
+        surface_format: vk::SurfaceFormatKHR,
+        swapchain_loader: &swapchain::Device,
+        window_extent: vk::Extent2D,
+        old_swapchain: vk::SwapchainKHR,
+    ) -> Result<(vk::SwapchainKHR, vk::Extent2D), KewError> {
+        let capabilities =
+            surface_loader.get_physical_device_surface_capabilities(context.physical, surface)?;
+        let swapchain_extent = match capabilities.current_extent.width {
+            u32::MAX => vk::Extent2D {
+                width: window_extent.width.clamp(
+                    capabilities.min_image_extent.width,
+                    capabilities.max_image_extent.width,
+                ),
+                height: window_extent.height.clamp(
+                    capabilities.min_image_extent.height,
+                    capabilities.max_image_extent.height,
+                ),
             },
-        }];
-        let begin_info = vk::RenderPassBeginInfo::default()
-            .render_pass(self.render_pass)
-            .framebuffer(self.frame_bundles[image_idx].framebuffer)
-            .render_area(vk::Rect2D {
-                offset: vk::Offset2D::default(),
-                extent: self.swapchain_extent,
-            })
-            .clear_values(&clear_vals);
-        self.kew_device
-            .cmd_begin_render_pass(cmd_buffer, &begin_info, vk::SubpassContents::INLINE);
-
-        let viewport = vk::Viewport::default()
-            .width(self.swapchain_extent.width as f32)
-            .height(self.swapchain_extent.height as f32);
-        let scissor = vk::Rect2D::default().extent(self.swapchain_extent);
-        self.kew_device.cmd_set_viewport(cmd_buffer, 0, &[viewport]);
-        self.kew_device.cmd_set_scissor(cmd_buffer, 0, &[scissor]);
+            _ => capabilities.current_extent,
+        };
+        let present_mode = surface_loader
+            .get_physical_device_surface_present_modes(context.physical, surface)?
+            .iter()
+            .map(|pm| *pm)
+            .find(|present_mode| *present_mode == vk::PresentModeKHR::IMMEDIATE)
+            .unwrap_or_else(|| {
+                warn!("desired present mode unavailable (default FIFO)");
+                vk::PresentModeKHR::FIFO
+            });
+
+        let create_info = vk::SwapchainCreateInfoKHR::default()
+            .surface(surface)
+            .min_image_count(capabilities.min_image_count + 1)
+            .image_color_space(surface_format.color_space)
+            .image_format(surface_format.format)
+            .image_extent(swapchain_extent)
+            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .pre_transform(capabilities.current_transform)
+            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .present_mode(present_mode)
+            .clipped(true)
+            .image_array_layers(1)
+            .old_swapchain(old_swapchain);
+        let swapchain = swapchain_loader.create_swapchain(&create_info, None)?;
+        Ok((swapchain, swapchain_extent))
     }
 
-    pub unsafe fn end_render_pass(&self, cmd_buffer: vk::CommandBuffer) {
-        self.kew_device.cmd_end_render_pass(cmd_buffer);
+    /// What pipelines drawing into the swapchain are created for: the attachment formats on
+    /// devices with dynamic rendering, the render pass otherwise.
+    pub fn pipeline_target(&self) -> PipelineTarget {
+        match self.kew_device.dynamic_rendering {
+            true => PipelineTarget::DYNAMIC(AttachmentFormats {
+                color: vec![self.image_format],
+                depth: Some(self.depth_format),
+            }),
+            false => PipelineTarget::PASS(self.render_pass),
+        }
     }
 
+    /// Imports swapchain image `image_idx` into `graph`, exported for presentation. Its contents
+    /// are discarded, the acquire semaphore is waited for before color output.
+    pub fn import_image<'g>(
+        &self,
+        graph: &mut KewRenderGraph<'g, 'a>,
+        image_idx: usize,
+    ) -> GraphImage {
+        let image = graph.import_external_image(
+            "swapchain",
+            ExternalImage {
+                attachment: self.frame_bundles[image_idx].swapchain_attachment(self.image_format),
+                layout: vk::ImageLayout::UNDEFINED,
+                stages: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            },
+        );
+        graph.export_image(image, ImageAccess::PRESENT);
+        image
+    }
+
+    /// Adds a pass drawing into swapchain image `image_idx` through `record` to `graph`, in the
+    /// render pass or with dynamic rendering depending on `pipeline_target`. With dynamic
+    /// rendering the depth (and multisampled color) attachments are transient images of the graph.
+    pub fn add_pass<'g>(
+        &'g self,
+        graph: &mut KewRenderGraph<'g, 'a>,
+        image_idx: usize,
+        name: &'static str,
+        record: impl FnOnce(vk::CommandBuffer) + 'g,
+    ) {
+        let kew_device = self.kew_device;
+        let extent = self.swapchain_extent;
+        let swapchain_image = self.import_image(graph, image_idx);
+        if !kew_device.dynamic_rendering {
+            let mode = RenderingMode::PASS {
+                render_pass: self.render_pass,
+                framebuffer: self.frame_bundles[image_idx].framebuffer,
+                clear_values: rendering::clear_values(1, true),
+            };
+            graph
+                .add_pass(name)
+                .image(swapchain_image, ImageAccess::COLOR)
+                .record(move |cmd_buffer, _| unsafe {
+                    rendering::record_rendering(kew_device, cmd_buffer, extent, mode, record);
+                    Ok(())
+                });
+            return;
+        }
+
+        let depth = graph.create_image(
+            "depth",
+            TransientImageInfo {
+                extent,
+                format: self.depth_format,
+                samples: self.samples,
+            },
+        );
+        let msaa = (self.samples != vk::SampleCountFlags::TYPE_1).then(|| {
+            graph.create_image(
+                "msaa color",
+                TransientImageInfo {
+                    extent,
+                    format: self.image_format,
+                    samples: self.samples,
+                },
+            )
+        });
+        let mut pass = graph
+            .add_pass(name)
+            .image(swapchain_image, ImageAccess::COLOR)
+            .image(depth, ImageAccess::DEPTH);
+        if let Some(msaa) = msaa {
+            pass = pass.image(msaa, ImageAccess::COLOR);
+        }
+        pass.record(move |cmd_buffer, resources| unsafe {
+            let color_attachment = match msaa {
+                Some(msaa) => (
+                    resources.image(msaa)?,
+                    Some(resources.image(swapchain_image)?),
+                ),
+                None => (resources.image(swapchain_image)?, None),
+            };
+            let mode = RenderingMode::DYNAMIC {
+                color_attachments: vec![color_attachment],
+                depth_attachment: Some(resources.image(depth)?),
+            };
+            rendering::record_rendering(kew_device, cmd_buffer, extent, mode, record);
+            Ok(())
+        });
+    }
+
+    /// Returns `Ok(true)` if the swapchain is suboptimal for the surface.
     pub unsafe fn submit_and_present(
         &self,
         cmd_buffer: vk::CommandBuffer,
         image_idx: usize,
         frame_idx: usize,
-        gfx_queue: &vk::Queue,
-    ) {
-        let wait_semaphores = [self.image_available_semaphores[frame_idx]];
-        let ping_semaphores = [self.render_finished_semaphores[frame_idx]];
-        let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let cmd_buffers = [cmd_buffer];
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&wait_stages)
-            .command_buffers(&cmd_buffers)
-            .signal_semaphores(&ping_semaphores);
-
-        self.kew_device
-            .reset_fences(&[self.frame_in_flight_fences[frame_idx]])
-            .unwrap();
-        self.kew_device
-            .queue_submit(
-                *gfx_queue,
-                &[submit_info],
-                self.frame_in_flight_fences[frame_idx],
-            )
-            .unwrap();
+        gfx_queue: &KewQueue,
+    ) -> Result<bool, KewError> {
+        gfx_queue.submit_with_fence(
+            &[cmd_buffer],
+            &[(
+                SyncPoint::BINARY(&self.image_available_semaphores[frame_idx]),
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            )],
+            &[SyncPoint::BINARY(&self.render_finished_semaphores[frame_idx])],
+            &self.frame_in_flight_fences[frame_idx],
+        )?;
 
         let swapchains = [self.swapchain];
         let image_idxs = [image_idx as u32];
-        let wait_semaphores = [self.render_finished_semaphores[frame_idx]];
+        let wait_semaphores = [*self.render_finished_semaphores[frame_idx]];
         let present_info = vk::PresentInfoKHR::default()
             .wait_semaphores(&wait_semaphores)
             .swapchains(&swapchains)
             .image_indices(&image_idxs);
-        self.swapchain_loader
-            .queue_present(self.present_queue, &present_info)
-            .expect("failed to present swapchain image");
+        Ok(self
+            .swapchain_loader
+            .queue_present(*self.present_queue, &present_info)?)
     }
 
-    pub unsafe fn frame_in_use(&self, frame_idx: usize) -> bool {
-        !self
-            .kew_device
-            .get_fence_status(self.frame_in_flight_fences[frame_idx])
-            .unwrap()
+    pub unsafe fn frame_in_use(&self, frame_idx: usize) -> Result<bool, KewError> {
+        Ok(!self.frame_in_flight_fences[frame_idx].is_signaled()?)
     }
 
+    #[allow(clippy::too_many_arguments)]
     unsafe fn create_frame_bundles(
         kew_device: &'a KewDevice,
+        allocator: &'a KewAllocator<'a>,
         swapchain_extent: vk::Extent2D,
         swapchain_loader: &swapchain::Device,
         swapchain: vk::SwapchainKHR,
         image_format: vk::Format,
+        depth_format: vk::Format,
+        samples: vk::SampleCountFlags,
         render_pass: vk::RenderPass,
-    ) -> Vec<KewFrameBundle<'a>> {
-        let swapchain_images = swapchain_loader.get_swapchain_images(swapchain).unwrap();
+    ) -> Result<Vec<KewFrameBundle<'a>>, KewError> {
+        let swapchain_images = swapchain_loader.get_swapchain_images(swapchain)?;
         let swapchain_views = swapchain_images
             .iter()
             .map(|image| {
@@ -233,87 +404,219 @@ impl<'a> KewSwapchain<'a> {
                         base_array_layer: 0,
                         layer_count: 1,
                     });
-                kew_device.create_image_view(&create_info, None).unwrap()
+                kew_device.create_image_view(&create_info, None)
             })
-            .collect::<Vec<vk::ImageView>>();
+            .collect::<Result<Vec<vk::ImageView>, vk::Result>>()?;
 
         let mut framebundles: Vec<KewFrameBundle> = Vec::with_capacity(swapchain_images.len());
         for i in 0..swapchain_images.len() {
-            let attachments = [swapchain_views[i]];
+            if kew_device.dynamic_rendering {
+                framebundles.push(KewFrameBundle {
+                    kew_device,
+                    framebuffer: vk::Framebuffer::null(),
+                    swapchain_attachment: (swapchain_images[i], swapchain_views[i]),
+                    _depth_attachment: None,
+                    _msaa_attachment: None,
+                });
+                continue;
+            }
+            let depth_attachment = Self::create_attachment(
+                kew_device,
+                allocator,
+                swapchain_extent,
+                depth_format,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                samples,
+            )?;
+            // multisampled color is rendered first and resolved into the swapchain image
+            let (attachments, msaa_attachment) = match samples {
+                vk::SampleCountFlags::TYPE_1 => {
+                    (vec![swapchain_views[i], depth_attachment.view.unwrap()], None)
+                }
+                _ => {
+                    let msaa_attachment = Self::create_attachment(
+                        kew_device,
+                        allocator,
+                        swapchain_extent,
+                        image_format,
+                        vk::ImageUsageFlags::COLOR_ATTACHMENT
+                            | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                        samples,
+                    )?;
+                    let attachments = vec![
+                        msaa_attachment.view.unwrap(),
+                        depth_attachment.view.unwrap(),
+                        swapchain_views[i],
+                    ];
+                    (attachments, Some(msaa_attachment))
+                }
+            };
             let create_info = vk::FramebufferCreateInfo::default()
                 .render_pass(render_pass)
                 .attachments(&attachments)
                 .width(swapchain_extent.width)
                 .height(swapchain_extent.height)
                 .layers(1);
-            let framebuffer = kew_device.create_framebuffer(&create_info, None).unwrap();
+            let framebuffer = kew_device.create_framebuffer(&create_info, None)?;
             framebundles.push(KewFrameBundle {
                 kew_device,
                 framebuffer,
                 swapchain_attachment: (swapchain_images[i], swapchain_views[i]),
+                _depth_attachment: Some(depth_attachment),
+                _msaa_attachment: msaa_attachment,
             });
         }
-        framebundles
+        Ok(framebundles)
+    }
+
+    fn create_attachment(
+        kew_device: &'a KewDevice,
+        allocator: &'a KewAllocator<'a>,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        usage: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags,
+    ) -> Result<KewImage<'a>, KewError> {
+        let mut image = KewImage::with_samples(
+            kew_device,
+            extent.width,
+            extent.height,
+            format,
+            extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4,
+            usage,
+            samples,
+        )?;
+        image.bind_memory(allocator.allocate(
+            &image.get_memory_requirements(),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            KewResourceKind::OPTIMAL,
+        )?)?;
+        image.recreate_image_view()?;
+        Ok(image)
     }
 
     unsafe fn create_render_pass(
         kew_device: &KewDevice,
         swapchain_image_format: vk::Format,
-    ) -> vk::RenderPass {
+        depth_format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Result<vk::RenderPass, KewError> {
+        let multisampled = samples != vk::SampleCountFlags::TYPE_1;
         let color_attachment = vk::AttachmentDescription::default()
             .format(swapchain_image_format)
-            .samples(vk::SampleCountFlags::TYPE_1)
+            .samples(samples)
             .load_op(vk::AttachmentLoadOp::CLEAR)
-            .store_op(vk::AttachmentStoreOp::STORE)
+            .store_op(match multisampled {
+                true => vk::AttachmentStoreOp::DONT_CARE,
+                false => vk::AttachmentStoreOp::STORE,
+            })
             .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
             .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(match multisampled {
+                true => vk::ImageLayout::UNDEFINED,
+                false => vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let depth_attachment = vk::AttachmentDescription::default()
+            .format(depth_format)
+            .samples(samples)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
             .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
-        let attachments = [color_attachment];
+            .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        let resolve_attachment = vk::AttachmentDescription::default()
+            .format(swapchain_image_format)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+        let attachments = match multisampled {
+            true => vec![color_attachment, depth_attachment, resolve_attachment],
+            false => vec![color_attachment, depth_attachment],
+        };
 
         let color_attachment_ref = vk::AttachmentReference::default()
             .attachment(0)
             .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
         let attachment_refs = [color_attachment_ref];
+        let depth_attachment_ref = vk::AttachmentReference::default()
+            .attachment(1)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+        let resolve_attachment_refs = [vk::AttachmentReference::default()
+            .attachment(2)
+            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
 
-        let subpass = vk::SubpassDescription::default()
+        let mut subpass = vk::SubpassDescription::default()
             .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            .color_attachments(&attachment_refs);
+            .color_attachments(&attachment_refs)
+            .depth_stencil_attachment(&depth_attachment_ref);
+        if multisampled {
+            subpass = subpass.resolve_attachments(&resolve_attachment_refs);
+        }
         let subpasses = [subpass];
 
+        // the render graph moves the swapchain image in and out of COLOR_ATTACHMENT_OPTIMAL,
+        // wait for earlier depth and multisampled color writes before clearing
+        let dependencies = [vk::SubpassDependency::default()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+            )
+            .dst_stage_mask(
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+                    | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS,
+            )
+            .src_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )
+            .dst_access_mask(
+                vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                    | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            )];
+
         let create_info = vk::RenderPassCreateInfo::default()
             .attachments(&attachments)
-            .subpasses(&subpasses);
-        kew_device.create_render_pass(&create_info, None).unwrap()
+            .subpasses(&subpasses)
+            .dependencies(&dependencies);
+        Ok(kew_device.create_render_pass(&create_info, None)?)
     }
 
     unsafe fn pick_surface_format(
         context: &KewContext,
         surface_loader: &surface::Instance,
         surface: vk::SurfaceKHR,
-    ) -> vk::SurfaceFormatKHR {
-        let formats = surface_loader
-            .get_physical_device_surface_formats(context.physical, surface)
-            .unwrap();
-        let format = formats
-            .iter()
-            .find(|surface_format| {
-                surface_format.format == PREFERRED_SURFACE_FORMAT
-                    && surface_format.color_space == PREFERRED_SURFACE_COLORS
-            })
-            .unwrap_or_else(|| {
+    ) -> Result<vk::SurfaceFormatKHR, KewError> {
+        let formats =
+            surface_loader.get_physical_device_surface_formats(context.physical, surface)?;
+        let format = match formats.iter().find(|surface_format| {
+            surface_format.format == PREFERRED_SURFACE_FORMAT
+                && surface_format.color_space == PREFERRED_SURFACE_COLORS
+        }) {
+            Some(format) => format,
+            None => {
                 warn!("did not find desired surface format (defaulting to first enumerated)");
-                formats.first().unwrap()
-            });
+                formats
+                    .first()
+                    .ok_or(KewError::UNSUPPORTED("surface without formats"))?
+            }
+        };
         debug!("surface format: {:?}", format);
-        *format
+        Ok(*format)
     }
 
     pub unsafe fn next_image_idx(&self, frame_idx: usize) -> Result<(u32, bool), vk::Result> {
         self.swapchain_loader.acquire_next_image(
             self.swapchain,
             u64::MAX,
-            self.image_available_semaphores[frame_idx],
+            *self.image_available_semaphores[frame_idx],
             vk::Fence::null(),
         )
     }
@@ -323,23 +626,18 @@ impl Drop for KewSwapchain<'_> {
     fn drop(&mut self) {
         debug!("dropping KewSwapchain");
         unsafe {
-            self.kew_device
-                .wait_for_fences(&self.frame_in_flight_fences, true, u64::MAX)
-                .unwrap();
-            self.frame_in_flight_fences
-                .iter()
-                .for_each(|fence| self.kew_device.destroy_fence(*fence, None));
+            for fence in &self.frame_in_flight_fences {
+                if let Err(e) = fence.wait(Duration::MAX) {
+                    error!(
+                        "failed waiting for frame in flight: {} (destroying anyway)",
+                        e
+                    );
+                }
+            }
 
             self.swapchain_loader
                 .destroy_swapchain(self.swapchain, None);
             self.kew_device.destroy_render_pass(self.render_pass, None);
-
-            self.image_available_semaphores
-                .iter()
-                .for_each(|s| self.kew_device.destroy_semaphore(*s, None));
-            self.render_finished_semaphores
-                .iter()
-                .for_each(|s| self.kew_device.destroy_semaphore(*s, None));
         }
     }
 }
@@ -0,0 +1,206 @@
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use ash::vk;
+use log::debug;
+use std::ops::Deref;
+use std::time::Duration;
+
+/// Signaled by the queue when a submission has completed.
+pub struct KewFence<'a> {
+    kew_device: &'a KewDevice,
+    vk_fence: vk::Fence,
+}
+
+impl<'a> KewFence<'a> {
+    /// `signaled` fences pass their first wait, e.g. for frames that were never submitted.
+    pub fn new(kew_device: &'a KewDevice, signaled: bool) -> Result<Self, KewError> {
+        let flags = match signaled {
+            true => vk::FenceCreateFlags::SIGNALED,
+            false => vk::FenceCreateFlags::empty(),
+        };
+        let create_info = vk::FenceCreateInfo::default().flags(flags);
+        let vk_fence = unsafe { kew_device.create_fence(&create_info, None)? };
+        Ok(Self {
+            kew_device,
+            vk_fence,
+        })
+    }
+
+    /// Blocks for at most `timeout`, returns whether the fence is signaled.
+    pub fn wait(&self, timeout: Duration) -> Result<bool, KewError> {
+        let timeout = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        match unsafe {
+            self.kew_device
+                .wait_for_fences(&[self.vk_fence], true, timeout)
+        } {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Returns whether the fence is signaled without blocking.
+    pub fn is_signaled(&self) -> Result<bool, KewError> {
+        Ok(unsafe { self.kew_device.get_fence_status(self.vk_fence)? })
+    }
+
+    pub fn reset(&self) -> Result<(), KewError> {
+        unsafe { self.kew_device.reset_fences(&[self.vk_fence])? };
+        Ok(())
+    }
+}
+
+impl Deref for KewFence<'_> {
+    type Target = vk::Fence;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_fence
+    }
+}
+
+impl Drop for KewFence<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewFence");
+        unsafe {
+            self.kew_device.destroy_fence(self.vk_fence, None);
+        }
+    }
+}
+
+/// Binary semaphore ordering submissions and presentation on the device.
+pub struct KewSemaphore<'a> {
+    kew_device: &'a KewDevice,
+    vk_semaphore: vk::Semaphore,
+}
+
+impl<'a> KewSemaphore<'a> {
+    pub fn new(kew_device: &'a KewDevice) -> Result<Self, KewError> {
+        let create_info = vk::SemaphoreCreateInfo::default();
+        let vk_semaphore = unsafe { kew_device.create_semaphore(&create_info, None)? };
+        Ok(Self {
+            kew_device,
+            vk_semaphore,
+        })
+    }
+}
+
+impl Deref for KewSemaphore<'_> {
+    type Target = vk::Semaphore;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_semaphore
+    }
+}
+
+impl Drop for KewSemaphore<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewSemaphore");
+        unsafe {
+            self.kew_device.destroy_semaphore(self.vk_semaphore, None);
+        }
+    }
+}
+
+/// Timeline semaphore, a counter that submissions and the host signal with increasing values.
+/// Any number of submissions on any queue can wait for a value, e.g. compute work waiting for
+/// upload `N` on the transfer queue.
+pub struct KewTimeline<'a> {
+    kew_device: &'a KewDevice,
+    vk_semaphore: vk::Semaphore,
+}
+
+impl<'a> KewTimeline<'a> {
+    /// Fails with `UNSUPPORTED` if the device has no timeline semaphores, see
+    /// `KewDevice::timeline_semaphores`.
+    pub fn new(kew_device: &'a KewDevice, initial_value: u64) -> Result<Self, KewError> {
+        if !kew_device.timeline_semaphores {
+            return Err(KewError::UNSUPPORTED("timeline semaphores"));
+        }
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+        let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+        let vk_semaphore = unsafe { kew_device.create_semaphore(&create_info, None)? };
+        Ok(Self {
+            kew_device,
+            vk_semaphore,
+        })
+    }
+
+    /// Last value signaled.
+    pub fn value(&self) -> Result<u64, KewError> {
+        Ok(unsafe {
+            self.kew_device
+                .get_semaphore_counter_value(self.vk_semaphore)?
+        })
+    }
+
+    /// Blocks for at most `timeout`, returns whether the timeline reached `value`.
+    pub fn wait(&self, value: u64, timeout: Duration) -> Result<bool, KewError> {
+        let timeout = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        let semaphores = [self.vk_semaphore];
+        let values = [value];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        match unsafe { self.kew_device.wait_semaphores(&wait_info, timeout) } {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Signals `value` from the host, it has to be greater than the current value.
+    pub fn signal(&self, value: u64) -> Result<(), KewError> {
+        let signal_info = vk::SemaphoreSignalInfo::default()
+            .semaphore(self.vk_semaphore)
+            .value(value);
+        unsafe { self.kew_device.signal_semaphore(&signal_info)? };
+        Ok(())
+    }
+
+    pub fn at(&self, value: u64) -> SyncPoint<'_> {
+        SyncPoint::TIMELINE(self, value)
+    }
+}
+
+impl Deref for KewTimeline<'_> {
+    type Target = vk::Semaphore;
+
+    fn deref(&self) -> &Self::Target {
+        &self.vk_semaphore
+    }
+}
+
+impl Drop for KewTimeline<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewTimeline");
+        unsafe {
+            self.kew_device.destroy_semaphore(self.vk_semaphore, None);
+        }
+    }
+}
+
+/// Semaphore a submission waits for or signals, timelines at a value.
+#[derive(Clone, Copy)]
+pub enum SyncPoint<'s> {
+    BINARY(&'s KewSemaphore<'s>),
+    TIMELINE(&'s KewTimeline<'s>, u64),
+}
+
+impl SyncPoint<'_> {
+    pub fn semaphore(&self) -> vk::Semaphore {
+        match self {
+            SyncPoint::BINARY(semaphore) => ***semaphore,
+            SyncPoint::TIMELINE(timeline, _) => ***timeline,
+        }
+    }
+
+    /// Value of timeline semaphores, ignored by binary ones.
+    pub fn value(&self) -> u64 {
+        match self {
+            SyncPoint::BINARY(_) => 0,
+            SyncPoint::TIMELINE(_, value) => *value,
+        }
+    }
+}
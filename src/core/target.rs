@@ -0,0 +1,361 @@
+use crate::core::buffer::KewBuffer;
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::graph::{GraphImage, ImageAccess, KewRenderGraph};
+use crate::core::image::KewImage;
+use crate::core::memory::{KewAllocator, KewResourceKind};
+use crate::core::pipeline::{AttachmentFormats, PipelineTarget};
+use crate::core::rendering::{self, RenderingMode};
+use ash::vk;
+use image::RgbaImage;
+use log::debug;
+
+/// Offscreen color (and optional depth) attachments with their own render pass and framebuffer,
+/// also usable with dynamic rendering. Both draw through passes of a `KewRenderGraph`, which
+/// moves the attachments in and out of attachment layouts.
+/// With more than one sample, rendering goes to multisampled images resolved into `color_images`.
+pub struct KewRenderTarget<'a> {
+    kew_device: &'a KewDevice,
+    framebuffer: vk::Framebuffer,
+    msaa_images: Vec<KewImage<'a>>,
+    pub color_images: Vec<KewImage<'a>>,
+    pub depth_image: Option<KewImage<'a>>,
+    pub render_pass: vk::RenderPass,
+    pub extent: vk::Extent2D,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl<'a> KewRenderTarget<'a> {
+    pub fn new(
+        kew_device: &'a KewDevice,
+        allocator: &'a KewAllocator<'a>,
+        extent: vk::Extent2D,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Self, KewError> {
+        let samples = kew_device.clamp_sample_count(samples);
+        let image_b_size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+        let mut color_images = Vec::with_capacity(color_formats.len());
+        let mut msaa_images = Vec::new();
+        for format in color_formats {
+            color_images.push(Self::create_attachment(
+                kew_device,
+                allocator,
+                extent,
+                *format,
+                image_b_size,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::SAMPLED,
+                vk::SampleCountFlags::TYPE_1,
+            )?);
+            if samples != vk::SampleCountFlags::TYPE_1 {
+                msaa_images.push(Self::create_attachment(
+                    kew_device,
+                    allocator,
+                    extent,
+                    *format,
+                    image_b_size,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                    samples,
+                )?);
+            }
+        }
+        let depth_image = match depth_format {
+            Some(format) => Some(Self::create_attachment(
+                kew_device,
+                allocator,
+                extent,
+                format,
+                image_b_size,
+                vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+                samples,
+            )?),
+            None => None,
+        };
+
+        let render_pass =
+            unsafe { Self::create_render_pass(kew_device, color_formats, depth_format, samples)? };
+        // attachment order: rendered color, depth, resolved color
+        let attachments = match samples {
+            vk::SampleCountFlags::TYPE_1 => color_images
+                .iter()
+                .chain(depth_image.iter())
+                .map(|image| image.view.unwrap())
+                .collect::<Vec<_>>(),
+            _ => msaa_images
+                .iter()
+                .chain(depth_image.iter())
+                .chain(color_images.iter())
+                .map(|image| image.view.unwrap())
+                .collect::<Vec<_>>(),
+        };
+        let create_info = vk::FramebufferCreateInfo::default()
+            .render_pass(render_pass)
+            .attachments(&attachments)
+            .width(extent.width)
+            .height(extent.height)
+            .layers(1);
+        let framebuffer = unsafe {
+            match kew_device.create_framebuffer(&create_info, None) {
+                Ok(framebuffer) => framebuffer,
+                Err(e) => {
+                    kew_device.destroy_render_pass(render_pass, None);
+                    return Err(e.into());
+                }
+            }
+        };
+
+        Ok(Self {
+            kew_device,
+            framebuffer,
+            msaa_images,
+            color_images,
+            depth_image,
+            render_pass,
+            extent,
+            samples,
+        })
+    }
+
+    fn create_attachment(
+        kew_device: &'a KewDevice,
+        allocator: &'a KewAllocator<'a>,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        b_size: vk::DeviceSize,
+        usage: vk::ImageUsageFlags,
+        samples: vk::SampleCountFlags,
+    ) -> Result<KewImage<'a>, KewError> {
+        let mut image = KewImage::with_samples(
+            kew_device,
+            extent.width,
+            extent.height,
+            format,
+            b_size,
+            usage,
+            samples,
+        )?;
+        image.bind_memory(allocator.allocate(
+            &image.get_memory_requirements(),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            KewResourceKind::OPTIMAL,
+        )?)?;
+        image.recreate_image_view()?;
+        Ok(image)
+    }
+
+    unsafe fn create_render_pass(
+        kew_device: &KewDevice,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+        samples: vk::SampleCountFlags,
+    ) -> Result<vk::RenderPass, KewError> {
+        let multisampled = samples != vk::SampleCountFlags::TYPE_1;
+        let mut attachments = color_formats
+            .iter()
+            .map(|format| match multisampled {
+                true => vk::AttachmentDescription::default()
+                    .format(*format)
+                    .samples(samples)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+                false => vk::AttachmentDescription::default()
+                    .format(*format)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+            })
+            .collect::<Vec<_>>();
+        let color_refs = (0..color_formats.len())
+            .map(|idx| {
+                vk::AttachmentReference::default()
+                    .attachment(idx as u32)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            })
+            .collect::<Vec<_>>();
+        let depth_ref = vk::AttachmentReference::default()
+            .attachment(color_formats.len() as u32)
+            .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+        let mut subpass = vk::SubpassDescription::default()
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_refs);
+        if let Some(format) = depth_format {
+            attachments.push(
+                vk::AttachmentDescription::default()
+                    .format(format)
+                    .samples(samples)
+                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .stencil_load_op(vk::AttachmentLoadOp::CLEAR)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+                    .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL),
+            );
+            subpass = subpass.depth_stencil_attachment(&depth_ref);
+        }
+        let resolve_offset = attachments.len();
+        let resolve_refs = (0..color_formats.len())
+            .map(|idx| {
+                vk::AttachmentReference::default()
+                    .attachment((resolve_offset + idx) as u32)
+                    .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            })
+            .collect::<Vec<_>>();
+        if multisampled {
+            attachments.extend(color_formats.iter().map(|format| {
+                vk::AttachmentDescription::default()
+                    .format(*format)
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    .load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+                    .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+                    .initial_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+                    .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            }));
+            subpass = subpass.resolve_attachments(&resolve_refs);
+        }
+        let subpasses = [subpass];
+
+        // the render graph recording the pass synchronizes the attachments with other passes
+        let create_info = vk::RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&subpasses);
+        Ok(kew_device.create_render_pass(&create_info, None)?)
+    }
+
+    /// What pipelines drawing into the target are created for: the attachment formats on devices
+    /// with dynamic rendering, the render pass otherwise.
+    pub fn pipeline_target(&self) -> PipelineTarget {
+        match self.kew_device.dynamic_rendering {
+            true => PipelineTarget::DYNAMIC(AttachmentFormats {
+                color: self
+                    .color_images
+                    .iter()
+                    .map(|image| image.format())
+                    .collect(),
+                depth: self.depth_image.as_ref().map(|image| image.format()),
+            }),
+            false => PipelineTarget::PASS(self.render_pass),
+        }
+    }
+
+    /// Adds a pass drawing into the target through `record` to `graph`, in the render pass or
+    /// with dynamic rendering depending on `pipeline_target`. The attachments are cleared first.
+    /// Returns the color attachments holding the result, the resolved ones when multisampled.
+    pub fn add_pass<'g>(
+        &'g mut self,
+        graph: &mut KewRenderGraph<'g, 'a>,
+        name: &'static str,
+        record: impl FnOnce(vk::CommandBuffer) + 'g,
+    ) -> Vec<GraphImage> {
+        let kew_device = self.kew_device;
+        let (render_pass, framebuffer, extent) = (self.render_pass, self.framebuffer, self.extent);
+        let color = self
+            .color_images
+            .iter_mut()
+            .map(|image| graph.import_image("target color", image, None))
+            .collect::<Vec<_>>();
+        let msaa = self
+            .msaa_images
+            .iter_mut()
+            .map(|image| graph.import_image("target msaa color", image, None))
+            .collect::<Vec<_>>();
+        let depth = self
+            .depth_image
+            .as_mut()
+            .map(|image| graph.import_image("target depth", image, None));
+
+        let mut pass = graph.add_pass(name);
+        for image in color.iter().chain(&msaa) {
+            pass = pass.image(*image, ImageAccess::COLOR);
+        }
+        if let Some(depth) = depth {
+            pass = pass.image(depth, ImageAccess::DEPTH);
+        }
+        let resolved = color.clone();
+        pass.record(move |cmd_buffer, resources| unsafe {
+            let mode = match kew_device.dynamic_rendering {
+                true => RenderingMode::DYNAMIC {
+                    color_attachments: match msaa.is_empty() {
+                        true => color
+                            .iter()
+                            .map(|image| Ok((resources.image(*image)?, None)))
+                            .collect::<Result<Vec<_>, KewError>>()?,
+                        false => msaa
+                            .iter()
+                            .zip(&color)
+                            .map(|(image, resolve)| {
+                                Ok((resources.image(*image)?, Some(resources.image(*resolve)?)))
+                            })
+                            .collect::<Result<Vec<_>, KewError>>()?,
+                    },
+                    depth_attachment: depth.map(|image| resources.image(image)).transpose()?,
+                },
+                false => RenderingMode::PASS {
+                    render_pass,
+                    framebuffer,
+                    clear_values: rendering::clear_values(color.len(), depth.is_some()),
+                },
+            };
+            rendering::record_rendering(kew_device, cmd_buffer, extent, mode, record);
+            Ok(())
+        });
+        resolved
+    }
+
+    /// Host visible buffer large enough to hold one color attachment.
+    pub fn create_readback_buffer(
+        &self,
+        allocator: &'a KewAllocator<'a>,
+    ) -> Result<KewBuffer<'a>, KewError> {
+        let b_size = self.extent.width as vk::DeviceSize * self.extent.height as vk::DeviceSize * 4;
+        let mut buffer =
+            KewBuffer::new(self.kew_device, b_size, vk::BufferUsageFlags::TRANSFER_DST)?;
+        buffer.bind_memory(allocator.allocate(
+            &buffer.get_memory_requirements(),
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            KewResourceKind::LINEAR,
+        )?)?;
+        Ok(buffer)
+    }
+
+    /// Reads a finished readback into an image, swizzling BGRA attachments to RGBA.
+    pub fn read_image(&self, attachment: usize, buffer: &KewBuffer) -> Result<RgbaImage, KewError> {
+        let mut result = RgbaImage::new(self.extent.width, self.extent.height);
+        let b_size = self.extent.width as vk::DeviceSize * self.extent.height as vk::DeviceSize * 4;
+        match self.color_images[attachment].format() {
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => unsafe {
+                buffer.rd_visible_mem(&mut *result, b_size, 0);
+            },
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => unsafe {
+                buffer.rd_visible_mem(&mut *result, b_size, 0);
+                result.pixels_mut().for_each(|pixel| pixel.0.swap(0, 2));
+            },
+            _ => return Err(KewError::UNSUPPORTED("readback of non 8-bit rgba format")),
+        }
+        Ok(result)
+    }
+}
+
+impl Drop for KewRenderTarget<'_> {
+    fn drop(&mut self) {
+        debug!("dropping KewRenderTarget");
+        unsafe {
+            self.kew_device.destroy_framebuffer(self.framebuffer, None);
+            self.kew_device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::core::buffer::KewBuffer;
+use crate::core::command::KewCommandPool;
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::image::{mip_level_count, KewImage};
+use crate::core::memory::{KewAllocator, KewResourceKind};
+use crate::core::transfer::KewTransfer;
+use ash::vk;
+use image::DynamicImage;
+use log::{debug, warn};
+use std::borrow::Cow;
+
+/// How the texel values of a texture are encoded. Color textures (base color, emissive) are
+/// authored in sRGB and decoded by the sampler, data textures (normals, roughness) are linear.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureEncoding {
+    SRGB,
+    LINEAR,
+}
+
+impl TextureEncoding {
+    pub fn format(self) -> vk::Format {
+        match self {
+            TextureEncoding::SRGB => vk::Format::R8G8B8A8_SRGB,
+            TextureEncoding::LINEAR => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+/// Uploads `image` as an 8 bit RGBA texture with a full mip chain through a staging buffer and
+/// leaves it ready for sampling in fragment shaders. The copy runs on the transfer queue, the mip
+/// chain is generated on the queue of `cmd_pool`, which has to support graphics. The mip chain
+/// is skipped if the device cannot blit the format with linear filtering.
+pub fn upload_texture<'a>(
+    kew_device: &'a KewDevice,
+    allocator: &'a KewAllocator<'a>,
+    transfer: &mut KewTransfer,
+    cmd_pool: &KewCommandPool,
+    image: &DynamicImage,
+    encoding: TextureEncoding,
+) -> Result<KewImage<'a>, KewError> {
+    let rgba = match image {
+        DynamicImage::ImageRgba8(rgba) => Cow::Borrowed(rgba),
+        image => Cow::Owned(image.to_rgba8()),
+    };
+    let pixels = rgba.as_raw();
+    let format = encoding.format();
+    let mip_levels = match kew_device.find_supported_format(
+        &[format],
+        vk::ImageTiling::OPTIMAL,
+        vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+    ) {
+        Some(_) => mip_level_count(rgba.width(), rgba.height()),
+        None => {
+            warn!(
+                "{:?} does not support linear blits (mipmaps skipped)",
+                format
+            );
+            1
+        }
+    };
+
+    let mut staging_buffer = KewBuffer::new(
+        kew_device,
+        pixels.len() as u64,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+    )?;
+    let allocation = allocator.allocate(
+        &staging_buffer.get_memory_requirements(),
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        KewResourceKind::LINEAR,
+    )?;
+    staging_buffer.bind_memory(allocation)?;
+    unsafe { staging_buffer.wr_visible_mem(pixels, pixels.len() as u64, 0) };
+
+    let mut texture = KewImage::with_mip_levels(
+        kew_device,
+        rgba.width(),
+        rgba.height(),
+        format,
+        pixels.len() as u64,
+        vk::ImageUsageFlags::TRANSFER_SRC
+            | vk::ImageUsageFlags::TRANSFER_DST
+            | vk::ImageUsageFlags::SAMPLED,
+        mip_levels,
+    )?;
+    let allocation = allocator.allocate(
+        &texture.get_memory_requirements(),
+        vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        KewResourceKind::OPTIMAL,
+    )?;
+    texture.bind_memory(allocation)?;
+
+    let (tfr_family, gfx_family) = (transfer.family(), cmd_pool.queue.family_idx);
+    transfer.submit(
+        cmd_pool,
+        vk::PipelineStageFlags::TRANSFER,
+        |tfr_cmd_buffer, gfx_cmd_buffer| unsafe {
+            let to_transfer = texture.acquire_barrier(
+                tfr_family,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::TRANSFER_WRITE,
+            );
+            kew_device.cmd_pipeline_barrier(
+                tfr_cmd_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer],
+            );
+            staging_buffer.copy_to_image(&texture, tfr_cmd_buffer);
+            if let Some(release) = texture.release_barrier(
+                gfx_family,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+            )? {
+                kew_device.cmd_pipeline_barrier(
+                    tfr_cmd_buffer,
+                    vk::PipelineStageFlags::TRANSFER,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    vk::DependencyFlags::empty(),
+                    &[],
+                    &[],
+                    &[release],
+                );
+            }
+
+            // the submission waits for the copy, the acquire makes it visible to the blits
+            let acquire = texture.acquire_barrier(
+                gfx_family,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::AccessFlags::TRANSFER_READ | vk::AccessFlags::TRANSFER_WRITE,
+            );
+            kew_device.cmd_pipeline_barrier(
+                gfx_cmd_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[acquire],
+            );
+            texture.generate_mipmaps(gfx_cmd_buffer);
+            Ok(())
+        },
+    )?;
+    texture.recreate_image_view()?;
+    debug!(
+        "uploaded {}x{} {:?} texture ({} mip levels)",
+        rgba.width(),
+        rgba.height(),
+        format,
+        mip_levels
+    );
+    Ok(texture)
+}
@@ -0,0 +1,176 @@
+use crate::core::buffer::KewBuffer;
+use crate::core::command::KewCommandPool;
+use crate::core::device::KewDevice;
+use crate::core::error::KewError;
+use crate::core::memory::{KewAllocator, KewResourceKind};
+use crate::core::sync::KewTimeline;
+use ash::vk;
+use std::time::Duration;
+
+/// Uploads on the transfer queue family (`KewQueueIndices::tfr_idx`), handed over to the queue
+/// of another command pool which finishes them, e.g. generates mipmaps on the graphics queue.
+/// The transfer submission signals a timeline value the finishing submission waits for on the
+/// device. Devices without timeline semaphores wait for the transfer on the host instead.
+pub struct KewTransfer<'a> {
+    kew_device: &'a KewDevice,
+    cmd_pool: KewCommandPool<'a>,
+    timeline: Option<KewTimeline<'a>>,
+    /// Last value signaled on `timeline`.
+    value: u64,
+}
+
+impl<'a> KewTransfer<'a> {
+    pub fn new(kew_device: &'a KewDevice, tfr_queue_idx: u32) -> Result<Self, KewError> {
+        let timeline = match kew_device.timeline_semaphores {
+            true => Some(KewTimeline::new(kew_device, 0)?),
+            false => None,
+        };
+        Ok(Self {
+            kew_device,
+            cmd_pool: KewCommandPool::new(kew_device, tfr_queue_idx)?,
+            timeline,
+            value: 0,
+        })
+    }
+
+    /// Queue family the uploads are recorded for.
+    pub fn family(&self) -> u32 {
+        self.cmd_pool.queue.family_idx
+    }
+
+    /// Uploads `b_size` bytes of `data` into a new device local buffer and hands it to the queue
+    /// family of `dst_pool`, where it is ready for `dst_access` at `dst_stage`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn upload_buffer<T: Copy>(
+        &mut self,
+        allocator: &'a KewAllocator<'a>,
+        dst_pool: &KewCommandPool,
+        data: &[T],
+        b_size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        dst_stage: vk::PipelineStageFlags,
+        dst_access: vk::AccessFlags,
+    ) -> Result<KewBuffer<'a>, KewError> {
+        let mut staging_buffer =
+            KewBuffer::new(self.kew_device, b_size, vk::BufferUsageFlags::TRANSFER_SRC)?;
+        let allocation = allocator.allocate(
+            &staging_buffer.get_memory_requirements(),
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            KewResourceKind::LINEAR,
+        )?;
+        staging_buffer.bind_memory(allocation)?;
+        unsafe { staging_buffer.wr_visible_mem(data, b_size, 0) };
+
+        let mut buffer = KewBuffer::new(
+            self.kew_device,
+            b_size,
+            usage | vk::BufferUsageFlags::TRANSFER_DST,
+        )?;
+        let allocation = allocator.allocate(
+            &buffer.get_memory_requirements(),
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            KewResourceKind::LINEAR,
+        )?;
+        buffer.bind_memory(allocation)?;
+
+        let kew_device = self.kew_device;
+        let (tfr_family, dst_family) = (self.family(), dst_pool.queue.family_idx);
+        self.submit(
+            dst_pool,
+            dst_stage,
+            |tfr_cmd_buffer, dst_cmd_buffer| unsafe {
+                // the contents are undefined before the copy, nothing to acquire yet
+                buffer.owner = Some(tfr_family);
+                let region = vk::BufferCopy::default().size(b_size);
+                kew_device.cmd_copy_buffer(tfr_cmd_buffer, *staging_buffer, *buffer, &[region]);
+                if let Some(release) =
+                    buffer.release_barrier(dst_family, vk::AccessFlags::TRANSFER_WRITE)?
+                {
+                    kew_device.cmd_pipeline_barrier(
+                        tfr_cmd_buffer,
+                        vk::PipelineStageFlags::TRANSFER,
+                        vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[release],
+                        &[],
+                    );
+                }
+                // within one family the wait at `dst_stage` makes the copy visible
+                if let Some(acquire) = buffer.acquire_barrier(dst_family, dst_access) {
+                    kew_device.cmd_pipeline_barrier(
+                        dst_cmd_buffer,
+                        vk::PipelineStageFlags::TOP_OF_PIPE,
+                        dst_stage,
+                        vk::DependencyFlags::empty(),
+                        &[],
+                        &[acquire],
+                        &[],
+                    );
+                }
+                Ok(())
+            },
+        )?;
+        Ok(buffer)
+    }
+
+    /// Records a transfer and a finishing command buffer for the queue of `dst_pool` through
+    /// `record`, submits both and blocks until they have been executed. The finishing one
+    /// starts at `dst_stage` once the transfer has completed. Resources written by the transfer
+    /// have to be released to `dst_pool`'s family in the first and acquired in the second.
+    pub fn submit(
+        &mut self,
+        dst_pool: &KewCommandPool,
+        dst_stage: vk::PipelineStageFlags,
+        record: impl FnOnce(vk::CommandBuffer, vk::CommandBuffer) -> Result<(), KewError>,
+    ) -> Result<(), KewError> {
+        let tfr_cmd_buffer = self.cmd_pool.begin_once()?;
+        let dst_cmd_buffer = match dst_pool.begin_once() {
+            Ok(cmd_buffer) => cmd_buffer,
+            Err(e) => {
+                self.cmd_pool.free_command_buffers(&[tfr_cmd_buffer]);
+                return Err(e);
+            }
+        };
+        let result = record(tfr_cmd_buffer, dst_cmd_buffer)
+            .and_then(|_| self.cmd_pool.end(tfr_cmd_buffer))
+            .and_then(|_| dst_pool.end(dst_cmd_buffer))
+            .and_then(|_| self.execute(tfr_cmd_buffer, dst_pool, dst_cmd_buffer, dst_stage));
+        self.cmd_pool.free_command_buffers(&[tfr_cmd_buffer]);
+        dst_pool.free_command_buffers(&[dst_cmd_buffer]);
+        result
+    }
+
+    fn execute(
+        &mut self,
+        tfr_cmd_buffer: vk::CommandBuffer,
+        dst_pool: &KewCommandPool,
+        dst_cmd_buffer: vk::CommandBuffer,
+        dst_stage: vk::PipelineStageFlags,
+    ) -> Result<(), KewError> {
+        let Some(timeline) = &self.timeline else {
+            self.cmd_pool
+                .queue
+                .submit(&[tfr_cmd_buffer], &[], &[])?
+                .wait(Duration::MAX)?;
+            dst_pool
+                .queue
+                .submit(&[dst_cmd_buffer], &[], &[])?
+                .wait(Duration::MAX)?;
+            return Ok(());
+        };
+        let value = self.value + 1;
+        let tfr_fence =
+            self.cmd_pool
+                .queue
+                .submit(&[tfr_cmd_buffer], &[], &[timeline.at(value)])?;
+        self.value = value;
+        let finished = dst_pool
+            .queue
+            .submit(&[dst_cmd_buffer], &[(timeline.at(value), dst_stage)], &[])
+            .and_then(|dst_fence| dst_fence.wait(Duration::MAX));
+        // the transfer command buffer is freed afterwards, even if the second submission failed
+        let transferred = tfr_fence.wait(Duration::MAX);
+        finished.and(transferred).map(|_| ())
+    }
+}
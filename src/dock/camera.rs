@@ -0,0 +1,244 @@
+use crate::math::matrix::Matrix;
+use crate::math::vector::Vector;
+use ash::vk;
+use std::collections::HashSet;
+use std::f32::consts::FRAC_PI_2;
+use winit::event::MouseButton;
+use winit::keyboard::KeyCode;
+
+const LOOK_SENSITIVITY: f32 = 0.005;
+const ZOOM_FACTOR: f32 = 0.9;
+const FLY_SPEED: f32 = 2.0;
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+const MIN_DISTANCE: f32 = 0.1;
+
+/// Window input forwarded from the event loop to the dock thread.
+#[derive(Clone, Copy, Debug)]
+pub enum CameraInput {
+    KEY(KeyCode, bool),
+    BUTTON(MouseButton, bool),
+    CURSOR(f64, f64),
+    /// Scroll distance in lines, positive away from the user.
+    SCROLL(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    PERSPECTIVE,
+    ORTHOGRAPHIC,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraController {
+    /// Dragging rotates around the target, scrolling zooms.
+    ORBIT,
+    /// WASD moves, E and Q rise and sink, dragging looks around.
+    FLY,
+}
+
+/// Placement of the camera, see `Camera`. The default is the pose the dock starts with.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraPose {
+    pub target: Vector<f32, 3>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+impl Default for CameraPose {
+    fn default() -> Self {
+        Self {
+            target: Vector::default(),
+            distance: 3.0,
+            yaw: 0.6,
+            pitch: 0.5,
+        }
+    }
+}
+
+/// Per frame uniform of the dock vertex shaders, matches the `Camera` block in `mesh.vert`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CameraUniform {
+    pub view: Matrix<f32, 4, 4>,
+    pub projection: Matrix<f32, 4, 4>,
+}
+
+/// Camera looking at `target` from `distance` away. Yaw and pitch orient the eye around the
+/// target so both controllers share the same state and switching keeps the view.
+pub struct Camera {
+    pub projection: Projection,
+    pub controller: CameraController,
+    pub target: Vector<f32, 3>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+    aspect: f32,
+    pressed_keys: HashSet<KeyCode>,
+    dragging: bool,
+    cursor: Option<(f64, f64)>,
+}
+
+impl Camera {
+    pub fn new(extent: vk::Extent2D) -> Self {
+        let pose = CameraPose::default();
+        let mut camera = Self {
+            projection: Projection::PERSPECTIVE,
+            controller: CameraController::ORBIT,
+            target: pose.target,
+            distance: pose.distance,
+            yaw: pose.yaw,
+            pitch: pose.pitch,
+            fov_y: 60f32.to_radians(),
+            near: 0.05,
+            far: 100.0,
+            aspect: 1.0,
+            pressed_keys: HashSet::new(),
+            dragging: false,
+            cursor: None,
+        };
+        camera.resize(extent);
+        camera
+    }
+
+    pub fn resize(&mut self, extent: vk::Extent2D) {
+        if extent.width > 0 && extent.height > 0 {
+            self.aspect = extent.width as f32 / extent.height as f32;
+        }
+    }
+
+    pub fn handle_input(&mut self, input: CameraInput) {
+        match input {
+            CameraInput::KEY(code, true) if !self.pressed_keys.contains(&code) => {
+                match code {
+                    KeyCode::Tab => self.toggle_controller(),
+                    KeyCode::KeyP => self.toggle_projection(),
+                    _ => (),
+                }
+                self.pressed_keys.insert(code);
+            }
+            CameraInput::KEY(code, true) => {
+                self.pressed_keys.insert(code);
+            }
+            CameraInput::KEY(code, false) => {
+                self.pressed_keys.remove(&code);
+            }
+            CameraInput::BUTTON(MouseButton::Left, pressed) => self.dragging = pressed,
+            CameraInput::BUTTON(..) => (),
+            CameraInput::CURSOR(x, y) => {
+                if let (true, Some((last_x, last_y))) = (self.dragging, self.cursor) {
+                    self.look((x - last_x) as f32, (y - last_y) as f32);
+                }
+                self.cursor = Some((x, y));
+            }
+            CameraInput::SCROLL(lines) => {
+                self.distance = (self.distance * ZOOM_FACTOR.powf(lines)).max(MIN_DISTANCE);
+            }
+        }
+    }
+
+    pub fn pose(&self) -> CameraPose {
+        CameraPose {
+            target: self.target,
+            distance: self.distance,
+            yaw: self.yaw,
+            pitch: self.pitch,
+        }
+    }
+
+    pub fn set_pose(&mut self, pose: CameraPose) {
+        self.target = pose.target;
+        self.distance = pose.distance.max(MIN_DISTANCE);
+        self.yaw = pose.yaw;
+        self.pitch = pose.pitch.clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Moves the fly camera by the keys held for `dt` seconds.
+    pub fn update(&mut self, dt: f32) {
+        if self.controller != CameraController::FLY {
+            return;
+        }
+        let axis = |positive: KeyCode, negative: KeyCode| {
+            self.pressed_keys.contains(&positive) as i32 as f32
+                - self.pressed_keys.contains(&negative) as i32 as f32
+        };
+        let forward = -self.eye_direction();
+        let right = forward.cross(&Vector::from([0.0, 1.0, 0.0])).normalize();
+        let movement = forward * axis(KeyCode::KeyW, KeyCode::KeyS)
+            + right * axis(KeyCode::KeyD, KeyCode::KeyA)
+            + Vector::from([0.0, 1.0, 0.0]) * axis(KeyCode::KeyE, KeyCode::KeyQ);
+        self.target += movement.normalize() * FLY_SPEED * dt;
+    }
+
+    pub fn position(&self) -> Vector<f32, 3> {
+        self.target + self.eye_direction() * self.distance
+    }
+
+    pub fn view(&self) -> Matrix<f32, 4, 4> {
+        Matrix::look_at(self.position(), self.target, Vector::from([0.0, 1.0, 0.0]))
+    }
+
+    pub fn projection(&self) -> Matrix<f32, 4, 4> {
+        match self.projection {
+            Projection::PERSPECTIVE => {
+                Matrix::perspective(self.fov_y, self.aspect, self.near, self.far)
+            }
+            Projection::ORTHOGRAPHIC => {
+                // frames the target like the perspective projection does
+                let half_height = self.distance * (self.fov_y / 2.0).tan();
+                let half_width = half_height * self.aspect;
+                Matrix::orthographic(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.near,
+                    self.far,
+                )
+            }
+        }
+    }
+
+    pub fn uniform(&self) -> CameraUniform {
+        CameraUniform {
+            view: self.view(),
+            projection: self.projection(),
+        }
+    }
+
+    fn look(&mut self, dx: f32, dy: f32) {
+        let eye = self.position();
+        self.yaw -= dx * LOOK_SENSITIVITY;
+        self.pitch = (self.pitch + dy * LOOK_SENSITIVITY).clamp(-MAX_PITCH, MAX_PITCH);
+        if self.controller == CameraController::FLY {
+            // the eye stays in place and the target swings around it
+            self.target = eye - self.eye_direction() * self.distance;
+        }
+    }
+
+    fn toggle_controller(&mut self) {
+        self.controller = match self.controller {
+            CameraController::ORBIT => CameraController::FLY,
+            CameraController::FLY => CameraController::ORBIT,
+        };
+    }
+
+    fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::PERSPECTIVE => Projection::ORTHOGRAPHIC,
+            Projection::ORTHOGRAPHIC => Projection::PERSPECTIVE,
+        };
+    }
+
+    /// Unit vector from the target to the eye.
+    fn eye_direction(&self) -> Vector<f32, 3> {
+        Vector::from([
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        ])
+    }
+}
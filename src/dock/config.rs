@@ -1,20 +1,27 @@
 use std::ffi::CStr;
+use std::mem::size_of;
 use ash::vk;
 use crate::core::model::VertexType;
-use crate::core::pipeline::{ColorTarget, GfxPipelineConfig, PrimitiveState};
-use crate::core::shader::{DescriptorSetLayoutBindingInfo, ShaderStageConfig};
+use crate::core::pipeline::{
+    ColorTarget, DepthStencilState, GfxPipelineConfig, MultisampleState, PrimitiveState,
+};
+use crate::core::sampler::SamplerConfig;
+use crate::core::shader::{DescriptorSetLayoutBindingInfo, PushConstantInfo, ShaderStageConfig};
+use crate::math::matrix::Matrix;
 
 pub const VERT_SHADER_CONFIG: ShaderStageConfig<1> = unsafe {
     ShaderStageConfig {
         entry_name: CStr::from_bytes_with_nul_unchecked(b"main\0"),
-        path: "./shader/compiled/kew.vert.spv",
-        bindings: [
+        path: "./shader/kew.vert",
+        set: 0,
+        bindings: Some([
             DescriptorSetLayoutBindingInfo {
                 descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
                 descriptor_count: 1,
                 stage_flags: vk::ShaderStageFlags::VERTEX,
             }
-        ],
+        ]),
+        push_constants: None,
         stage: vk::ShaderStageFlags::VERTEX,
         create_flags: vk::PipelineShaderStageCreateFlags::empty(),
     }
@@ -22,17 +29,108 @@ pub const VERT_SHADER_CONFIG: ShaderStageConfig<1> = unsafe {
 pub const FRAG_SHADER_CONFIG: ShaderStageConfig<0> = unsafe {
     ShaderStageConfig {
         entry_name: CStr::from_bytes_with_nul_unchecked(b"main\0"),
-        path: "./shader/compiled/kew.frag.spv",
-        bindings: [],
+        path: "./shader/kew.frag",
+        set: 0,
+        bindings: None,
+        push_constants: None,
+        stage: vk::ShaderStageFlags::FRAGMENT,
+        create_flags: vk::PipelineShaderStageCreateFlags::empty(),
+    }
+};
+
+/// Bindings are pinned so reloaded shaders stay compatible with the frame descriptor sets.
+pub const MESH_VERT_SHADER_CONFIG: ShaderStageConfig<1> = unsafe {
+    ShaderStageConfig {
+        entry_name: CStr::from_bytes_with_nul_unchecked(b"main\0"),
+        path: "./shader/mesh.vert",
+        set: 0,
+        bindings: Some([
+            DescriptorSetLayoutBindingInfo {
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+            }
+        ]),
+        // model transform
+        push_constants: Some(PushConstantInfo {
+            offset: 0,
+            size: size_of::<Matrix<f32, 4, 4>>() as u32,
+        }),
+        stage: vk::ShaderStageFlags::VERTEX,
+        create_flags: vk::PipelineShaderStageCreateFlags::empty(),
+    }
+};
+/// Material set bound after the camera set of `MESH_VERT_SHADER_CONFIG`.
+pub const MESH_FRAG_SHADER_CONFIG: ShaderStageConfig<1> = unsafe {
+    ShaderStageConfig {
+        entry_name: CStr::from_bytes_with_nul_unchecked(b"main\0"),
+        path: "./shader/mesh.frag",
+        set: 1,
+        bindings: Some([
+            DescriptorSetLayoutBindingInfo {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            }
+        ]),
+        push_constants: None,
+        stage: vk::ShaderStageFlags::FRAGMENT,
+        create_flags: vk::PipelineShaderStageCreateFlags::empty(),
+    }
+};
+/// Replaces `MESH_FRAG_SHADER_CONFIG` on devices with `KewDevice::bindless`. Set 1 is the
+/// bindless table, the texture handle is pushed after the model transform.
+pub const MESH_BINDLESS_FRAG_SHADER_CONFIG: ShaderStageConfig<1> = unsafe {
+    ShaderStageConfig {
+        entry_name: CStr::from_bytes_with_nul_unchecked(b"main\0"),
+        path: "./shader/mesh_bindless.frag",
+        set: 1,
+        bindings: None,
+        push_constants: Some(PushConstantInfo {
+            offset: BINDLESS_TEXTURE_OFFSET,
+            size: size_of::<u32>() as u32,
+        }),
         stage: vk::ShaderStageFlags::FRAGMENT,
         create_flags: vk::PipelineShaderStageCreateFlags::empty(),
     }
 };
+pub const BINDLESS_TEXTURE_OFFSET: u32 = size_of::<Matrix<f32, 4, 4>>() as u32;
+/// Textures the dock can hold in bindless mode.
+pub const BINDLESS_TEXTURES: u32 = 4096;
+/// Storage buffers the dock can hold in bindless mode.
+pub const BINDLESS_BUFFERS: u32 = 256;
+
+pub const SHADER_SOURCE_DIR: &str = "./shader";
+pub const SHADER_CACHE_DIR: &str = "./shader/cache";
+
+pub const TEXTURE_SAMPLER: SamplerConfig = SamplerConfig {
+    mag_filter: vk::Filter::LINEAR,
+    min_filter: vk::Filter::LINEAR,
+    mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+    address_mode: vk::SamplerAddressMode::REPEAT,
+    max_anisotropy: Some(16.0),
+};
+
+pub const OFFSCREEN_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+/// Requested for the swapchain, offscreen targets and every pipeline drawing into them.
+pub const MSAA_SAMPLES: vk::SampleCountFlags = vk::SampleCountFlags::TYPE_4;
 
 pub const NULL_VERT_CONFIG: usize = 0;
 pub const FLAT_VERT_CONFIG: usize = 1;
+pub const MESH_VERT_CONFIG: usize = 2;
+
+pub const MODEL_PATH: &str = "./assets/cubes.gltf";
+pub const SCREENSHOT_PATH: &str = "./screenshot.png";
+
+const DEPTH_LESS: DepthStencilState = DepthStencilState {
+    depth_test: true,
+    depth_write: true,
+    compare_op: vk::CompareOp::LESS,
+    stencil_front: None,
+    stencil_back: None,
+};
 
-pub const PIPELINE_CONFIGS: [GfxPipelineConfig; 2] = [
+pub const PIPELINE_CONFIGS: [GfxPipelineConfig; 3] = [
     GfxPipelineConfig {
         primitive: PrimitiveState {
             topology: vk::PrimitiveTopology::TRIANGLE_LIST,
@@ -47,6 +145,12 @@ pub const PIPELINE_CONFIGS: [GfxPipelineConfig; 2] = [
             alpha_blend: None,
             write_mask: vk::ColorComponentFlags::RGBA,
         }],
+        multisample: MultisampleState {
+            samples: MSAA_SAMPLES,
+            sample_shading: None,
+            alpha_to_coverage: false,
+        },
+        depth_stencil: Some(DEPTH_LESS),
         vertex_type: VertexType::NULL,
     },
     GfxPipelineConfig {
@@ -63,6 +167,34 @@ pub const PIPELINE_CONFIGS: [GfxPipelineConfig; 2] = [
             alpha_blend: None,
             write_mask: vk::ColorComponentFlags::RGBA,
         }],
+        multisample: MultisampleState {
+            samples: MSAA_SAMPLES,
+            sample_shading: None,
+            alpha_to_coverage: false,
+        },
+        depth_stencil: Some(DEPTH_LESS),
         vertex_type: VertexType::FLAT,
-    }
+    },
+    GfxPipelineConfig {
+        primitive: PrimitiveState {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            restart: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            depth_clamp: false,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+        },
+        color_targets: &[ColorTarget {
+            color_blend: None,
+            alpha_blend: None,
+            write_mask: vk::ColorComponentFlags::RGBA,
+        }],
+        multisample: MultisampleState {
+            samples: MSAA_SAMPLES,
+            sample_shading: None,
+            alpha_to_coverage: false,
+        },
+        depth_stencil: Some(DEPTH_LESS),
+        vertex_type: VertexType::MESH,
+    },
 ];
\ No newline at end of file
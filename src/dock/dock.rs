@@ -1,23 +1,55 @@
+use crate::core::bindless::KewBindlessTable;
 use crate::core::buffer::KewBuffer;
 use crate::core::command::KewCommandPool;
-use crate::core::descriptor::{KewDescriptorPool, KewDescriptorPoolBuilder};
+use crate::core::compiler::KewShaderCompiler;
+use crate::core::descriptor::{KewDescriptorAllocator, KewDescriptorWriter};
 use crate::core::device::{KewDevice, KewQueueIndices};
-use crate::core::memory::KewMemory;
-use crate::core::model::{KewModel, KewModelVertexData};
-use crate::core::pipeline::KewGfxPipeline;
-use crate::core::shader::KewShader;
+use crate::core::error::KewError;
+use crate::core::graph::{BufferAccess, ImageAccess, KewRenderGraph, KewTransientPool};
+use crate::core::image::KewImage;
+use crate::core::memory::{KewAllocator, KewResourceKind};
+use crate::core::model::{KewModel, KewModelVertexData, Vertex};
+use crate::core::pipeline::{KewGfxPipeline, PipelineTarget};
+use crate::core::sampler::KewSampler;
+use crate::core::scene::{KewMaterial, KewScene};
+use crate::core::shader::{KewSetLayouts, KewShader, ShaderStageConfig};
 use crate::core::swapchain::{KewSwapchain, MAX_IN_FLIGHT_FRAMES};
-use crate::dock::config::{FLAT_VERT_CONFIG, FRAG_SHADER_CONFIG, PIPELINE_CONFIGS, VERT_SHADER_CONFIG};
-use crate::dock::{DockErr, DockMessage};
+use crate::core::target::KewRenderTarget;
+use crate::core::texture::{upload_texture, TextureEncoding};
+use crate::core::transfer::KewTransfer;
+use crate::dock::camera::{Camera, CameraUniform};
+use crate::dock::config::{
+    BINDLESS_BUFFERS, BINDLESS_TEXTURES, BINDLESS_TEXTURE_OFFSET, MESH_BINDLESS_FRAG_SHADER_CONFIG,
+    MESH_FRAG_SHADER_CONFIG, MESH_VERT_CONFIG, MESH_VERT_SHADER_CONFIG, MODEL_PATH, MSAA_SAMPLES,
+    OFFSCREEN_FORMAT, PIPELINE_CONFIGS, SHADER_CACHE_DIR, SHADER_SOURCE_DIR, TEXTURE_SAMPLER,
+};
+use crate::dock::{DockErr, DockEvent, DockMessage, FrameStats, ModelId};
+use crate::math::matrix::Matrix;
 use ash::khr::surface;
 use ash::vk;
-use crossbeam::channel::Receiver;
-use log::{debug, error, warn};
+use crossbeam::channel::{never, select, Receiver};
+use image::{DynamicImage, Rgba, RgbaImage};
+use log::{debug, error, info, warn};
+use std::fs;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
 use std::thread;
+use std::time::{Duration, Instant};
+use winit::event_loop::EventLoopProxy;
 
-const MODEL_MEM_IDX: usize = 0;
-const MODEL_MEM_SIZE: u64 = 1024;
+/// Slot of the uniform buffer and descriptor allocator used for screenshots, after the frame
+/// slots.
+const CAPTURE_SLOT: usize = MAX_IN_FLIGHT_FRAMES;
+const FRAME_STATS_INTERVAL: Duration = Duration::from_secs(1);
+/// White texture of models without a base color texture.
+const DEFAULT_TEXTURE: usize = 0;
+/// Material sets of the first descriptor pool, later pools double in size.
+const MATERIAL_POOL_SETS: u32 = 16;
+/// Camera sets of the first descriptor pool of a frame slot, one is allocated per frame.
+const CAMERA_POOL_SETS: u32 = 1;
 
+/// Runs the dock render loop until `SHUTDOWN` is received or the application side hangs up.
+/// Destroys `surface` after everything created for it.
 pub fn init_dock(
     kew_device: &KewDevice,
     surface_loader: &surface::Instance,
@@ -25,111 +57,396 @@ pub fn init_dock(
     queue_indices: &KewQueueIndices,
     window_extent: vk::Extent2D,
     application_thread: Receiver<DockMessage>,
-) {
+    events: EventLoopProxy<DockEvent>,
+) -> Result<(), KewError> {
+    let result = run_dock(
+        kew_device,
+        surface_loader,
+        surface,
+        queue_indices,
+        window_extent,
+        application_thread,
+        events,
+    );
+    // the swapchain has been dropped with the renderer
+    unsafe { surface_loader.destroy_surface(surface, None) };
+    result
+}
+
+fn run_dock(
+    kew_device: &KewDevice,
+    surface_loader: &surface::Instance,
+    surface: vk::SurfaceKHR,
+    queue_indices: &KewQueueIndices,
+    window_extent: vk::Extent2D,
+    application_thread: Receiver<DockMessage>,
+    events: EventLoopProxy<DockEvent>,
+) -> Result<(), KewError> {
+    let allocator = KewAllocator::new(kew_device);
     let mut renderer = DockRenderer::new(
         &kew_device,
+        &allocator,
         &surface_loader,
         surface,
         window_extent,
-        queue_indices.prs_idx,
+        queue_indices.prs_idx.ok_or(KewError::QUEUE("present"))?,
         queue_indices.gfx_idx,
-    );
+    )?;
 
-    let mut memory_allocations: Vec<KewMemory> = Vec::with_capacity(8);
+    let compiler = KewShaderCompiler::new(SHADER_SOURCE_DIR, SHADER_CACHE_DIR);
+    let (_watcher, shader_changes) = match compiler.watch() {
+        Ok((watcher, changes)) => (Some(watcher), changes),
+        Err(e) => {
+            warn!("shader hot reload disabled: {}", e);
+            (None, never())
+        }
+    };
+
+    thread::scope(|scope| {
+        scope.spawn(|| -> Result<(), KewError> {
+            let mut shaders = DockShaders::new(kew_device, &compiler)?;
+            let mut dock_scene = DockScene::new(
+                &kew_device,
+                &allocator,
+                &renderer.cmd_pool,
+                queue_indices.tfr_idx,
+                &shaders.vert,
+                &shaders.frag,
+                &renderer.swapchain.pipeline_target(),
+            )?;
+            let mut camera = Camera::new(window_extent);
+            let mut frame_stats = (0u32, Instant::now());
+            let mut last_frame = Instant::now();
+            for heap in allocator.stats() {
+                debug!("{:?}", heap);
+            }
+
+            let result = dock_scene
+                .load(Path::new(MODEL_PATH), &renderer.cmd_pool)
+                .and_then(|_| loop {
+                    select! {
+                        recv(application_thread) -> message => match message {
+                            Ok(DockMessage::RENDER) => {
+                                camera.update(last_frame.elapsed().as_secs_f32());
+                                last_frame = Instant::now();
+                                if renderer.render_scene(&dock_scene, &camera.uniform())? {
+                                    frame_stats.0 += 1;
+                                }
+                                let elapsed = frame_stats.1.elapsed();
+                                if elapsed >= FRAME_STATS_INTERVAL {
+                                    let _ = events.send_event(DockEvent::FRAME(FrameStats {
+                                        fps: frame_stats.0 as f32 / elapsed.as_secs_f32(),
+                                        frame_time: elapsed / frame_stats.0.max(1),
+                                    }));
+                                    frame_stats = (0, Instant::now());
+                                }
+                            }
+                            Ok(DockMessage::RESIZE(extent)) => {
+                                renderer.resize(extent);
+                                camera.resize(extent);
+                            }
+                            Ok(DockMessage::INPUT(input)) => camera.handle_input(input),
+                            Ok(DockMessage::CAMERA(pose)) => camera.set_pose(pose),
+                            Ok(DockMessage::ADD(model_data, reply)) => {
+                                let _ = reply
+                                    .send(dock_scene.add_model(&model_data, &renderer.cmd_pool));
+                            }
+                            Ok(DockMessage::UPDATE(id, model_data)) => {
+                                if let Err(e) =
+                                    dock_scene.update_model(id, &model_data, &renderer.cmd_pool)
+                                {
+                                    let _ = events.send_event(DockEvent::ERROR(e));
+                                }
+                            }
+                            Ok(DockMessage::TRANSFORM(id, transform)) => {
+                                if let Err(e) = dock_scene.set_transform(id, transform) {
+                                    let _ = events.send_event(DockEvent::ERROR(e));
+                                }
+                            }
+                            Ok(DockMessage::REMOVE(id)) => {
+                                if let Err(e) = dock_scene.remove_model(id) {
+                                    let _ = events.send_event(DockEvent::ERROR(e));
+                                }
+                            }
+                            Ok(DockMessage::LOAD(path, reply)) => {
+                                let _ = reply.send(dock_scene.load(&path, &renderer.cmd_pool));
+                            }
+                            Ok(DockMessage::SCREENSHOT(reply)) => {
+                                let _ = reply.send(renderer.screenshot(
+                                    &mut dock_scene,
+                                    &shaders.vert,
+                                    &shaders.frag,
+                                    &camera.uniform(),
+                                ));
+                            }
+                            Ok(DockMessage::SHUTDOWN) => break Ok(()),
+                            Err(_) => {
+                                warn!("dock application channel closed (shutting down)");
+                                break Ok(());
+                            }
+                        },
+                        recv(shader_changes) -> path => {
+                            // editors tend to emit several events per save
+                            let changed = path
+                                .into_iter()
+                                .chain(shader_changes.try_iter())
+                                .collect::<Vec<_>>();
+                            let reloaded = shaders.reload(&changed).and_then(|reloaded| {
+                                if let Some(reloaded) = reloaded {
+                                    let (vert, frag) = shaders.stages(&reloaded);
+                                    dock_scene.rebuild_pipeline(vert, frag)?;
+                                    shaders.replace(reloaded);
+                                }
+                                Ok(())
+                            });
+                            if let Err(e) = reloaded {
+                                error!("shader reload failed: {}", e);
+                                let _ = events.send_event(DockEvent::ERROR(e));
+                            }
+                        }
+                    }
+                });
+            // nothing may be in flight when the scene, shaders and renderer are dropped
+            unsafe { kew_device.device_wait_idle()? };
+            info!("dock render loop stopped");
+            result
+        })
+        .join()
+        .unwrap()
+    })
+}
+
+/// Renders the dock scene once into an offscreen target and saves it as an image file.
+pub fn render_offscreen(
+    kew_device: &KewDevice,
+    queue_indices: &KewQueueIndices,
+    extent: vk::Extent2D,
+    path: &Path,
+) -> Result<(), KewError> {
+    let allocator = KewAllocator::new(kew_device);
+    let cmd_pool = KewCommandPool::new(kew_device, queue_indices.gfx_idx)?;
+    let compiler = KewShaderCompiler::new(SHADER_SOURCE_DIR, SHADER_CACHE_DIR);
+    let shaders = DockShaders::new(kew_device, &compiler)?;
 
-    let (mut vrt_buffer, vrt_mem_type) = create_buffer(
+    let mut target = KewRenderTarget::new(
         kew_device,
-        vk::BufferUsageFlags::VERTEX_BUFFER,
-        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        MODEL_MEM_SIZE
-    );
-    let (mut idx_buffer, idx_mem_type) = create_buffer(
+        &allocator,
+        extent,
+        &[OFFSCREEN_FORMAT],
+        Some(kew_device.find_depth_format()?),
+        MSAA_SAMPLES,
+    )?;
+    let mut dock_scene = DockScene::new(
         kew_device,
-        vk::BufferUsageFlags::INDEX_BUFFER,
-        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-        MODEL_MEM_SIZE
-    );
-    assert_eq!(vrt_mem_type, idx_mem_type);
-    memory_allocations.push(KewMemory::new(
+        &allocator,
+        &cmd_pool,
+        queue_indices.tfr_idx,
+        &shaders.vert,
+        &shaders.frag,
+        &target.pipeline_target(),
+    )?;
+    dock_scene.load(Path::new(MODEL_PATH), &cmd_pool)?;
+
+    let mut descriptor_allocator = KewDescriptorAllocator::new(
         kew_device,
-        MODEL_MEM_SIZE * 2,
-        vrt_mem_type,
-    ));
+        &[(vk::DescriptorType::UNIFORM_BUFFER, 1)],
+        CAMERA_POOL_SETS,
+    );
+    let uniform_buffer = create_camera_uniform(kew_device, &allocator)?;
+    let descriptor_set =
+        allocate_camera_set(&mut descriptor_allocator, &dock_scene, &uniform_buffer)?;
+    let camera = Camera::new(extent);
     unsafe {
-        memory_allocations
-            .get(MODEL_MEM_IDX)
-            .unwrap()
-            .map(vk::WHOLE_SIZE, 0);
-        vrt_buffer.bind_memory(&memory_allocations[MODEL_MEM_IDX], 0);
-        idx_buffer.bind_memory(&memory_allocations[MODEL_MEM_IDX], MODEL_MEM_SIZE);
+        uniform_buffer.wr_visible_mem(&[camera.uniform()], size_of::<CameraUniform>() as u64, 0);
     }
 
-    let vert_shader = KewShader::new(&kew_device, &VERT_SHADER_CONFIG);
-    let frag_shader = KewShader::new(&kew_device, &FRAG_SHADER_CONFIG);
-
-    thread::scope(|scope| {
-        scope.spawn(|| {
-            let mut dock_scene = DockScene::dummy(
-                &kew_device,
-                &vrt_buffer,
-                &idx_buffer,
-                &vert_shader,
-                &frag_shader,
-                &renderer.swapchain.render_pass,
-            );
+    render_to_image(
+        &allocator,
+        &cmd_pool,
+        &mut target,
+        &dock_scene,
+        descriptor_set,
+    )?
+    .save(path)?;
+    info!("saved offscreen render to {}", path.display());
+    Ok(())
+}
 
-            let model = load_model(&vrt_buffer, &idx_buffer);
-            dock_scene.add_model(model);
-
-            loop {
-                if let Ok(_) = application_thread.recv() {
-                    renderer.render_scene(&dock_scene);
-                } else {
-                    error!("dock render thread error mpsc message received (dropping thread)");
-                    unsafe {
-                        surface_loader.destroy_surface(surface, None);
-                        return;
-                    }
-                }
-            }
+/// Draws `scene` into the first color attachment of `target` and reads it back. The scene
+/// needs a pipeline for the target, see `DockScene::cache_pipeline`.
+fn render_to_image<'a>(
+    allocator: &'a KewAllocator<'a>,
+    cmd_pool: &KewCommandPool,
+    target: &mut KewRenderTarget<'a>,
+    scene: &DockScene<'a>,
+    frame_descriptor_set: vk::DescriptorSet,
+) -> Result<RgbaImage, KewError> {
+    let pipeline = scene
+        .pipeline_for(&target.pipeline_target())
+        .ok_or(KewError::UNSUPPORTED("render target without a pipeline"))?;
+    let readback_buffer = target.create_readback_buffer(allocator)?;
+    let mut graph = KewRenderGraph::new(scene.kew_device);
+    let color = target.add_pass(&mut graph, "scene", move |cmd_buffer| unsafe {
+        scene.record_with(pipeline, cmd_buffer, frame_descriptor_set)
+    })[0];
+    let readback = graph.import_buffer("readback", &readback_buffer, None);
+    graph
+        .add_pass("readback")
+        .image(color, ImageAccess::SOURCE)
+        .buffer(readback, BufferAccess::DESTINATION)
+        .record(move |cmd_buffer, resources| {
+            // imported by `add_pass`
+            let image = resources.kew_image(color).unwrap();
+            image.copy_to_buffer(resources.buffer(readback), cmd_buffer);
+            Ok(())
         });
-    });
+    graph.export_buffer(readback, BufferAccess::HOST);
+    cmd_pool.submit_once(|cmd_buffer| unsafe { graph.execute(cmd_buffer, None) })?;
+    target.read_image(0, &readback_buffer)
 }
 
-fn create_buffer(
-    kew_device: &KewDevice,
+fn create_buffer<'a>(
+    kew_device: &'a KewDevice,
+    allocator: &'a KewAllocator<'a>,
     buffer_usage: vk::BufferUsageFlags,
     memory_flags: vk::MemoryPropertyFlags,
     b_size: u64,
-) -> (KewBuffer, u32) {
-    let buffer = KewBuffer::new(
+) -> Result<KewBuffer<'a>, KewError> {
+    let mut buffer = KewBuffer::new(kew_device, b_size, buffer_usage)?;
+    let allocation = allocator.allocate(
+        &buffer.get_memory_requirements(),
+        memory_flags,
+        KewResourceKind::LINEAR,
+    )?;
+    buffer.bind_memory(allocation)?;
+    Ok(buffer)
+}
+
+/// Uniform buffer holding the camera of one frame slot.
+fn create_camera_uniform<'a>(
+    kew_device: &'a KewDevice,
+    allocator: &'a KewAllocator<'a>,
+) -> Result<KewBuffer<'a>, KewError> {
+    create_buffer(
         kew_device,
-        b_size,
-        buffer_usage,
-    );
-    let memory_type = kew_device
-        .find_memory_type(
-            &buffer.get_memory_requirements(),
-            memory_flags,
-        )
-        .unwrap();
-    (buffer, memory_type)
+        allocator,
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+        vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+        size_of::<CameraUniform>() as u64,
+    )
 }
 
-fn load_model(vrt_buffer: &KewBuffer, idx_buffer: &KewBuffer) -> KewModel {
-    let model_data = KewModelVertexData::square();
-    unsafe {
-        model_data.write_to_memory(
-            vrt_buffer,
-            idx_buffer,
+/// Allocates a camera set compatible with the scene pipeline and points it at `uniform_buffer`.
+fn allocate_camera_set(
+    descriptor_allocator: &mut KewDescriptorAllocator,
+    scene: &DockScene,
+    uniform_buffer: &KewBuffer,
+) -> Result<vk::DescriptorSet, KewError> {
+    let descriptor_set = descriptor_allocator.allocate(scene.camera_set_layout())?;
+    KewDescriptorWriter::new()
+        .write_buffer(
+            descriptor_set,
             0,
-            0
-        );
+            vk::DescriptorType::UNIFORM_BUFFER,
+            uniform_buffer.descriptor_info(),
+        )
+        .update(scene.kew_device);
+    Ok(descriptor_set)
+}
+
+/// Dock shaders compiled from source, recompiled when their sources change.
+/// Shaders recompiled by `DockShaders::reload`, `None` for the unchanged ones.
+struct ReloadedShaders<'a> {
+    vert: Option<KewShader<'a>>,
+    frag: Option<KewShader<'a>>,
+}
+
+struct DockShaders<'a, 'c> {
+    kew_device: &'a KewDevice,
+    compiler: &'c KewShaderCompiler,
+    /// Bindless fragment shader on devices that support it.
+    frag_config: &'static ShaderStageConfig<1>,
+    vert: KewShader<'a>,
+    frag: KewShader<'a>,
+}
+
+impl<'a, 'c> DockShaders<'a, 'c> {
+    fn new(kew_device: &'a KewDevice, compiler: &'c KewShaderCompiler) -> Result<Self, KewError> {
+        let frag_config = match kew_device.bindless {
+            true => &MESH_BINDLESS_FRAG_SHADER_CONFIG,
+            false => &MESH_FRAG_SHADER_CONFIG,
+        };
+        Ok(Self {
+            kew_device,
+            compiler,
+            frag_config,
+            vert: Self::load(kew_device, compiler, &MESH_VERT_SHADER_CONFIG)?,
+            frag: Self::load(kew_device, compiler, frag_config)?,
+        })
+    }
+
+    fn load<const N: usize>(
+        kew_device: &'a KewDevice,
+        compiler: &KewShaderCompiler,
+        config: &'static ShaderStageConfig<N>,
+    ) -> Result<KewShader<'a>, KewError> {
+        let code = compiler.compile(Path::new(config.path))?;
+        KewShader::with_code(kew_device, config, &code)
     }
-    KewModel {
-        vertex_offset: 0,
-        index_amount: model_data.indices.len() as u32,
-        index_offset: 0,
+
+    /// Recompiles the shaders whose source, or a file it includes, is in `changed`, `None` if
+    /// there are none. The current shaders stay in use until `replace`, also on failure.
+    fn reload(&self, changed: &[PathBuf]) -> Result<Option<ReloadedShaders<'a>>, KewError> {
+        let changed = changed
+            .iter()
+            .filter_map(|path| fs::canonicalize(path).ok())
+            .collect::<Vec<_>>();
+        let is_changed = |source: &str| -> Result<bool, KewError> {
+            let sources = self.compiler.sources(Path::new(source))?;
+            Ok(sources.iter().any(|source| {
+                fs::canonicalize(source).is_ok_and(|source| changed.contains(&source))
+            }))
+        };
+        let vert = match is_changed(MESH_VERT_SHADER_CONFIG.path)? {
+            true => Some(Self::load(
+                self.kew_device,
+                self.compiler,
+                &MESH_VERT_SHADER_CONFIG,
+            )?),
+            false => None,
+        };
+        let frag = match is_changed(self.frag_config.path)? {
+            true => Some(Self::load(
+                self.kew_device,
+                self.compiler,
+                self.frag_config,
+            )?),
+            false => None,
+        };
+        Ok((vert.is_some() || frag.is_some()).then_some(ReloadedShaders { vert, frag }))
+    }
+
+    /// The vertex and fragment shader with `reloaded` in place of the current ones.
+    fn stages<'s>(
+        &'s self,
+        reloaded: &'s ReloadedShaders<'a>,
+    ) -> (&'s KewShader<'a>, &'s KewShader<'a>) {
+        (
+            reloaded.vert.as_ref().unwrap_or(&self.vert),
+            reloaded.frag.as_ref().unwrap_or(&self.frag),
+        )
+    }
+
+    /// Puts `reloaded` in use. The replaced shaders destroy their set layouts, so nothing may
+    /// use them anymore, e.g. after `DockScene::rebuild_pipeline`.
+    fn replace(&mut self, reloaded: ReloadedShaders<'a>) {
+        if let Some(vert) = reloaded.vert {
+            self.vert = vert;
+        }
+        if let Some(frag) = reloaded.frag {
+            self.frag = frag;
+        }
+        info!("reloaded dock shaders");
     }
 }
 
@@ -138,75 +455,210 @@ pub struct DockRenderer<'a> {
     swapchain: KewSwapchain<'a>,
     cmd_pool: KewCommandPool<'a>,
     cmd_buffers: [vk::CommandBuffer; MAX_IN_FLIGHT_FRAMES],
-    descriptor_pool: KewDescriptorPool<'a>,
+    allocator: &'a KewAllocator<'a>,
+    /// Camera uniforms of the frame slots followed by the one of `CAPTURE_SLOT`.
+    uniform_buffers: Vec<KewBuffer<'a>>,
+    /// Camera sets are allocated every frame from the allocator of its slot, which is reset
+    /// once the slot is no longer in flight. Indexed like `uniform_buffers`.
+    descriptor_allocators: Vec<KewDescriptorAllocator<'a>>,
+    /// Transient attachments of frames recorded as render graphs, one pool per frame slot.
+    transient_pools: Vec<KewTransientPool<'a>>,
+    window_extent: vk::Extent2D,
     current_frame_idx: usize,
     current_image_idx: usize,
     frame_opened: bool,
+    swapchain_outdated: bool,
 }
 
 impl<'a> DockRenderer<'a> {
     pub fn new(
         kew_device: &'a KewDevice,
+        allocator: &'a KewAllocator<'a>,
         surface_loader: &surface::Instance,
         surface: vk::SurfaceKHR,
         window_extent: vk::Extent2D,
         prs_queue_idx: u32,
         gfx_queue_idx: u32,
-    ) -> Self {
-        let cmd_pool = KewCommandPool::new(&kew_device, gfx_queue_idx);
+    ) -> Result<Self, KewError> {
+        let cmd_pool = KewCommandPool::new(&kew_device, gfx_queue_idx)?;
         let cmd_buffers = cmd_pool
-            .allocate_command_buffers::<MAX_IN_FLIGHT_FRAMES>(vk::CommandBufferLevel::PRIMARY);
+            .allocate_command_buffers::<MAX_IN_FLIGHT_FRAMES>(vk::CommandBufferLevel::PRIMARY)?;
 
         let swapchain = KewSwapchain::new(
             &kew_device,
+            allocator,
             &surface_loader,
             surface,
             window_extent,
             prs_queue_idx,
-        );
-        let descriptor_pool = KewDescriptorPoolBuilder::new(MAX_IN_FLIGHT_FRAMES as u32)
-            .add_pool_size(
-                vk::DescriptorType::UNIFORM_BUFFER,
-                MAX_IN_FLIGHT_FRAMES as u32,
-            )
-            .build(kew_device);
-
-        Self {
+            MSAA_SAMPLES,
+        )?;
+        let uniform_buffers = (0..=CAPTURE_SLOT)
+            .map(|_| create_camera_uniform(kew_device, allocator))
+            .collect::<Result<Vec<_>, _>>()?;
+        let descriptor_allocators = (0..=CAPTURE_SLOT)
+            .map(|_| {
+                KewDescriptorAllocator::new(
+                    kew_device,
+                    &[(vk::DescriptorType::UNIFORM_BUFFER, 1)],
+                    CAMERA_POOL_SETS,
+                )
+            })
+            .collect();
+
+        Ok(Self {
             kew_device,
             swapchain,
             cmd_pool,
             cmd_buffers,
-            descriptor_pool,
+            allocator,
+            uniform_buffers,
+            descriptor_allocators,
+            transient_pools: (0..MAX_IN_FLIGHT_FRAMES)
+                .map(|_| KewTransientPool::new(kew_device, allocator))
+                .collect(),
+            window_extent,
             current_frame_idx: 0,
             current_image_idx: 0,
             frame_opened: false,
+            swapchain_outdated: false,
+        })
+    }
+
+    /// Writes `camera` to the uniform of `slot` and allocates a camera set pointing at it. The
+    /// slot must not be in flight.
+    fn camera_set(
+        &mut self,
+        slot: usize,
+        scene: &DockScene,
+        camera: &CameraUniform,
+    ) -> Result<vk::DescriptorSet, KewError> {
+        unsafe {
+            self.uniform_buffers[slot].wr_visible_mem(
+                std::slice::from_ref(camera),
+                size_of::<CameraUniform>() as u64,
+                0,
+            );
+        }
+        self.descriptor_allocators[slot].reset()?;
+        allocate_camera_set(
+            &mut self.descriptor_allocators[slot],
+            scene,
+            &self.uniform_buffers[slot],
+        )
+    }
+
+    pub fn resize(&mut self, window_extent: vk::Extent2D) {
+        if window_extent != self.window_extent {
+            self.window_extent = window_extent;
+            self.swapchain_outdated = true;
         }
     }
 
-    pub fn render_scene(&mut self, scene: &DockScene) {
+    fn is_minimized(&self) -> bool {
+        self.window_extent.width == 0 || self.window_extent.height == 0
+    }
+
+    /// Returns whether a frame was submitted, frames are dropped while minimized or when the
+    /// swapchain is busy.
+    pub fn render_scene(
+        &mut self,
+        scene: &DockScene,
+        camera: &CameraUniform,
+    ) -> Result<bool, KewError> {
+        if self.is_minimized() {
+            return Ok(false);
+        }
         unsafe {
-            if let Ok(cmd_buffer) = self.open_frame() {
-                self.swapchain
-                    .begin_render_pass(cmd_buffer, self.current_image_idx);
-                scene.record_cmd_buffer(cmd_buffer);
-                self.swapchain.end_render_pass(cmd_buffer);
-                self.close_frame(cmd_buffer);
+            if self.swapchain_outdated {
+                self.swapchain.recreate(self.window_extent)?;
+                self.swapchain_outdated = false;
+            }
+            match self.open_frame() {
+                Ok(cmd_buffer) => {
+                    // the frame fence has been waited on, the slot is no longer read
+                    let frame_descriptor_set =
+                        self.camera_set(self.current_frame_idx, scene, camera)?;
+                    self.record_frame_graph(cmd_buffer, scene, frame_descriptor_set)?;
+                    self.close_frame(cmd_buffer).map(|_| true)
+                }
+                Err(DockErr::SOFT) => Ok(false),
+                Err(DockErr::HARD(e)) => Err(e),
             }
         }
     }
 
+    /// Records the frame as a render graph drawing the scene into the swapchain image, see
+    /// `KewSwapchain::add_pass`.
+    unsafe fn record_frame_graph(
+        &mut self,
+        cmd_buffer: vk::CommandBuffer,
+        scene: &DockScene,
+        frame_descriptor_set: vk::DescriptorSet,
+    ) -> Result<(), KewError> {
+        let mut graph = KewRenderGraph::new(self.kew_device);
+        self.swapchain.add_pass(
+            &mut graph,
+            self.current_image_idx,
+            "scene",
+            move |cmd_buffer| scene.record_cmd_buffer(cmd_buffer, frame_descriptor_set),
+        );
+        graph.execute(
+            cmd_buffer,
+            Some(&mut self.transient_pools[self.current_frame_idx]),
+        )
+    }
+
+    /// Renders the scene into an offscreen target the size of the swapchain. With dynamic
+    /// rendering the scene pipeline is reused if the target formats match the swapchain,
+    /// otherwise the scene caches a pipeline for the target.
+    pub fn screenshot(
+        &mut self,
+        scene: &mut DockScene<'a>,
+        vert_shader: &KewShader,
+        frag_shader: &KewShader,
+        camera: &CameraUniform,
+    ) -> Result<RgbaImage, KewError> {
+        if self.is_minimized() {
+            return Err(KewError::UNSUPPORTED("screenshot of a minimized window"));
+        }
+        let mut target = KewRenderTarget::new(
+            self.kew_device,
+            self.allocator,
+            self.swapchain.extent(),
+            &[OFFSCREEN_FORMAT],
+            Some(self.swapchain.depth_format),
+            MSAA_SAMPLES,
+        )?;
+        scene.cache_pipeline(vert_shader, frag_shader, &target.pipeline_target())?;
+        // screenshots wait for their submission, the capture slot is free again
+        let frame_descriptor_set = self.camera_set(CAPTURE_SLOT, scene, camera)?;
+        render_to_image(
+            self.allocator,
+            &self.cmd_pool,
+            &mut target,
+            scene,
+            frame_descriptor_set,
+        )
+    }
+
     unsafe fn open_frame(&mut self) -> Result<vk::CommandBuffer, DockErr> {
-        if self.frame_opened || self.swapchain.frame_in_use(self.current_frame_idx) {
+        if self.frame_opened || self.swapchain.frame_in_use(self.current_frame_idx)? {
             debug!("dropped frame");
             return Err(DockErr::SOFT);
         }
         let image_idx_result = self.swapchain.next_image_idx(self.current_frame_idx);
         match image_idx_result {
-            Err(_) => todo!(),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                debug!("swapchain out of date (recreating swapchain)");
+                self.swapchain.recreate(self.window_extent)?;
+                return Err(DockErr::SOFT);
+            }
+            Err(e) => return Err(KewError::from(e).into()),
             Ok((idx, suboptimal)) => {
                 if suboptimal {
                     warn!("swapchain suboptimal for surface (recreating swapchain)");
-                    // TODO: recreate swapchain
+                    self.swapchain_outdated = true;
                 }
                 self.frame_opened = true;
                 self.current_image_idx = idx as usize;
@@ -216,82 +668,518 @@ impl<'a> DockRenderer<'a> {
         unsafe {
             self.kew_device
                 .begin_command_buffer(cmd_buffer, &vk::CommandBufferBeginInfo::default())
-                .unwrap()
+                .map_err(KewError::from)?
         }
         return Ok(cmd_buffer);
     }
 
-    unsafe fn close_frame(&mut self, cmd_buffer: vk::CommandBuffer) {
-        self.kew_device.end_command_buffer(cmd_buffer).unwrap();
-        self.swapchain.submit_and_present(
+    unsafe fn close_frame(&mut self, cmd_buffer: vk::CommandBuffer) -> Result<(), KewError> {
+        self.frame_opened = false;
+        self.kew_device.end_command_buffer(cmd_buffer)?;
+        match self.swapchain.submit_and_present(
             cmd_buffer,
             self.current_image_idx,
             self.current_frame_idx,
             &self.cmd_pool.queue,
-        );
-        self.frame_opened = false;
+        ) {
+            Ok(false) => (),
+            Ok(true) | Err(KewError::VULKAN(vk::Result::ERROR_OUT_OF_DATE_KHR)) => {
+                self.swapchain_outdated = true
+            }
+            Err(e) => return Err(e),
+        }
         self.current_frame_idx = (self.current_frame_idx + 1) % MAX_IN_FLIGHT_FRAMES;
+        Ok(())
     }
 }
 
+struct DockModel<'a> {
+    vrt_buffer: KewBuffer<'a>,
+    idx_buffer: KewBuffer<'a>,
+    model: KewModel,
+    /// Index into `DockScene::textures`, `DEFAULT_TEXTURE` for untextured models.
+    texture: usize,
+    /// Applied on top of the uploaded vertices, pushed to the vertex shader per draw.
+    transform: Matrix<f32, 4, 4>,
+}
+
+/// Models drawn by the dock, each in its own vertex and index buffer. Removed models leave
+/// an empty slot so the ids of the others stay valid.
 pub struct DockScene<'a> {
-    vrt_buffer: &'a KewBuffer<'a>,
-    idx_buffer: &'a KewBuffer<'a>,
-    model_infos: Vec<KewModel>,
+    kew_device: &'a KewDevice,
+    allocator: &'a KewAllocator<'a>,
+    models: Vec<Option<DockModel<'a>>>,
+    materials: Vec<KewMaterial>,
+    textures: Vec<KewImage<'a>>,
+    texture_binding: TextureBinding<'a>,
+    sampler: KewSampler<'a>,
+    /// Textures are copied on the transfer queue before their mipmaps are generated.
+    transfer: KewTransfer<'a>,
     pipeline: KewGfxPipeline<'a>,
-    //vert_dset_layout: vk::DescriptorSetLayout,
-    //frag_dset_layout: vk::DescriptorSetLayout,
+    /// Set layouts of `pipeline`, the camera sets of frames are allocated from them.
+    set_layouts: KewSetLayouts<'a>,
+    /// What `pipeline` was created for, kept for rebuilds.
+    pipeline_target: PipelineTarget,
+    /// Pipeline of the last screenshot target not matching `pipeline_target`, dropped when the
+    /// shaders are reloaded.
+    capture_pipeline: Option<(PipelineTarget, KewGfxPipeline<'a>, KewSetLayouts<'a>)>,
 }
 
 impl<'a> DockScene<'a> {
-    pub fn dummy(
+    pub fn new(
         kew_device: &'a KewDevice,
-        vrt_buffer: &'a KewBuffer<'a>,
-        idx_buffer: &'a KewBuffer<'a>,
+        allocator: &'a KewAllocator<'a>,
+        cmd_pool: &KewCommandPool,
+        tfr_queue_idx: u32,
         vert_shader: &KewShader,
         frag_shader: &KewShader,
-        render_pass: &vk::RenderPass,
-    ) -> Self {
-        let pipeline = KewGfxPipeline::new(
+        pipeline_target: &PipelineTarget,
+    ) -> Result<Self, KewError> {
+        let texture_binding = TextureBinding::new(kew_device)?;
+        let (pipeline, set_layouts) = match Self::create_pipeline_for(
             kew_device,
-            &PIPELINE_CONFIGS[FLAT_VERT_CONFIG],
-            Self::create_pipeline_layout(kew_device),
             vert_shader,
             frag_shader,
-            render_pass,
-        );
-        Self {
-            vrt_buffer,
-            idx_buffer,
-            model_infos: Vec::new(),
+            texture_binding.set_layout(),
+            pipeline_target,
+        ) {
+            Ok(created) => created,
+            Err(e) => {
+                unsafe { texture_binding.destroy(kew_device) };
+                return Err(e);
+            }
+        };
+        let mut dock_scene = Self {
+            kew_device,
+            allocator,
+            models: Vec::new(),
+            materials: Vec::new(),
+            textures: Vec::new(),
+            texture_binding,
+            sampler: KewSampler::new(kew_device, &TEXTURE_SAMPLER)?,
+            transfer: KewTransfer::new(kew_device, tfr_queue_idx)?,
             pipeline,
+            set_layouts,
+            pipeline_target: pipeline_target.clone(),
+            capture_pipeline: None,
+        };
+        let white = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255; 4])));
+        dock_scene.add_textures(&[(&white, TextureEncoding::SRGB)], cmd_pool)?;
+        Ok(dock_scene)
+    }
+
+    pub unsafe fn record_cmd_buffer(
+        &self,
+        cmd_buffer: vk::CommandBuffer,
+        frame_descriptor_set: vk::DescriptorSet,
+    ) {
+        self.record_with(&self.pipeline, cmd_buffer, frame_descriptor_set);
+    }
+
+    /// Same as `record_cmd_buffer` with a pipeline for another render pass.
+    pub unsafe fn record_with(
+        &self,
+        pipeline: &KewGfxPipeline,
+        cmd_buffer: vk::CommandBuffer,
+        frame_descriptor_set: vk::DescriptorSet,
+    ) {
+        pipeline.bind_pipeline(cmd_buffer);
+        if let TextureBinding::BINDLESS { table, .. } = &self.texture_binding {
+            pipeline.bind_descriptor_sets(cmd_buffer, &[frame_descriptor_set, **table]);
+        }
+        for dock_model in self.models.iter().flatten() {
+            match &self.texture_binding {
+                TextureBinding::SETS { sets, .. } => pipeline.bind_descriptor_sets(
+                    cmd_buffer,
+                    &[frame_descriptor_set, sets[dock_model.texture]],
+                ),
+                TextureBinding::BINDLESS { handles, .. } => pipeline.push_constants(
+                    cmd_buffer,
+                    BINDLESS_TEXTURE_OFFSET,
+                    &[handles[dock_model.texture]],
+                ),
+            }
+            pipeline.push_constants(cmd_buffer, 0, &[dock_model.transform]);
+            dock_model.model.bind(
+                self.kew_device,
+                cmd_buffer,
+                &dock_model.vrt_buffer,
+                &dock_model.idx_buffer,
+            );
+            dock_model.model.draw(self.kew_device, cmd_buffer);
+        }
+    }
+
+    /// Layout of the camera set, compatible with every pipeline created from the current
+    /// shaders.
+    pub fn camera_set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layouts[MESH_VERT_SHADER_CONFIG.set as usize]
+    }
+
+    /// Creates a pipeline drawing the scene models into `pipeline_target`, together with its
+    /// set layouts.
+    pub fn create_pipeline(
+        &self,
+        vert_shader: &KewShader,
+        frag_shader: &KewShader,
+        pipeline_target: &PipelineTarget,
+    ) -> Result<(KewGfxPipeline<'a>, KewSetLayouts<'a>), KewError> {
+        Self::create_pipeline_for(
+            self.kew_device,
+            vert_shader,
+            frag_shader,
+            self.texture_binding.set_layout(),
+            pipeline_target,
+        )
+    }
+
+    /// Recreates the pipeline from reloaded shaders, waiting for in flight frames before the old
+    /// one is dropped. The scene is unchanged on failure.
+    pub fn rebuild_pipeline(
+        &mut self,
+        vert_shader: &KewShader,
+        frag_shader: &KewShader,
+    ) -> Result<(), KewError> {
+        let (pipeline, set_layouts) =
+            self.create_pipeline(vert_shader, frag_shader, &self.pipeline_target)?;
+        unsafe { self.kew_device.device_wait_idle()? };
+        self.pipeline = pipeline;
+        self.set_layouts = set_layouts;
+        self.capture_pipeline = None;
+        Ok(())
+    }
+
+    /// Creates the pipeline for a screenshot target other than the one of the scene. It is
+    /// kept until the target or the shaders change.
+    pub fn cache_pipeline(
+        &mut self,
+        vert_shader: &KewShader,
+        frag_shader: &KewShader,
+        pipeline_target: &PipelineTarget,
+    ) -> Result<(), KewError> {
+        if self.pipeline_for(pipeline_target).is_none() {
+            let (pipeline, set_layouts) =
+                self.create_pipeline(vert_shader, frag_shader, pipeline_target)?;
+            self.capture_pipeline = Some((pipeline_target.clone(), pipeline, set_layouts));
         }
+        Ok(())
     }
 
-    pub unsafe fn record_cmd_buffer(&self, cmd_buffer: vk::CommandBuffer) {
-        self.pipeline.bind_pipeline(cmd_buffer);
-        for model in &self.model_infos {
-            model.bind(self.pipeline.kew_device, cmd_buffer, &self.vrt_buffer, &self.idx_buffer);
-            model.draw(self.pipeline.kew_device, cmd_buffer);
+    /// The scene pipeline or the cached one drawing into `pipeline_target`.
+    pub fn pipeline_for(&self, pipeline_target: &PipelineTarget) -> Option<&KewGfxPipeline<'a>> {
+        match &self.capture_pipeline {
+            _ if *pipeline_target == self.pipeline_target => Some(&self.pipeline),
+            Some((target, pipeline, _)) if target == pipeline_target => Some(pipeline),
+            _ => None,
         }
     }
 
-    pub fn add_model(&mut self, model: KewModel) {
-        self.model_infos.push(model);
+    pub fn add_model(
+        &mut self,
+        model_data: &KewModelVertexData<Vertex>,
+        cmd_pool: &KewCommandPool,
+    ) -> Result<ModelId, KewError> {
+        self.add_textured_model(model_data, DEFAULT_TEXTURE, cmd_pool)
     }
 
-    fn create_pipeline_layout(kew_device: &KewDevice) -> vk::PipelineLayout {
-        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default();
-        unsafe {
-            kew_device
-                .create_pipeline_layout(&pipeline_layout_info, None)
-                .unwrap()
+    /// Replaces the geometry of a model. Waits for in flight frames before freeing the old one.
+    pub fn update_model(
+        &mut self,
+        id: ModelId,
+        model_data: &KewModelVertexData<Vertex>,
+        cmd_pool: &KewCommandPool,
+    ) -> Result<(), KewError> {
+        let (texture, transform) = {
+            let dock_model = self.model_slot(id)?;
+            (dock_model.texture, dock_model.transform)
+        };
+        let mut dock_model = self.create_model(model_data, texture, cmd_pool)?;
+        dock_model.transform = transform;
+        unsafe { self.kew_device.device_wait_idle()? };
+        self.models[id.0] = Some(dock_model);
+        Ok(())
+    }
+
+    /// Places a model in the scene, takes effect with the next recorded frame.
+    pub fn set_transform(
+        &mut self,
+        id: ModelId,
+        transform: Matrix<f32, 4, 4>,
+    ) -> Result<(), KewError> {
+        self.model_slot(id)?;
+        if let Some(dock_model) = &mut self.models[id.0] {
+            dock_model.transform = transform;
+        }
+        Ok(())
+    }
+
+    /// Waits for in flight frames and frees the model buffers.
+    pub fn remove_model(&mut self, id: ModelId) -> Result<(), KewError> {
+        self.model_slot(id)?;
+        unsafe { self.kew_device.device_wait_idle()? };
+        self.models[id.0] = None;
+        Ok(())
+    }
+
+    /// Loads an `.obj`, `.gltf` or `.glb` file and adds one model per primitive. glTF node
+    /// transforms are baked into the uploaded vertices, base color textures are sampled.
+    pub fn load(
+        &mut self,
+        path: &Path,
+        cmd_pool: &KewCommandPool,
+    ) -> Result<Vec<ModelId>, KewError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gltf") | Some("glb") => {
+                let scene = KewScene::from_gltf(path)?;
+                let first_texture = self.textures.len();
+                let images = scene
+                    .images
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, image)| (image, scene.image_encoding(idx)))
+                    .collect::<Vec<_>>();
+                self.add_textures(&images, cmd_pool)?;
+                let ids = scene
+                    .flatten()
+                    .iter()
+                    .map(|primitive| {
+                        let texture = primitive
+                            .material
+                            .and_then(|material| scene.materials[material].base_color_texture)
+                            .map_or(DEFAULT_TEXTURE, |image| first_texture + image);
+                        self.add_textured_model(&primitive.data, texture, cmd_pool)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.materials.extend(scene.materials);
+                Ok(ids)
+            }
+            _ => {
+                let (model_data, _materials) = KewModelVertexData::from_obj(path)?;
+                Ok(vec![self.add_model(&model_data, cmd_pool)?])
+            }
         }
     }
+
+    fn model_slot(&self, id: ModelId) -> Result<&DockModel<'a>, KewError> {
+        match self.models.get(id.0) {
+            Some(Some(dock_model)) => Ok(dock_model),
+            _ => Err(KewError::ASSET(format!("no dock model with id {}", id.0))),
+        }
+    }
+
+    fn add_textured_model(
+        &mut self,
+        model_data: &KewModelVertexData<Vertex>,
+        texture: usize,
+        cmd_pool: &KewCommandPool,
+    ) -> Result<ModelId, KewError> {
+        let dock_model = self.create_model(model_data, texture, cmd_pool)?;
+        self.models.push(Some(dock_model));
+        Ok(ModelId(self.models.len() - 1))
+    }
+
+    /// Uploads `images` and allocates a material set or registers a bindless handle for each.
+    /// On failure the textures uploaded before stay added.
+    fn add_textures(
+        &mut self,
+        images: &[(&DynamicImage, TextureEncoding)],
+        cmd_pool: &KewCommandPool,
+    ) -> Result<(), KewError> {
+        let mut writer = KewDescriptorWriter::new();
+        let result = images.iter().try_for_each(|(image, encoding)| {
+            self.add_texture(&mut writer, image, *encoding, cmd_pool)
+        });
+        // the sets of the textures added before a failure are written as well
+        writer.update(self.kew_device);
+        result
+    }
+
+    fn add_texture(
+        &mut self,
+        writer: &mut KewDescriptorWriter,
+        image: &DynamicImage,
+        encoding: TextureEncoding,
+        cmd_pool: &KewCommandPool,
+    ) -> Result<(), KewError> {
+        let mut texture = upload_texture(
+            self.kew_device,
+            self.allocator,
+            &mut self.transfer,
+            cmd_pool,
+            image,
+            encoding,
+        )?;
+        match &mut self.texture_binding {
+            TextureBinding::SETS {
+                allocator,
+                layout,
+                sets,
+            } => {
+                let image_info = texture.descriptor_info(Some(&self.sampler))?;
+                let texture_set = allocator.allocate(*layout)?;
+                writer.write_image(
+                    texture_set,
+                    0,
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    image_info,
+                );
+                sets.push(texture_set);
+            }
+            TextureBinding::BINDLESS { table, handles } => {
+                handles.push(table.register_image(&mut texture, &self.sampler)?);
+            }
+        }
+        self.textures.push(texture);
+        Ok(())
+    }
+
+    /// Uploads the model geometry on the transfer queue for drawing on the queue of `cmd_pool`.
+    fn create_model(
+        &mut self,
+        model_data: &KewModelVertexData<Vertex>,
+        texture: usize,
+        cmd_pool: &KewCommandPool,
+    ) -> Result<DockModel<'a>, KewError> {
+        if model_data.vertices.is_empty() || model_data.indices.is_empty() {
+            return Err(KewError::ASSET("model without vertices".to_string()));
+        }
+        let vrt_buffer = self.transfer.upload_buffer(
+            self.allocator,
+            cmd_pool,
+            &model_data.vertices,
+            model_data.vertex_data_size(),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+        )?;
+        let idx_buffer = self.transfer.upload_buffer(
+            self.allocator,
+            cmd_pool,
+            &model_data.indices,
+            model_data.index_data_size(),
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDEX_READ,
+        )?;
+        Ok(DockModel {
+            vrt_buffer,
+            idx_buffer,
+            model: KewModel {
+                vertex_offset: 0,
+                index_amount: model_data.indices.len() as u32,
+                index_offset: 0,
+            },
+            texture,
+            transform: Matrix::identity(),
+        })
+    }
+
+    fn create_pipeline_for(
+        kew_device: &'a KewDevice,
+        vert_shader: &KewShader,
+        frag_shader: &KewShader,
+        material_set_layout: vk::DescriptorSetLayout,
+        pipeline_target: &PipelineTarget,
+    ) -> Result<(KewGfxPipeline<'a>, KewSetLayouts<'a>), KewError> {
+        let (pipeline_layout, set_layouts) = Self::create_pipeline_layout(
+            kew_device,
+            vert_shader,
+            frag_shader,
+            material_set_layout,
+        )?;
+        let pipeline = KewGfxPipeline::new(
+            kew_device,
+            &PIPELINE_CONFIGS[MESH_VERT_CONFIG],
+            pipeline_layout,
+            vert_shader,
+            frag_shader,
+            pipeline_target,
+        )?;
+        Ok((pipeline, set_layouts))
+    }
+
+    /// Camera set from the vertex shader, the material set declared by the fragment shader
+    /// with the layout of `TextureBinding` and the push constants of both.
+    fn create_pipeline_layout(
+        kew_device: &'a KewDevice,
+        vert_shader: &KewShader,
+        frag_shader: &KewShader,
+        material_set_layout: vk::DescriptorSetLayout,
+    ) -> Result<(vk::PipelineLayout, KewSetLayouts<'a>), KewError> {
+        let mut set_layouts =
+            KewShader::pipeline_set_layouts(kew_device, &[vert_shader, frag_shader])?;
+        set_layouts[frag_shader.set as usize] = material_set_layout;
+        let push_constant_ranges = [vert_shader, frag_shader]
+            .iter()
+            .filter_map(|shader| shader.push_constant_range)
+            .collect::<Vec<_>>();
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout =
+            unsafe { kew_device.create_pipeline_layout(&pipeline_layout_info, None)? };
+        Ok((pipeline_layout, set_layouts))
+    }
 }
 
 impl Drop for DockScene<'_> {
     fn drop(&mut self) {
-        debug!("HELLO")
+        debug!("dropping DockScene");
+        unsafe { self.texture_binding.destroy(self.kew_device) };
+    }
+}
+
+/// How the fragment shader finds the base color texture of a model.
+enum TextureBinding<'a> {
+    /// A material set per texture, indexed like `DockScene::textures` and bound per draw.
+    /// Material sets outlive reloaded shaders, so they get a layout of their own.
+    SETS {
+        allocator: KewDescriptorAllocator<'a>,
+        layout: vk::DescriptorSetLayout,
+        sets: Vec<vk::DescriptorSet>,
+    },
+    /// A bindless table bound once per frame, the handle of each texture is pushed per draw.
+    BINDLESS {
+        table: KewBindlessTable<'a>,
+        handles: Vec<u32>,
+    },
+}
+
+impl<'a> TextureBinding<'a> {
+    fn new(kew_device: &'a KewDevice) -> Result<Self, KewError> {
+        if kew_device.bindless {
+            return Ok(TextureBinding::BINDLESS {
+                // textures only, the dock shaders read no buffers through the table
+                table: KewBindlessTable::new(kew_device, BINDLESS_TEXTURES, BINDLESS_BUFFERS)?,
+                handles: Vec::new(),
+            });
+        }
+        let bindings = MESH_FRAG_SHADER_CONFIG
+            .build_dset_layout_bindings()
+            .unwrap();
+        let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+        Ok(TextureBinding::SETS {
+            allocator: KewDescriptorAllocator::new(
+                kew_device,
+                &[(vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 1)],
+                MATERIAL_POOL_SETS,
+            ),
+            layout: unsafe { kew_device.create_descriptor_set_layout(&create_info, None)? },
+            sets: Vec::new(),
+        })
+    }
+
+    fn set_layout(&self) -> vk::DescriptorSetLayout {
+        match self {
+            TextureBinding::SETS { layout, .. } => *layout,
+            TextureBinding::BINDLESS { table, .. } => table.layout,
+        }
+    }
+
+    /// Destroys the material set layout, the bindless table destroys its own when dropped.
+    unsafe fn destroy(&self, kew_device: &KewDevice) {
+        if let TextureBinding::SETS { layout, .. } = self {
+            kew_device.destroy_descriptor_set_layout(*layout, None);
+        }
     }
 }
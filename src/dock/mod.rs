@@ -1,35 +1,254 @@
-use std::thread;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 use ash::vk;
-use crossbeam::channel::{Sender, unbounded};
+use crossbeam::channel::{Sender, bounded, unbounded};
+use image::RgbaImage;
+use log::{error, info, warn};
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 use winit::{
     application::ApplicationHandler,
     event::WindowEvent,
-    event_loop::ActiveEventLoop,
+    event_loop::{ActiveEventLoop, EventLoopProxy},
     window::{Window, WindowId},
 };
 use crate::core::context::KewContext;
 use crate::core::device::{KewDevice, KewQueueIndices};
-use crate::dock::dock::init_dock;
+use crate::core::error::KewError;
+use crate::core::model::{KewModelVertexData, Vertex};
+use crate::dock::camera::{CameraInput, CameraPose};
+use crate::dock::config::SCREENSHOT_PATH;
+use crate::dock::dock::{init_dock, render_offscreen};
+use crate::math::matrix::Matrix;
+use crate::math::vector::Vector;
+use std::path::Path;
+use winit::event::{ElementState, KeyEvent, MouseScrollDelta};
+use winit::keyboard::{KeyCode, PhysicalKey};
 
+mod camera;
 mod config;
 mod dock;
 
+/// Pixels per scrolled line for touchpads reporting pixel deltas.
+const SCROLL_LINE_PIXELS: f64 = 40.0;
+
+/// Keys handled by the dock instead of the camera: screenshot, reloading, removing and
+/// scaling the dropped models, and resetting the camera.
+const SHORTCUT_KEYS: [KeyCode; 6] = [
+    KeyCode::F12,
+    KeyCode::F5,
+    KeyCode::Delete,
+    KeyCode::Equal,
+    KeyCode::Minus,
+    KeyCode::Home,
+];
+
+/// Scale factor applied to the dropped models per `=` press, `-` divides by it.
+const SCALE_STEP: f32 = 1.25;
+
+/// Handle of a model added to the dock scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelId(usize);
+
+/// Requests from the application to the dock render thread. Requests producing a result
+/// answer on their reply channel, failures of the others are reported as `DockEvent::ERROR`.
 pub enum DockMessage {
-    TEST
+    RENDER,
+    RESIZE(vk::Extent2D),
+    INPUT(CameraInput),
+    CAMERA(CameraPose),
+    ADD(KewModelVertexData<Vertex>, Sender<Result<ModelId, KewError>>),
+    UPDATE(ModelId, KewModelVertexData<Vertex>),
+    TRANSFORM(ModelId, Matrix<f32, 4, 4>),
+    REMOVE(ModelId),
+    /// Adds every model of an obj or glTF file.
+    LOAD(PathBuf, Sender<Result<Vec<ModelId>, KewError>>),
+    SCREENSHOT(Sender<Result<RgbaImage, KewError>>),
+    /// Stops the render loop and destroys the renderer before the surface.
+    SHUTDOWN,
+}
+
+/// Notifications from the dock render thread, delivered as winit user events.
+#[derive(Debug)]
+pub enum DockEvent {
+    FRAME(FrameStats),
+    ERROR(KewError),
+    /// The render thread has exited and released the window surface.
+    STOPPED,
+}
+
+/// Averages over the frames presented since the previous `DockEvent::FRAME`.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    pub fps: f32,
+    pub frame_time: Duration,
 }
 
 pub enum DockErr {
-    SOFT
+    SOFT,
+    HARD(KewError),
+}
+
+impl From<KewError> for DockErr {
+    fn from(e: KewError) -> Self {
+        DockErr::HARD(e)
+    }
+}
+
+/// Models added from the last dropped file, which the model shortcuts act on.
+struct DroppedModels {
+    path: PathBuf,
+    ids: Vec<ModelId>,
+    scale: f32,
+}
+
+struct DockThread {
+    sender: Sender<DockMessage>,
+    handle: JoinHandle<()>,
 }
 
-#[derive(Default)]
 pub struct Dock {
     window: Option<Window>,
-    vk_thread: Option<Sender<DockMessage>>
+    vk_thread: Option<DockThread>,
+    events: EventLoopProxy<DockEvent>,
+    dropped: Arc<Mutex<Option<DroppedModels>>>,
 }
 
-impl ApplicationHandler for Dock {
+impl Dock {
+    pub fn new(events: EventLoopProxy<DockEvent>) -> Self {
+        Self {
+            window: None,
+            vk_thread: None,
+            events,
+            dropped: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Adds the models of a dropped obj or glTF file to the scene. Obj files are parsed here
+    /// and added as one model, glTF files are loaded by the render thread.
+    fn load(&self, path: PathBuf) {
+        let Some(sender) = self.sender() else {
+            return;
+        };
+        let dropped = self.dropped.clone();
+        // the reply arrives after the upload, keep the event loop responsive meanwhile
+        thread::spawn(move || {
+            let reply = if is_obj(&path) {
+                match KewModelVertexData::from_obj(&path) {
+                    Ok((model_data, _materials)) => {
+                        request(&sender, |tx| DockMessage::ADD(model_data, tx))
+                            .map(|id| id.map(|id| vec![id]))
+                    }
+                    Err(e) => Some(Err(e)),
+                }
+            } else {
+                request(&sender, |tx| DockMessage::LOAD(path.clone(), tx))
+            };
+            match reply {
+                Some(Ok(ids)) => {
+                    info!("loaded {} ({} models)", path.display(), ids.len());
+                    *dropped.lock().unwrap() = Some(DroppedModels {
+                        path,
+                        ids,
+                        scale: 1.0,
+                    });
+                }
+                Some(Err(e)) => error!("failed to load {}: {}", path.display(), e),
+                None => (),
+            }
+        });
+    }
+
+    /// Parses the last dropped obj file again and replaces the geometry of its model.
+    fn reload(&self) {
+        let (path, id) = match self.dropped.lock().unwrap().as_ref() {
+            Some(dropped) if is_obj(&dropped.path) => (dropped.path.clone(), dropped.ids[0]),
+            Some(dropped) => {
+                warn!(
+                    "only obj files can be reloaded, not {} (skipped)",
+                    dropped.path.display()
+                );
+                return;
+            }
+            None => return,
+        };
+        let Some(sender) = self.sender() else {
+            return;
+        };
+        thread::spawn(move || match KewModelVertexData::from_obj(&path) {
+            Ok((model_data, _materials)) => {
+                let _ = sender.send(DockMessage::UPDATE(id, model_data));
+            }
+            Err(e) => error!("failed to reload {}: {}", path.display(), e),
+        });
+    }
+
+    /// Removes the models of the last dropped file from the scene.
+    fn remove(&self) {
+        if let Some(dropped) = self.dropped.lock().unwrap().take() {
+            for id in dropped.ids {
+                self.send(DockMessage::REMOVE(id));
+            }
+        }
+    }
+
+    /// Multiplies the uniform scale of the last dropped models by `factor`.
+    fn scale(&self, factor: f32) {
+        if let Some(dropped) = self.dropped.lock().unwrap().as_mut() {
+            dropped.scale *= factor;
+            let transform = Matrix::scaling(Vector::from([dropped.scale; 3]));
+            for &id in &dropped.ids {
+                self.send(DockMessage::TRANSFORM(id, transform));
+            }
+        }
+    }
+
+    /// Captures the next frame into `SCREENSHOT_PATH`.
+    fn screenshot(&self) {
+        let (tx, rx) = bounded(1);
+        self.send(DockMessage::SCREENSHOT(tx));
+        thread::spawn(move || {
+            let saved = match rx.recv() {
+                Ok(image) => image.and_then(|image| Ok(image.save(SCREENSHOT_PATH)?)),
+                Err(_) => return,
+            };
+            match saved {
+                Ok(()) => info!("saved screenshot to {}", SCREENSHOT_PATH),
+                Err(e) => error!("screenshot failed: {}", e),
+            }
+        });
+    }
+
+    fn shortcut(&self, key: KeyCode) {
+        match key {
+            KeyCode::F12 => self.screenshot(),
+            KeyCode::F5 => self.reload(),
+            KeyCode::Delete => self.remove(),
+            KeyCode::Equal => self.scale(SCALE_STEP),
+            KeyCode::Minus => self.scale(1.0 / SCALE_STEP),
+            KeyCode::Home => self.send(DockMessage::CAMERA(CameraPose::default())),
+            _ => (),
+        }
+    }
+
+    fn sender(&self) -> Option<Sender<DockMessage>> {
+        self.vk_thread
+            .as_ref()
+            .map(|vk_thread| vk_thread.sender.clone())
+    }
+
+    /// Sends `message` to the render thread. A stopped thread has already sent `STOPPED`.
+    pub fn send(&self, message: DockMessage) {
+        if let Some(vk_thread) = &self.vk_thread {
+            if vk_thread.sender.send(message).is_err() {
+                warn!("dock render thread stopped (message dropped)");
+            }
+        }
+    }
+}
+
+impl ApplicationHandler<DockEvent> for Dock {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.vk_thread.is_none() {
             let attributes = Window::default_attributes()
@@ -37,32 +256,16 @@ impl ApplicationHandler for Dock {
                 .with_active(true);
             let window = event_loop.create_window(attributes).unwrap();
 
-            let kew_context = KewContext::new();
-            let (surface_loader, surface) = unsafe {
-                crate::core::surface::create_surface(
-                    &kew_context.entry,
-                    &kew_context.instance,
-                    window.display_handle().unwrap().as_raw(),
-                    window.window_handle().unwrap().as_raw(),
-                )
-            };
-            let window_extent = get_window_extent(&window);
-            let queue_indices = KewQueueIndices::new(&kew_context, &surface_loader, surface);
-            let kew_device = KewDevice::new(kew_context, &queue_indices);
-
-            let (tx, rx) = unbounded();
-            thread::spawn(move || {
-                init_dock(
-                    &kew_device,
-                    &surface_loader,
-                    surface,
-                    &queue_indices,
-                    window_extent,
-                    rx
-                );
-            });
-            self.vk_thread = Some(tx);
-            self.window = Some(window);
+            match spawn_vk_thread(&window, self.events.clone()) {
+                Ok(vk_thread) => {
+                    self.vk_thread = Some(vk_thread);
+                    self.window = Some(window);
+                }
+                Err(e) => {
+                    error!("failed to initialize dock: {}", e);
+                    event_loop.exit();
+                }
+            }
         }
     }
 
@@ -74,14 +277,145 @@ impl ApplicationHandler for Dock {
     ) {
         match event {
             WindowEvent::CloseRequested => event_loop.exit(),
-            WindowEvent::RedrawRequested => {
-                if let Some(sender) = &self.vk_thread {
-                    sender.send(DockMessage::TEST).unwrap();
+            WindowEvent::Resized(size) => self.send(DockMessage::RESIZE(vk::Extent2D {
+                width: size.width,
+                height: size.height,
+            })),
+            WindowEvent::RedrawRequested => self.send(DockMessage::RENDER),
+            WindowEvent::DroppedFile(path) => self.load(path),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(key),
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } if SHORTCUT_KEYS.contains(&key) => {
+                if state.is_pressed() && !repeat {
+                    self.shortcut(key)
                 }
             }
-            _ => (),
+            event => {
+                if let Some(input) = camera_input(event) {
+                    self.send(DockMessage::INPUT(input));
+                }
+            }
+        }
+    }
+
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: DockEvent) {
+        match event {
+            DockEvent::FRAME(stats) => {
+                if let Some(window) = &self.window {
+                    window.set_title(&format!(
+                        "Kew Dock ({:.0} fps, {:.2} ms)",
+                        stats.fps,
+                        stats.frame_time.as_secs_f64() * 1000.0
+                    ));
+                }
+            }
+            DockEvent::ERROR(e) => error!("dock render thread error: {}", e),
+            DockEvent::STOPPED => event_loop.exit(),
         }
     }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(window) = &self.window {
+            window.request_redraw();
+        }
+    }
+
+    /// Joins the render thread so the surface is gone before the window is dropped.
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(vk_thread) = self.vk_thread.take() {
+            let _ = vk_thread.sender.send(DockMessage::SHUTDOWN);
+            if vk_thread.handle.join().is_err() {
+                error!("dock render thread panicked");
+            }
+        }
+    }
+}
+
+/// Sends a request built around a reply channel and waits for the answer, `None` once the
+/// render thread has stopped.
+fn request<T>(
+    sender: &Sender<DockMessage>,
+    message: impl FnOnce(Sender<T>) -> DockMessage,
+) -> Option<T> {
+    let (tx, rx) = bounded(1);
+    sender.send(message(tx)).ok()?;
+    rx.recv().ok()
+}
+
+fn is_obj(path: &Path) -> bool {
+    path.extension().and_then(|ext| ext.to_str()) == Some("obj")
+}
+
+fn camera_input(event: WindowEvent) -> Option<CameraInput> {
+    match event {
+        WindowEvent::KeyboardInput { event, .. } => match event.physical_key {
+            PhysicalKey::Code(code) => Some(CameraInput::KEY(code, event.state.is_pressed())),
+            PhysicalKey::Unidentified(_) => None,
+        },
+        WindowEvent::MouseInput { state, button, .. } => Some(CameraInput::BUTTON(
+            button,
+            state == ElementState::Pressed,
+        )),
+        WindowEvent::CursorMoved { position, .. } => {
+            Some(CameraInput::CURSOR(position.x, position.y))
+        }
+        WindowEvent::MouseWheel { delta, .. } => Some(CameraInput::SCROLL(match delta {
+            MouseScrollDelta::LineDelta(_, lines) => lines,
+            MouseScrollDelta::PixelDelta(position) => (position.y / SCROLL_LINE_PIXELS) as f32,
+        })),
+        _ => None,
+    }
+}
+
+fn spawn_vk_thread(
+    window: &Window,
+    events: EventLoopProxy<DockEvent>,
+) -> Result<DockThread, KewError> {
+    let kew_context = KewContext::new()?;
+    let (surface_loader, surface) = unsafe {
+        crate::core::surface::create_surface(
+            &kew_context.entry,
+            &kew_context.instance,
+            window.display_handle().unwrap().as_raw(),
+            window.window_handle().unwrap().as_raw(),
+        )?
+    };
+    let window_extent = get_window_extent(window);
+    let queue_indices = KewQueueIndices::new(&kew_context, &surface_loader, surface)?;
+    let kew_device = KewDevice::new(kew_context, &queue_indices)?;
+
+    let (tx, rx) = unbounded();
+    let handle = thread::spawn(move || {
+        if let Err(e) = init_dock(
+            &kew_device,
+            &surface_loader,
+            surface,
+            &queue_indices,
+            window_extent,
+            rx,
+            events.clone(),
+        ) {
+            error!("dock render thread failed: {}", e);
+            let _ = events.send_event(DockEvent::ERROR(e));
+        }
+        let _ = events.send_event(DockEvent::STOPPED);
+    });
+    Ok(DockThread { sender: tx, handle })
+}
+
+/// Renders the dock scene without a window (headless context) and writes it to `path`.
+pub fn render_to_file(path: &Path, extent: vk::Extent2D) -> Result<(), KewError> {
+    let kew_context = KewContext::new_headless()?;
+    let queue_indices = KewQueueIndices::new_headless(&kew_context)?;
+    let kew_device = KewDevice::new(kew_context, &queue_indices)?;
+    render_offscreen(&kew_device, &queue_indices, extent, path)
 }
 
 fn get_window_extent(window: &Window) -> vk::Extent2D {
@@ -1,6 +1,11 @@
-use dock::Dock;
+use ash::vk;
+use dock::{Dock, DockEvent};
+use log::error;
+use std::env;
+use std::path::Path;
 use winit::event_loop::{ControlFlow, EventLoop};
 
+mod apps;
 mod core;
 mod dock;
 mod math;
@@ -24,8 +29,48 @@ fn main() {
     //     Ok(i) => println!("program idx not found: {i}"),
     //     Err(..) => println!("failed to parse: {}", input),
     // };
-    let event_loop = EventLoop::new().unwrap();
+    let args = env::args().collect::<Vec<_>>();
+    match args.as_slice() {
+        [_, flag, path] if flag == "--offscreen" => {
+            let extent = vk::Extent2D {
+                width: 800,
+                height: 600,
+            };
+            if let Err(e) = dock::render_to_file(Path::new(path), extent) {
+                error!("offscreen render failed: {}", e);
+            }
+            return;
+        }
+        [_, flag] if flag == "--sqr" => {
+            let data = (0..16).collect::<Vec<i32>>();
+            let result = apps::run_headless(|kew_device, allocator, cmp_cmd_pool| {
+                apps::sqr::sqr_compute(kew_device, allocator, cmp_cmd_pool, &data)
+            });
+            if let Err(e) = result {
+                error!("sqr compute failed: {}", e);
+            }
+            return;
+        }
+        [_, flag, src, dst] if flag == "--img" => {
+            let result = apps::run_headless(|kew_device, allocator, cmp_cmd_pool| {
+                apps::img::img_compute(
+                    kew_device,
+                    allocator,
+                    cmp_cmd_pool,
+                    Path::new(src),
+                    Path::new(dst),
+                )
+            });
+            if let Err(e) = result {
+                error!("img compute failed: {}", e);
+            }
+            return;
+        }
+        _ => (),
+    }
+
+    let event_loop = EventLoop::<DockEvent>::with_user_event().build().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
-    let mut dock = Dock::default();
+    let mut dock = Dock::new(event_loop.create_proxy());
     let _ = event_loop.run_app(&mut dock);
 }
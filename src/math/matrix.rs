@@ -0,0 +1,432 @@
+use super::number::{Float, Number};
+use super::quaternion::Quaternion;
+use super::vector::Vector;
+use std::array;
+use std::ops::{Add, Index, IndexMut, Mul, Sub};
+
+/// Column major `R`x`C` matrix with the memory layout of GLSL matrices. Note that std140
+/// pads the columns of non vec4 matrices, so use `Matrix<f32, 4, 4>` in uniform buffers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Matrix<T, const R: usize, const C: usize> {
+    columns: [Vector<T, R>; C],
+}
+
+impl<T: Copy, const R: usize, const C: usize> Matrix<T, R, C> {
+    pub fn from_columns(columns: [Vector<T, R>; C]) -> Self {
+        Self { columns }
+    }
+
+    pub fn from_rows(rows: [[T; C]; R]) -> Self {
+        Self {
+            columns: array::from_fn(|col| array::from_fn(|row| rows[row][col]).into()),
+        }
+    }
+
+    pub fn column(&self, col: usize) -> Vector<T, R> {
+        self.columns[col]
+    }
+
+    pub fn row(&self, row: usize) -> Vector<T, C> {
+        array::from_fn(|col| self.columns[col][row]).into()
+    }
+
+    pub fn transpose(&self) -> Matrix<T, C, R> {
+        Matrix {
+            columns: array::from_fn(|row| self.row(row)),
+        }
+    }
+}
+
+impl<T: Number, const N: usize> Matrix<T, N, N> {
+    pub fn identity() -> Self {
+        Self {
+            columns: array::from_fn(|col| {
+                array::from_fn(|row| match row == col {
+                    true => T::one(),
+                    false => T::zero(),
+                })
+                .into()
+            }),
+        }
+    }
+}
+
+impl<T: Float, const N: usize> Matrix<T, N, N> {
+    pub fn determinant(&self) -> T {
+        let mut rows: [[T; N]; N] = array::from_fn(|row| self.row(row).into());
+        let mut determinant = T::one();
+        for col in 0..N {
+            let pivot = match Self::pivot_row(&rows, col) {
+                Some(pivot) => pivot,
+                None => return T::zero(),
+            };
+            if pivot != col {
+                rows.swap(pivot, col);
+                determinant = -determinant;
+            }
+            determinant *= rows[col][col];
+            let pivot_row = rows[col];
+            for row in rows.iter_mut().skip(col + 1) {
+                let factor = row[col] / pivot_row[col];
+                for (value, pivot_value) in row.iter_mut().zip(pivot_row).skip(col) {
+                    *value -= factor * pivot_value;
+                }
+            }
+        }
+        determinant
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting, `None` for singular matrices.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut rows: [[T; N]; N] = array::from_fn(|row| self.row(row).into());
+        let mut inverse: [[T; N]; N] = array::from_fn(|row| Self::identity().row(row).into());
+        for col in 0..N {
+            let pivot = Self::pivot_row(&rows, col)?;
+            rows.swap(pivot, col);
+            inverse.swap(pivot, col);
+
+            let scale = rows[col][col];
+            for k in 0..N {
+                rows[col][k] /= scale;
+                inverse[col][k] /= scale;
+            }
+            for row in 0..N {
+                if row == col {
+                    continue;
+                }
+                let factor = rows[row][col];
+                for k in 0..N {
+                    let (value, inverse_value) = (rows[col][k], inverse[col][k]);
+                    rows[row][k] -= factor * value;
+                    inverse[row][k] -= factor * inverse_value;
+                }
+            }
+        }
+        Some(Self::from_rows(inverse))
+    }
+
+    fn pivot_row(rows: &[[T; N]; N], col: usize) -> Option<usize> {
+        let pivot = (col..N).fold(col, |best, row| {
+            match rows[row][col].abs() > rows[best][col].abs() {
+                true => row,
+                false => best,
+            }
+        });
+        (rows[pivot][col].abs() > T::EPSILON).then_some(pivot)
+    }
+}
+
+impl<T: Float> Matrix<T, 4, 4> {
+    pub fn translation(offset: Vector<T, 3>) -> Self {
+        let mut matrix = Self::identity();
+        matrix.columns[3] = offset.extend(T::one());
+        matrix
+    }
+
+    pub fn scaling(scale: Vector<T, 3>) -> Self {
+        let mut matrix = Self::identity();
+        for axis in 0..3 {
+            matrix.columns[axis][axis] = scale[axis];
+        }
+        matrix
+    }
+
+    /// Right handed perspective projection onto Vulkan clip space: `y` points down and depth
+    /// maps `near..far` to `0..1`. `fov_y` is in radians.
+    pub fn perspective(fov_y: T, aspect: T, near: T, far: T) -> Self {
+        let focal = T::one() / (fov_y / T::from_f64(2.0)).tan();
+        let zero = T::zero();
+        Self::from_rows([
+            [focal / aspect, zero, zero, zero],
+            [zero, -focal, zero, zero],
+            [zero, zero, far / (near - far), near * far / (near - far)],
+            [zero, zero, -T::one(), zero],
+        ])
+    }
+
+    /// Right handed orthographic projection onto Vulkan clip space, see `perspective`.
+    pub fn orthographic(left: T, right: T, bottom: T, top: T, near: T, far: T) -> Self {
+        let two = T::from_f64(2.0);
+        let zero = T::zero();
+        Self::from_rows([
+            [
+                two / (right - left),
+                zero,
+                zero,
+                -(right + left) / (right - left),
+            ],
+            [
+                zero,
+                -two / (top - bottom),
+                zero,
+                (top + bottom) / (top - bottom),
+            ],
+            [zero, zero, T::one() / (near - far), near / (near - far)],
+            [zero, zero, zero, T::one()],
+        ])
+    }
+
+    /// Right handed view matrix looking from `eye` at `target`, the camera looks down `-z`.
+    pub fn look_at(eye: Vector<T, 3>, target: Vector<T, 3>, up: Vector<T, 3>) -> Self {
+        let forward = (target - eye).normalize();
+        let side = forward.cross(&up).normalize();
+        let up = side.cross(&forward);
+        let zero = T::zero();
+        Self::from_rows([
+            [side.x(), side.y(), side.z(), -side.dot(&eye)],
+            [up.x(), up.y(), up.z(), -up.dot(&eye)],
+            [-forward.x(), -forward.y(), -forward.z(), forward.dot(&eye)],
+            [zero, zero, zero, T::one()],
+        ])
+    }
+
+    pub fn transform_point(&self, point: Vector<T, 3>) -> Vector<T, 3> {
+        (*self * point.extend(T::one())).xyz()
+    }
+
+    pub fn transform_vector(&self, vector: Vector<T, 3>) -> Vector<T, 3> {
+        (*self * vector.extend(T::zero())).xyz()
+    }
+
+    /// Inverse transpose of the upper 3x3, keeps normals perpendicular to surfaces under
+    /// non-uniform scale. Singular matrices fall back to the upper 3x3 itself.
+    pub fn normal_matrix(&self) -> Matrix<T, 3, 3> {
+        let upper = Matrix::from_columns(array::from_fn(|col| self.columns[col].xyz()));
+        upper.inverse().unwrap_or(upper).transpose()
+    }
+}
+
+impl<T: Float> From<Quaternion<T>> for Matrix<T, 4, 4> {
+    fn from(rotation: Quaternion<T>) -> Self {
+        let Quaternion { x, y, z, w } = rotation.normalize();
+        let (one, two, zero) = (T::one(), T::from_f64(2.0), T::zero());
+        Self::from_rows([
+            [
+                one - two * (y * y + z * z),
+                two * (x * y - w * z),
+                two * (x * z + w * y),
+                zero,
+            ],
+            [
+                two * (x * y + w * z),
+                one - two * (x * x + z * z),
+                two * (y * z - w * x),
+                zero,
+            ],
+            [
+                two * (x * z - w * y),
+                two * (y * z + w * x),
+                one - two * (x * x + y * y),
+                zero,
+            ],
+            [zero, zero, zero, one],
+        ])
+    }
+}
+
+impl<T, const R: usize, const C: usize> From<[[T; R]; C]> for Matrix<T, R, C> {
+    /// Builds the matrix from its columns.
+    fn from(columns: [[T; R]; C]) -> Self {
+        Self {
+            columns: columns.map(Vector::from),
+        }
+    }
+}
+
+impl<T, const R: usize, const C: usize> From<Matrix<T, R, C>> for [[T; R]; C] {
+    fn from(matrix: Matrix<T, R, C>) -> Self {
+        matrix.columns.map(<[T; R]>::from)
+    }
+}
+
+impl<T: Number, const N: usize> Default for Matrix<T, N, N> {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl<T, const R: usize, const C: usize> Index<usize> for Matrix<T, R, C> {
+    type Output = Vector<T, R>;
+
+    fn index(&self, col: usize) -> &Vector<T, R> {
+        &self.columns[col]
+    }
+}
+
+impl<T, const R: usize, const C: usize> IndexMut<usize> for Matrix<T, R, C> {
+    fn index_mut(&mut self, col: usize) -> &mut Vector<T, R> {
+        &mut self.columns[col]
+    }
+}
+
+impl<T: Number, const R: usize, const C: usize, const K: usize> Mul<Matrix<T, C, K>>
+    for Matrix<T, R, C>
+{
+    type Output = Matrix<T, R, K>;
+
+    fn mul(self, rhs: Matrix<T, C, K>) -> Matrix<T, R, K> {
+        Matrix {
+            columns: rhs.columns.map(|column| self * column),
+        }
+    }
+}
+
+impl<T: Number, const R: usize, const C: usize> Mul<Vector<T, C>> for Matrix<T, R, C> {
+    type Output = Vector<T, R>;
+
+    fn mul(self, rhs: Vector<T, C>) -> Vector<T, R> {
+        (0..C).fold(Vector::from([T::zero(); R]), |sum, col| {
+            sum + self.columns[col] * rhs[col]
+        })
+    }
+}
+
+impl<T: Number, const R: usize, const C: usize> Mul<T> for Matrix<T, R, C> {
+    type Output = Self;
+
+    fn mul(self, rhs: T) -> Self {
+        Self {
+            columns: self.columns.map(|column| column * rhs),
+        }
+    }
+}
+
+impl<T: Number, const R: usize, const C: usize> Add for Matrix<T, R, C> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            columns: array::from_fn(|col| self.columns[col] + rhs.columns[col]),
+        }
+    }
+}
+
+impl<T: Number, const R: usize, const C: usize> Sub for Matrix<T, R, C> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            columns: array::from_fn(|col| self.columns[col] - rhs.columns[col]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::FRAC_PI_2;
+
+    const TOLERANCE: f32 = 1e-5;
+
+    fn assert_close<const R: usize, const C: usize>(
+        actual: Matrix<f32, R, C>,
+        expected: Matrix<f32, R, C>,
+    ) {
+        for col in 0..C {
+            for row in 0..R {
+                assert!(
+                    (actual[col][row] - expected[col][row]).abs() < TOLERANCE,
+                    "{:?} != {:?}",
+                    actual,
+                    expected
+                );
+            }
+        }
+    }
+
+    /// Projects `point` and divides by `w`.
+    fn project(matrix: Matrix<f32, 4, 4>, point: [f32; 3]) -> [f32; 3] {
+        let clip = matrix * Vector::from(point).extend(1.0);
+        [clip[0] / clip[3], clip[1] / clip[3], clip[2] / clip[3]]
+    }
+
+    fn assert_point(actual: [f32; 3], expected: [f32; 3]) {
+        for axis in 0..3 {
+            assert!(
+                (actual[axis] - expected[axis]).abs() < TOLERANCE,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn inverts_affine_transforms() {
+        let transform = Matrix::translation(Vector::new([1.0, -2.0, 3.0]))
+            * Matrix::from(Quaternion::from_axis_angle(
+                Vector::new([0.0, 1.0, 0.0]),
+                0.7,
+            ))
+            * Matrix::scaling(Vector::new([2.0, 0.5, 4.0]));
+        let inverse = transform.inverse().unwrap();
+        assert_close(transform * inverse, Matrix::identity());
+        assert_close(inverse * transform, Matrix::identity());
+    }
+
+    #[test]
+    fn inverts_with_pivoting() {
+        // zero on the diagonal, elimination without row swaps divides by zero
+        let matrix = Matrix::from_rows([[0.0, 1.0, 0.0], [2.0, 0.0, 0.0], [0.0, 0.0, 4.0]]);
+        let inverse = matrix.inverse().unwrap();
+        assert_close(
+            inverse,
+            Matrix::from_rows([[0.0, 0.5, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 0.25]]),
+        );
+        assert!((matrix.determinant() + 8.0).abs() < TOLERANCE);
+    }
+
+    #[test]
+    fn rejects_singular_matrices() {
+        let singular = Matrix::from_rows([[1.0, 2.0], [2.0, 4.0]]);
+        assert!(singular.inverse().is_none());
+        assert_eq!(singular.determinant(), 0.0);
+        assert!(Matrix::<f32, 4, 4>::scaling(Vector::new([1.0, 0.0, 1.0]))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn perspective_maps_to_vulkan_clip_space() {
+        let projection = Matrix::perspective(FRAC_PI_2, 2.0, 0.1, 100.0);
+        assert_point(project(projection, [0.0, 0.0, -0.1]), [0.0, 0.0, 0.0]);
+        assert_point(project(projection, [0.0, 0.0, -100.0]), [0.0, 0.0, 1.0]);
+        // 90 degree field of view: the top edge at distance 1 is one unit up, y points down
+        let depth = 100.0 * (1.0 - 0.1) / (1.0 * (100.0 - 0.1));
+        assert_point(project(projection, [2.0, 1.0, -1.0]), [1.0, -1.0, depth]);
+    }
+
+    #[test]
+    fn orthographic_maps_box_to_clip_space() {
+        let projection = Matrix::orthographic(-2.0, 2.0, -1.0, 1.0, 0.5, 10.5);
+        assert_point(project(projection, [-2.0, 1.0, -0.5]), [-1.0, -1.0, 0.0]);
+        assert_point(project(projection, [2.0, -1.0, -10.5]), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn look_at_moves_the_eye_to_the_origin() {
+        let view = Matrix::look_at(
+            Vector::new([0.0, 0.0, 5.0]),
+            Vector::new([0.0, 0.0, 0.0]),
+            Vector::new([0.0, 1.0, 0.0]),
+        );
+        assert_point(
+            view.transform_point(Vector::new([0.0, 0.0, 5.0])).into(),
+            [0.0; 3],
+        );
+        assert_point(
+            view.transform_point(Vector::new([1.0, 1.0, 0.0])).into(),
+            [1.0, 1.0, -5.0],
+        );
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let transform = Matrix::<f32, 4, 4>::scaling(Vector::new([4.0, 1.0, 1.0]));
+        // a plane through (1, 0, 0) and (0, 1, 0) with normal (1, 1, 0)
+        let tangent = transform.transform_vector(Vector::new([1.0, -1.0, 0.0]));
+        let normal = transform.normal_matrix() * Vector::new([1.0, 1.0, 0.0]);
+        assert!(tangent.dot(&normal).abs() < TOLERANCE);
+    }
+}
@@ -1,2 +1,4 @@
+pub mod matrix;
 pub mod number;
+pub mod quaternion;
 pub mod vector;
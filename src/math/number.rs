@@ -1,9 +1,110 @@
+use std::fmt::Debug;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
 pub trait Zero {
     fn zero() -> Self;
 }
 
-impl Zero for usize {
-    fn zero() -> Self {
-        0
-    }
+pub trait One {
+    fn one() -> Self;
+}
+
+/// Primitive number that vectors and matrices can be built from.
+pub trait Number:
+    Copy
+    + Debug
+    + PartialOrd
+    + Zero
+    + One
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + DivAssign
+{
+}
+
+pub trait Signed: Number + Neg<Output = Self> {
+    fn abs(self) -> Self;
+}
+
+pub trait Float: Signed {
+    const EPSILON: Self;
+    const PI: Self;
+
+    fn from_f64(value: f64) -> Self;
+    fn sqrt(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tan(self) -> Self;
+    fn acos(self) -> Self;
+}
+
+macro_rules! impl_number {
+    ($zero:literal, $one:literal, $($t:ty),*) => {$(
+        impl Zero for $t {
+            fn zero() -> Self {
+                $zero
+            }
+        }
+
+        impl One for $t {
+            fn one() -> Self {
+                $one
+            }
+        }
+
+        impl Number for $t {}
+    )*};
 }
+
+macro_rules! impl_signed {
+    ($($t:ty),*) => {$(
+        impl Signed for $t {
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_float {
+    ($($t:ident),*) => {$(
+        impl Float for $t {
+            const EPSILON: Self = $t::EPSILON;
+            const PI: Self = std::$t::consts::PI;
+
+            fn from_f64(value: f64) -> Self {
+                value as $t
+            }
+
+            fn sqrt(self) -> Self {
+                $t::sqrt(self)
+            }
+
+            fn sin(self) -> Self {
+                $t::sin(self)
+            }
+
+            fn cos(self) -> Self {
+                $t::cos(self)
+            }
+
+            fn tan(self) -> Self {
+                $t::tan(self)
+            }
+
+            fn acos(self) -> Self {
+                $t::acos(self)
+            }
+        }
+    )*};
+}
+
+impl_number!(0, 1, u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+impl_number!(0.0, 1.0, f32, f64);
+impl_signed!(i8, i16, i32, i64, isize, f32, f64);
+impl_float!(f32, f64);
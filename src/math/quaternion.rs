@@ -0,0 +1,133 @@
+use super::number::{Float, Number};
+use super::vector::Vector;
+use std::ops::Mul;
+
+/// Rotation quaternion stored as `x, y, z, w` like glTF and GLSL `vec4`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Quaternion<T> {
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
+}
+
+impl<T: Number> Quaternion<T> {
+    pub fn new(x: T, y: T, z: T, w: T) -> Self {
+        Self { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(T::zero(), T::zero(), T::zero(), T::one())
+    }
+
+    pub fn dot(&self, rhs: &Self) -> T {
+        self.x * rhs.x + self.y * rhs.y + self.z * rhs.z + self.w * rhs.w
+    }
+
+    pub fn vector(&self) -> Vector<T, 3> {
+        Vector::new([self.x, self.y, self.z])
+    }
+}
+
+impl<T: Float> Quaternion<T> {
+    /// Rotation of `angle` radians around `axis` (right handed).
+    pub fn from_axis_angle(axis: Vector<T, 3>, angle: T) -> Self {
+        let half = angle / T::from_f64(2.0);
+        let axis = axis.normalize() * half.sin();
+        Self::new(axis.x(), axis.y(), axis.z(), half.cos())
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn length(&self) -> T {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let length = self.length();
+        match length > T::EPSILON {
+            true => Self::new(
+                self.x / length,
+                self.y / length,
+                self.z / length,
+                self.w / length,
+            ),
+            false => Self::identity(),
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        let length_squared = self.dot(self);
+        let conjugate = self.conjugate();
+        Self::new(
+            conjugate.x / length_squared,
+            conjugate.y / length_squared,
+            conjugate.z / length_squared,
+            conjugate.w / length_squared,
+        )
+    }
+
+    /// Rotates `vector`, assuming a unit quaternion.
+    pub fn rotate(&self, vector: Vector<T, 3>) -> Vector<T, 3> {
+        let axis = self.vector();
+        let t = axis.cross(&vector) * T::from_f64(2.0);
+        vector + t * self.w + axis.cross(&t)
+    }
+
+    /// Spherical interpolation along the shortest arc.
+    pub fn slerp(&self, rhs: &Self, t: T) -> Self {
+        let (mut rhs, mut cos) = (*rhs, self.dot(rhs));
+        if cos < T::zero() {
+            rhs = Self::new(-rhs.x, -rhs.y, -rhs.z, -rhs.w);
+            cos = -cos;
+        }
+        let (a, b) = match cos > T::one() - T::from_f64(1e-4) {
+            // nearly parallel, fall back to a normalized lerp
+            true => (T::one() - t, t),
+            false => {
+                let angle = cos.acos();
+                let sin = angle.sin();
+                (
+                    ((T::one() - t) * angle).sin() / sin,
+                    (t * angle).sin() / sin,
+                )
+            }
+        };
+        Self::new(
+            self.x * a + rhs.x * b,
+            self.y * a + rhs.y * b,
+            self.z * a + rhs.z * b,
+            self.w * a + rhs.w * b,
+        )
+        .normalize()
+    }
+}
+
+impl<T: Number> Default for Quaternion<T> {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl<T: Number> Mul for Quaternion<T> {
+    type Output = Self;
+
+    /// Hamilton product, `self * rhs` applies `rhs` first.
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+            self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+        )
+    }
+}
+
+impl<T> From<[T; 4]> for Quaternion<T> {
+    fn from([x, y, z, w]: [T; 4]) -> Self {
+        Self { x, y, z, w }
+    }
+}
@@ -1,16 +1,156 @@
-use super::number::Zero;
+use super::number::{Float, Number, Signed, Zero};
+use std::array;
+use std::ops::{
+    Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
+};
 
-#[derive(Clone, Copy)]
+/// Fixed size vector with the memory layout of `[T; U]`, usable in vertex and uniform buffers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
 pub struct Vector<T, const U: usize> {
     data: [T; U],
 }
 
+impl<T, const U: usize> Vector<T, U> {
+    pub const fn new(data: [T; U]) -> Self {
+        Self { data }
+    }
+
+    pub fn as_array(&self) -> &[T; U] {
+        &self.data
+    }
+}
+
+impl<T: Copy, const U: usize> Vector<T, U> {
+    /// Picks components by index, e.g. `v.swizzle([2, 1, 0])` for `zyx`.
+    pub fn swizzle<const N: usize>(&self, indices: [usize; N]) -> Vector<T, N> {
+        Vector {
+            data: indices.map(|idx| self.data[idx]),
+        }
+    }
+}
+
+impl<T: Number, const U: usize> Vector<T, U> {
+    pub fn dot(&self, rhs: &Self) -> T {
+        self.data
+            .iter()
+            .zip(rhs.data.iter())
+            .fold(T::zero(), |sum, (a, b)| sum + *a * *b)
+    }
+
+    pub fn length_squared(&self) -> T {
+        self.dot(self)
+    }
+
+    /// Component wise product.
+    pub fn hadamard(&self, rhs: &Self) -> Self {
+        Self {
+            data: array::from_fn(|idx| self.data[idx] * rhs.data[idx]),
+        }
+    }
+}
+
+impl<T: Float, const U: usize> Vector<T, U> {
+    pub fn length(&self) -> T {
+        self.length_squared().sqrt()
+    }
+
+    /// Unit vector in the same direction. The zero vector is returned as is.
+    pub fn normalize(&self) -> Self {
+        let length = self.length();
+        match length > T::EPSILON {
+            true => *self / length,
+            false => *self,
+        }
+    }
+
+    pub fn lerp(&self, rhs: &Self, t: T) -> Self {
+        *self + (*rhs - *self) * t
+    }
+}
+
+impl<T: Copy> Vector<T, 2> {
+    pub fn x(&self) -> T {
+        self.data[0]
+    }
+
+    pub fn y(&self) -> T {
+        self.data[1]
+    }
+
+    pub fn extend(&self, z: T) -> Vector<T, 3> {
+        Vector::new([self.data[0], self.data[1], z])
+    }
+}
+
+impl<T: Copy> Vector<T, 3> {
+    pub fn x(&self) -> T {
+        self.data[0]
+    }
+
+    pub fn y(&self) -> T {
+        self.data[1]
+    }
+
+    pub fn z(&self) -> T {
+        self.data[2]
+    }
+
+    pub fn xy(&self) -> Vector<T, 2> {
+        self.swizzle([0, 1])
+    }
+
+    pub fn extend(&self, w: T) -> Vector<T, 4> {
+        Vector::new([self.data[0], self.data[1], self.data[2], w])
+    }
+}
+
+impl<T: Number> Vector<T, 3> {
+    pub fn cross(&self, rhs: &Self) -> Self {
+        let [ax, ay, az] = self.data;
+        let [bx, by, bz] = rhs.data;
+        Self::new([ay * bz - az * by, az * bx - ax * bz, ax * by - ay * bx])
+    }
+}
+
+impl<T: Copy> Vector<T, 4> {
+    pub fn x(&self) -> T {
+        self.data[0]
+    }
+
+    pub fn y(&self) -> T {
+        self.data[1]
+    }
+
+    pub fn z(&self) -> T {
+        self.data[2]
+    }
+
+    pub fn w(&self) -> T {
+        self.data[3]
+    }
+
+    pub fn xy(&self) -> Vector<T, 2> {
+        self.swizzle([0, 1])
+    }
+
+    pub fn xyz(&self) -> Vector<T, 3> {
+        self.swizzle([0, 1, 2])
+    }
+}
+
 impl<T, const U: usize> From<[T; U]> for Vector<T, U> {
     fn from(data: [T; U]) -> Self {
         Self { data }
     }
 }
 
+impl<T, const U: usize> From<Vector<T, U>> for [T; U] {
+    fn from(vector: Vector<T, U>) -> Self {
+        vector.data
+    }
+}
+
 impl<T, const U: usize> Default for Vector<T, U>
 where
     T: Zero + Copy,
@@ -21,3 +161,91 @@ where
         }
     }
 }
+
+impl<T, const U: usize> Index<usize> for Vector<T, U> {
+    type Output = T;
+
+    fn index(&self, idx: usize) -> &T {
+        &self.data[idx]
+    }
+}
+
+impl<T, const U: usize> IndexMut<usize> for Vector<T, U> {
+    fn index_mut(&mut self, idx: usize) -> &mut T {
+        &mut self.data[idx]
+    }
+}
+
+impl<T: Number, const U: usize> Add for Vector<T, U> {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            data: array::from_fn(|idx| self.data[idx] + rhs.data[idx]),
+        }
+    }
+}
+
+impl<T: Number, const U: usize> Sub for Vector<T, U> {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self {
+            data: array::from_fn(|idx| self.data[idx] - rhs.data[idx]),
+        }
+    }
+}
+
+impl<T: Number, const U: usize> Mul<T> for Vector<T, U> {
+    type Output = Self;
+
+    fn mul(self, rhs: T) -> Self {
+        Self {
+            data: self.data.map(|value| value * rhs),
+        }
+    }
+}
+
+impl<T: Number, const U: usize> Div<T> for Vector<T, U> {
+    type Output = Self;
+
+    fn div(self, rhs: T) -> Self {
+        Self {
+            data: self.data.map(|value| value / rhs),
+        }
+    }
+}
+
+impl<T: Signed, const U: usize> Neg for Vector<T, U> {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            data: self.data.map(|value| -value),
+        }
+    }
+}
+
+impl<T: Number, const U: usize> AddAssign for Vector<T, U> {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl<T: Number, const U: usize> SubAssign for Vector<T, U> {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl<T: Number, const U: usize> MulAssign<T> for Vector<T, U> {
+    fn mul_assign(&mut self, rhs: T) {
+        *self = *self * rhs;
+    }
+}
+
+impl<T: Number, const U: usize> DivAssign<T> for Vector<T, U> {
+    fn div_assign(&mut self, rhs: T) {
+        *self = *self / rhs;
+    }
+}